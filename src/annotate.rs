@@ -0,0 +1,388 @@
+use std::{fs, iter, time::Instant};
+
+use crate::{
+    gamestate::GameState,
+    history::History,
+    moves::Move,
+    pgn::{parse_pgn, write_pgn},
+    san::to_san,
+    simulation::{
+        analyse_with_limits, is_mate_score, score_repr, Progress, SearchLimits, SearchOptions,
+        MATE_SCORE,
+    },
+};
+
+// Used when no limit is given
+pub const DEFAULT_ANNOTATE_DEPTH: u8 = 6;
+
+// Centipawns a move gives up to be marked. Scores are capped first, so missing
+// a mate in a won position is no worse than dropping a rook
+const INACCURACY: i32 = 50;
+const MISTAKE: i32 = 100;
+const BLUNDER: i32 = 300;
+const SCORE_CAP: i32 = 1000;
+
+#[derive(Copy, Clone)]
+enum Mark {
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+impl Mark {
+    fn from_loss(loss: i32) -> Option<Self> {
+        match loss {
+            BLUNDER.. => Some(Mark::Blunder),
+            MISTAKE.. => Some(Mark::Mistake),
+            INACCURACY.. => Some(Mark::Inaccuracy),
+            _ => None,
+        }
+    }
+
+    // Numeric annotation glyphs for ?!, ? and ??, as export format writes them
+    fn nag(&self) -> &'static str {
+        match self {
+            Mark::Inaccuracy => "$6",
+            Mark::Mistake => "$2",
+            Mark::Blunder => "$4",
+        }
+    }
+
+    fn symbol(&self) -> &'static str {
+        match self {
+            Mark::Inaccuracy => "?!",
+            Mark::Mistake => "?",
+            Mark::Blunder => "??",
+        }
+    }
+}
+
+// Search of a position for the side to move. Depth 0 when the game is over
+struct Evaluation {
+    score: i32,
+    depth: u8,
+    pv: Vec<Move>,
+}
+
+fn evaluate_position(
+    game_state: &GameState,
+    limits: &SearchLimits,
+    options: &SearchOptions,
+) -> Evaluation {
+    let score = match game_state.game_result() {
+        Some(_) if game_state.in_check(false) && game_state.get_legal_moves(false).is_empty() => {
+            -MATE_SCORE
+        }
+        Some(_) => 0,
+        None => {
+            let analysis = analyse_with_limits(game_state, limits, options);
+            return Evaluation {
+                score: analysis.score,
+                depth: analysis.depth,
+                pv: analysis.pv,
+            };
+        }
+    };
+    Evaluation {
+        score,
+        depth: 0,
+        pv: Vec::new(),
+    }
+}
+
+// Centipawns a move gives up, from the best score for the player making it and
+// the score for the opponent after it. The engine's own choice loses nothing,
+// whatever the deeper search after it found
+fn move_loss(best: i32, after: i32, engine_choice: bool) -> i32 {
+    if engine_choice {
+        return 0;
+    }
+    (best.clamp(-SCORE_CAP, SCORE_CAP) + after.clamp(-SCORE_CAP, SCORE_CAP)).max(0)
+}
+
+// Chance of winning from 0 to 100 for a score, the curve Lichess uses
+fn win_percent(score: i32) -> f64 {
+    50. + 50. * (2. / (1. + (-0.00368208 * score as f64).exp()) - 1.)
+}
+
+// 100 for a move keeping the winning chances, falling quickly as they drop
+fn move_accuracy(before: i32, after: i32) -> f64 {
+    let drop = (win_percent(before) - win_percent(after)).max(0.);
+    (103.1668 * (-0.04354 * drop).exp() - 3.1669).clamp(0., 100.)
+}
+
+// Pawns and depth from white's side, as engines write them in PGN comments
+fn eval_comment(score: i32, depth: u8, black_to_move: bool) -> String {
+    let score = if black_to_move { -score } else { score };
+    if is_mate_score(score) {
+        format!("{{{}/{}}}", score_repr(score), depth)
+    } else {
+        format!("{{{:+.2}/{}}}", score as f64 / 100., depth)
+    }
+}
+
+#[derive(Default)]
+struct PlayerStats {
+    moves: u32,
+    loss: i32,
+    accuracy: f64,
+    // Inaccuracies, mistakes and blunders
+    marks: [u32; 3],
+}
+
+impl PlayerStats {
+    fn add(&mut self, loss: i32, accuracy: f64, mark: Option<Mark>) {
+        self.moves += 1;
+        self.loss += loss;
+        self.accuracy += accuracy;
+        if let Some(mark) = mark {
+            self.marks[mark as usize] += 1;
+        }
+    }
+
+    fn repr(&self, name: &str) -> String {
+        let moves = self.moves.max(1);
+        format!(
+            "{}: accuracy {:.1}%, average loss {} cp, inaccuracies {}, mistakes {}, blunders {}",
+            name,
+            self.accuracy / moves as f64,
+            self.loss / moves as i32,
+            self.marks[0],
+            self.marks[1],
+            self.marks[2]
+        )
+    }
+}
+
+// The engine's line from a position in SAN with move numbers, the first move
+// at the given index in the game and followed by the comment
+fn variation(
+    history: &History,
+    game_state: &GameState,
+    index: usize,
+    pv: &[Move],
+    comment: String,
+) -> String {
+    let mut game_state = game_state.clone();
+    let mut tokens = Vec::new();
+    for (i, mov) in pv.iter().enumerate() {
+        let number = history.move_number(index + i);
+        if i == 0 || !number.ends_with("...") {
+            tokens.push(number);
+        }
+        tokens.push(to_san(&game_state, mov));
+        if i == 0 {
+            tokens.push(comment.clone());
+        }
+        game_state = game_state.perform_move(mov);
+    }
+    format!("({})", tokens.join(" "))
+}
+
+// Searches every position of the first game in the PGN, marking each move by
+// the score it gives up against the engine's choice, and writes the game back
+// with the engine's lines, score comments and each player's accuracy
+pub fn annotate(path: &str, out: &str, limits: &SearchLimits, options: &SearchOptions) {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => {
+            println!("Couldn't read {}: {}", path, e);
+            return;
+        }
+    };
+    let Some(game) = parse_pgn(&text).into_iter().next() else {
+        println!("No game found in {}", path);
+        return;
+    };
+    let history = match History::from_game(&game) {
+        Ok((history, stopped)) => {
+            if let Some(san) = stopped {
+                println!("Stopped at illegal move {}", san);
+            }
+            history
+        }
+        Err(e) => {
+            println!("Couldn't replay {}: {}", path, e);
+            return;
+        }
+    };
+    let mut options = *options;
+    options.progress = Progress::Quiet;
+
+    let timer = Instant::now();
+    let positions: Vec<GameState> = iter::once(history.start().clone())
+        .chain(history.entries().iter().map(|e| e.game_state.clone()))
+        .collect();
+    let mut evaluations = vec![evaluate_position(&positions[0], limits, &options)];
+
+    let mut stats = [PlayerStats::default(), PlayerStats::default()];
+    let mut tokens = Vec::new();
+    let mut after_variation = false;
+    for (i, entry) in history.entries().iter().enumerate() {
+        let before = &positions[i];
+        let black = before.engine_black;
+        evaluations.push(evaluate_position(&positions[i + 1], limits, &options));
+        let best = evaluations[i].score;
+        let best_line = &evaluations[i].pv;
+        let capped = best.clamp(-SCORE_CAP, SCORE_CAP);
+        let loss = move_loss(
+            best,
+            evaluations[i + 1].score,
+            best_line.first() == Some(&entry.mov),
+        );
+        let mark = Mark::from_loss(loss);
+        stats[black as usize].add(loss, move_accuracy(capped, capped - loss), mark);
+
+        let number = history.move_number(i);
+        if i == 0 || !black || after_variation {
+            tokens.push(number.clone());
+        }
+        tokens.push(entry.san.clone());
+        after_variation = false;
+        if let Some(mark) = mark {
+            tokens.push(String::from(mark.nag()));
+        }
+        let after = &evaluations[i + 1];
+        if after.depth > 0 {
+            tokens.push(eval_comment(
+                after.score,
+                after.depth,
+                positions[i + 1].engine_black,
+            ));
+        }
+
+        let mut line = format!("{:<7} {:<7}", number, entry.san);
+        if let (Some(mark), Some(best_move)) = (mark, best_line.first()) {
+            let comment = eval_comment(best, evaluations[i].depth, black);
+            tokens.push(variation(&history, before, i, best_line, comment));
+            after_variation = true;
+            line += &format!(
+                " {:<2} lost {}, best {}",
+                mark.symbol(),
+                loss,
+                to_san(before, best_move)
+            );
+        }
+        println!("{}", line.trim_end());
+    }
+
+    let white = game.tag("White").unwrap_or("White");
+    let black = game.tag("Black").unwrap_or("Black");
+    let summary = [stats[0].repr(white), stats[1].repr(black)];
+    for line in &summary {
+        println!("{}", line);
+    }
+    println!(
+        "Searched {} positions in {:.1}s",
+        positions.len(),
+        timer.elapsed().as_secs_f64()
+    );
+
+    let mut tags: Vec<(&str, String)> = game
+        .tags
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.clone()))
+        .collect();
+    if game.tag("Annotator").is_none() {
+        tags.push(("Annotator", String::from("rust-chess-engine")));
+    }
+    let movetext = format!("{{{}}} {}", summary.join(". "), tokens.join(" "));
+    let result = game.result().unwrap_or("*");
+    match fs::write(out, write_pgn(&tags, &movetext, result)) {
+        Ok(()) => println!("Wrote {}", out),
+        Err(e) => println!("Couldn't write {}: {}", out, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    fn mark(loss: i32) -> Option<&'static str> {
+        Mark::from_loss(loss).map(|mark| mark.symbol())
+    }
+
+    #[test]
+    fn marks() {
+        assert_eq!(mark(0), None);
+        assert_eq!(mark(49), None);
+        assert_eq!(mark(50), Some("?!"));
+        assert_eq!(mark(99), Some("?!"));
+        assert_eq!(mark(100), Some("?"));
+        assert_eq!(mark(299), Some("?"));
+        assert_eq!(mark(300), Some("??"));
+        assert_eq!(Mark::from_loss(300).unwrap().nag(), "$4");
+    }
+
+    #[test]
+    fn losses() {
+        assert_eq!(move_loss(40, -40, false), 0);
+        assert_eq!(move_loss(40, 20, false), 60);
+        assert_eq!(move_loss(40, 500, true), 0);
+        // Better than expected is no loss
+        assert_eq!(move_loss(40, -100, false), 0);
+        // Still more than 10 pawns up, even missing a mate
+        assert_eq!(move_loss(MATE_SCORE - 3, -1500, false), 0);
+        assert_eq!(move_loss(MATE_SCORE - 3, -400, false), 600);
+        assert_eq!(move_loss(0, MATE_SCORE - 1, false), SCORE_CAP);
+    }
+
+    #[test]
+    fn summary() {
+        assert!((move_accuracy(50, 50) - 100.).abs() < 1e-3);
+        assert!(move_accuracy(50, -250) < move_accuracy(50, -50));
+        assert!(move_accuracy(SCORE_CAP, -SCORE_CAP) < 1.);
+
+        let mut stats = PlayerStats::default();
+        stats.add(0, 100., None);
+        stats.add(60, 80., Mark::from_loss(60));
+        stats.add(330, 30., Mark::from_loss(330));
+        assert_eq!(
+            stats.repr("White"),
+            "White: accuracy 70.0%, average loss 130 cp, inaccuracies 1, mistakes 0, blunders 1"
+        );
+        assert_eq!(
+            PlayerStats::default().repr("Black"),
+            "Black: accuracy 0.0%, average loss 0 cp, inaccuracies 0, mistakes 0, blunders 0"
+        );
+    }
+
+    #[test]
+    fn variation_after_a_marked_move() {
+        let dir = env::temp_dir();
+        let path = dir.join(format!("annotate-in-{}.pgn", process::id()));
+        let out = dir.join(format!("annotate-out-{}.pgn", process::id()));
+        fs::write(
+            &path,
+            "[White \"A\"]\n[Black \"B\"]\n\n1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7# 1-0\n",
+        )
+        .unwrap();
+        let options = SearchOptions {
+            threads: 1,
+            ..SearchOptions::default()
+        };
+        annotate(
+            path.to_str().unwrap(),
+            out.to_str().unwrap(),
+            &SearchLimits::depth(3),
+            &options,
+        );
+        let text = fs::read_to_string(&out).unwrap();
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&out).unwrap();
+
+        let game = parse_pgn(&text).into_iter().next().unwrap();
+        assert_eq!(game.tag("Annotator"), Some("rust-chess-engine"));
+        assert_eq!(game.moves.len(), 7);
+        assert_eq!(game.result(), Some("1-0"));
+        let movetext = text.split("\n\n").nth(1).unwrap().replace('\n', " ");
+        let blunder = movetext.find("Nf6 $4").unwrap();
+        let rest = movetext[blunder..].trim_start_matches("Nf6 $4");
+        // Score after the move, then the engine's line in its place
+        let variation = rest.find("(3... ").unwrap();
+        assert!(rest[..variation].trim().starts_with('{'));
+        assert!(rest[variation..].find(") 4. Qxf7#").is_some());
+    }
+}
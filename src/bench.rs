@@ -0,0 +1,123 @@
+use timeit::timeit_loops;
+
+use crate::{
+    endgame::generate_bitbases,
+    fen::parse_fen,
+    simulation::{analyse, Progress, SearchOptions},
+    transposition::table,
+};
+
+// Openings, middlegames and endgames, so changes to any part of the search or
+// move generation show up in the node counts
+const BENCH_POSITIONS: [&str; 10] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - 0 1",
+    "r1bq1rk1/pp2ppbp/2np1np1/8/3NP3/2N1BP2/PPPQ2PP/R3KB1R b KQ - 0 9",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "8/8/3k4/8/3KP3/8/8/8 w - - 0 1",
+    "6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1",
+    "8/5pk1/6p1/8/3B4/8/5PPP/6K1 b - - 0 1",
+];
+
+// FNV-1a, folding in each position's result
+const SIGNATURE_START: u64 = 0xcbf29ce484222325;
+const SIGNATURE_PRIME: u64 = 0x100000001b3;
+
+struct BenchResult {
+    moves: u64,
+    ms: u128,
+    signature: u64,
+}
+
+fn sign(signature: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(signature, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(SIGNATURE_PRIME)
+    })
+}
+
+fn run(depth: u8, options: &SearchOptions) -> BenchResult {
+    let mut result = BenchResult {
+        moves: 0,
+        ms: 0,
+        signature: SIGNATURE_START,
+    };
+    for (i, fen) in BENCH_POSITIONS.iter().enumerate() {
+        let game_state = parse_fen(fen).unwrap();
+        table().clear();
+        let mut analysis = None;
+        let seconds = timeit_loops!(1, {
+            analysis = Some(analyse(&game_state, depth, options));
+        });
+        let analysis = analysis.unwrap();
+        let ms = (seconds * 1000.0) as u128;
+        let best_move = analysis
+            .best_moves
+            .first()
+            .map_or(String::from("none"), |mov| {
+                mov.coordinate(game_state.engine_black)
+            });
+        println!(
+            "  Position {:>2}: {:>9} moves in {:>6} ms, best {} ({})",
+            i + 1,
+            analysis.sim_moves,
+            ms,
+            best_move,
+            analysis.score
+        );
+        result.moves += analysis.sim_moves;
+        result.ms += ms;
+        result.signature = sign(result.signature, &analysis.sim_moves.to_le_bytes());
+        result.signature = sign(result.signature, &analysis.score.to_le_bytes());
+        result.signature = sign(result.signature, best_move.as_bytes());
+    }
+    result
+}
+
+fn moves_per_second(moves: u64, ms: u128) -> u64 {
+    (moves as u128 * 1000 / u128::max(ms, 1)) as u64
+}
+
+// Searches a fixed set of positions to a fixed depth with one thread. This is
+// deterministic, so the node count and signature only change when the search
+// or move generation does. With more threads configured the search is run
+// again to show how it scales, which varies from run to run
+pub fn bench(depth: u8, options: &SearchOptions) {
+    let mut options = *options;
+    options.progress = Progress::Quiet;
+    let threads = options.threads;
+    // Kept out of the timings
+    generate_bitbases();
+
+    options.threads = 1;
+    println!("1 thread, depth {}", depth);
+    let single = run(depth, &options);
+    let nps = moves_per_second(single.moves, single.ms);
+    println!(
+        "  Total: {} moves in {} ms, {} moves/sec",
+        single.moves, single.ms, nps
+    );
+
+    if threads > 1 {
+        options.threads = threads;
+        println!("{} threads, depth {}", threads, depth);
+        let parallel = run(depth, &options);
+        let parallel_nps = moves_per_second(parallel.moves, parallel.ms);
+        println!(
+            "  Total: {} moves in {} ms, {} moves/sec",
+            parallel.moves, parallel.ms, parallel_nps
+        );
+        println!(
+            "  Speedup over 1 thread: {:.2}x time to depth, {:.2}x moves/sec",
+            single.ms as f64 / u128::max(parallel.ms, 1) as f64,
+            parallel_nps as f64 / u64::max(nps, 1) as f64
+        );
+    }
+
+    println!("Nodes: {}", single.moves);
+    println!("Time: {} ms", single.ms);
+    println!("NPS: {}", nps);
+    println!("Signature: {:016x}", single.signature);
+}
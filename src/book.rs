@@ -0,0 +1,226 @@
+use std::{collections::HashMap, fs, sync::OnceLock};
+
+use rand::Rng;
+
+use crate::{
+    gamestate::GameState,
+    moves::Move,
+    pgn::{parse_pgn, PgnGame},
+    polyglot::{encode_move, polyglot_key, BookEntry, ENTRY_SIZE},
+    san::parse_san,
+};
+
+pub struct Book {
+    // Sorted by key
+    entries: Vec<BookEntry>,
+    // Always play the highest weighted move instead of choosing by weight
+    pub best_only: bool,
+}
+
+impl Book {
+    pub fn load(path: &str) -> Result<Book, String> {
+        let bytes = fs::read(path).map_err(|e| format!("Couldn't read {}: {}", path, e))?;
+        if bytes.len() % ENTRY_SIZE != 0 {
+            return Err(format!("{} is not a Polyglot book", path));
+        }
+        let mut entries: Vec<BookEntry> = bytes
+            .chunks(ENTRY_SIZE)
+            .map(BookEntry::from_bytes)
+            .collect();
+        entries.sort_by_key(|entry| entry.key);
+        Ok(Book {
+            entries,
+            best_only: false,
+        })
+    }
+
+    pub fn entry_count(&self) -> usize {
+        self.entries.len()
+    }
+
+    // Legal book moves for the engine with their weights
+    pub fn probe(&self, game_state: &GameState) -> Vec<(Move, u16)> {
+        let key = polyglot_key(game_state);
+        let start = self.entries.partition_point(|entry| entry.key < key);
+        let legal_moves = game_state.get_legal_moves(false);
+        self.entries[start..]
+            .iter()
+            .take_while(|entry| entry.key == key)
+            .filter_map(|entry| {
+                legal_moves
+                    .iter()
+                    .find(|mov| encode_move(mov, game_state.engine_black) == entry.mov)
+                    .map(|mov| (*mov, entry.weight))
+            })
+            .collect()
+    }
+
+    pub fn choose(&self, game_state: &GameState, rng: &mut impl Rng) -> Option<Move> {
+        let moves = self.probe(game_state);
+        if self.best_only {
+            return moves
+                .iter()
+                .max_by_key(|(_, weight)| weight)
+                .map(|(mov, _)| *mov);
+        }
+        let total: u32 = moves.iter().map(|(_, weight)| *weight as u32).sum();
+        if total == 0 {
+            return moves.first().map(|(mov, _)| *mov);
+        }
+        let mut pick = rng.gen_range(0..total);
+        for (mov, weight) in moves {
+            if pick < weight as u32 {
+                return Some(mov);
+            }
+            pick -= weight as u32;
+        }
+        None
+    }
+}
+
+static BOOK: OnceLock<Book> = OnceLock::new();
+
+// Only has an effect before the first book move
+pub fn set_book(book: Book) {
+    let _ = BOOK.set(book);
+}
+
+// None when there is no book or the position isn't in it
+pub fn book_move(game_state: &GameState, rng: &mut impl Rng) -> Option<Move> {
+    BOOK.get()?.choose(game_state, rng)
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum BookWeight {
+    // Two points for each win by the side playing the move, one for a draw
+    Results,
+    // Number of games the move was played in
+    Games,
+}
+
+pub struct BuildOptions {
+    pub max_ply: usize,
+    // Moves played in fewer games are left out
+    pub min_games: u32,
+    pub weight: BookWeight,
+    // Sides whose moves go in the book
+    pub white: bool,
+    pub black: bool,
+}
+
+impl Default for BuildOptions {
+    fn default() -> Self {
+        Self {
+            max_ply: 30,
+            min_games: 3,
+            weight: BookWeight::Results,
+            white: true,
+            black: true,
+        }
+    }
+}
+
+#[derive(Default)]
+struct MoveStats {
+    games: u32,
+    points: u32,
+}
+
+// Book keys and moves for the game, or the first move that couldn't be played
+fn replay(game: &PgnGame, max_ply: usize) -> Result<Vec<(u64, u16, bool)>, String> {
+    let mut game_state = game.start_position()?;
+    let mut played = Vec::new();
+    for (ply, text) in game.moves.iter().enumerate() {
+        let mov = parse_san(text, &game_state, false)
+            .ok_or_else(|| format!("illegal move {} at ply {}", text, ply + 1))?;
+        if ply < max_ply {
+            played.push((
+                polyglot_key(&game_state),
+                encode_move(&mov, game_state.engine_black),
+                !game_state.engine_black,
+            ));
+        }
+        game_state = game_state.perform_move(&mov).flipped();
+    }
+    Ok(played)
+}
+
+// Writes a Polyglot book of the moves played in the given PGN files
+pub fn build_book(pgn_paths: &[&str], out_path: &str, options: &BuildOptions) {
+    let mut stats: HashMap<(u64, u16), MoveStats> = HashMap::new();
+    let (mut accepted, mut rejected) = (0, 0);
+    for path in pgn_paths {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) => {
+                println!("Couldn't read {}: {}", path, e);
+                continue;
+            }
+        };
+        for (i, game) in parse_pgn(&text).iter().enumerate() {
+            let played = match replay(game, options.max_ply) {
+                Ok(played) => played,
+                Err(e) => {
+                    println!("{} game {}: {}, skipped", path, i + 1, e);
+                    rejected += 1;
+                    continue;
+                }
+            };
+            accepted += 1;
+            let white_points = match game.result() {
+                Some("1-0") => 2,
+                Some("1/2-1/2") => 1,
+                _ => 0,
+            };
+            let black_points = match game.result() {
+                Some("0-1") => 2,
+                Some("1/2-1/2") => 1,
+                _ => 0,
+            };
+            for (key, mov, white) in played {
+                if (white && !options.white) || (!white && !options.black) {
+                    continue;
+                }
+                let entry = stats.entry((key, mov)).or_default();
+                entry.games += 1;
+                entry.points += if white { white_points } else { black_points };
+            }
+        }
+    }
+
+    let weight = |stats: &MoveStats| match options.weight {
+        BookWeight::Results => stats.points,
+        BookWeight::Games => stats.games,
+    };
+    let kept: Vec<((u64, u16), u32)> = stats
+        .iter()
+        .filter(|(_, stats)| stats.games >= options.min_games && weight(stats) > 0)
+        .map(|(key, stats)| (*key, weight(stats)))
+        .collect();
+    // Scaled down together if any weight won't fit in 16 bits
+    let largest = kept.iter().map(|(_, weight)| *weight).max().unwrap_or(0);
+    let scale = f64::min(u16::MAX as f64 / largest.max(1) as f64, 1.);
+
+    let mut entries: Vec<BookEntry> = kept
+        .into_iter()
+        .map(|((key, mov), weight)| BookEntry {
+            key,
+            mov,
+            weight: u16::max((weight as f64 * scale) as u16, 1),
+            learn: 0,
+        })
+        .collect();
+    entries.sort_by(|a, b| a.key.cmp(&b.key).then(b.weight.cmp(&a.weight)));
+
+    let bytes: Vec<u8> = entries.iter().flat_map(|entry| entry.to_bytes()).collect();
+    match fs::write(out_path, bytes) {
+        Ok(()) => println!(
+            "Read {} games ({} rejected), wrote {} entries to {}",
+            accepted,
+            rejected,
+            entries.len(),
+            out_path
+        ),
+        Err(e) => println!("Couldn't write {}: {}", out_path, e),
+    }
+}
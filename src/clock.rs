@@ -0,0 +1,394 @@
+use std::time::{Duration, Instant};
+
+use crate::utils::parse_duration;
+
+// Moves assumed to remain when the time control doesn't say
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+
+// Kept back on every move for reading and printing it
+const MOVE_OVERHEAD: Duration = Duration::from_millis(50);
+
+// Time given back for each move made
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Increment {
+    None,
+    // Added after every move
+    Fischer(Duration),
+    // Up to this much of the time a move took is given back
+    Bronstein(Duration),
+}
+
+#[derive(Copy, Clone)]
+pub struct TimeControl {
+    pub base: Duration,
+    // Moves per period, the base is added again after each. None for the
+    // whole game
+    pub moves: Option<u32>,
+    pub increment: Increment,
+}
+
+impl TimeControl {
+    // "90m" for the whole game, "40/90m" for 40 moves in 90 minutes,
+    // "5m+3s" with an increment, or any of these followed by "delay 3s"
+    pub fn parse(text: &str) -> Option<Self> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let (main, delay) = match words[..] {
+            [main] => (main, None),
+            [main, "delay", delay] => (main, Some(parse_duration(delay)?)),
+            _ => return None,
+        };
+        let (moves, rest) = match main.split_once('/') {
+            Some((moves, rest)) => (Some(moves.parse().ok().filter(|&n| n > 0)?), rest),
+            None => (None, main),
+        };
+        let (base, fischer) = match rest.split_once('+') {
+            Some((base, increment)) => (base, Some(parse_duration(increment)?)),
+            None => (rest, None),
+        };
+        let base = parse_duration(base).filter(|base| !base.is_zero())?;
+        let increment = match (fischer, delay) {
+            (Some(_), Some(_)) => return None,
+            (Some(increment), None) => Increment::Fischer(increment),
+            (None, Some(delay)) => Increment::Bronstein(delay),
+            (None, None) => Increment::None,
+        };
+        Some(Self {
+            base,
+            moves,
+            increment,
+        })
+    }
+
+    // As in the PGN TimeControl tag, which has no form for a delay
+    pub fn pgn_tag(&self) -> Option<String> {
+        let mut tag = match self.moves {
+            Some(moves) => format!("{}/{}", moves, self.base.as_secs()),
+            None => self.base.as_secs().to_string(),
+        };
+        match self.increment {
+            Increment::Bronstein(_) => return None,
+            Increment::Fischer(increment) => tag += &format!("+{}", increment.as_secs()),
+            Increment::None => (),
+        }
+        Some(tag)
+    }
+
+    pub fn repr(&self) -> String {
+        let mut out = match self.moves {
+            Some(moves) => format!("{} moves in {}", moves, clock_repr(self.base)),
+            None => clock_repr(self.base),
+        };
+        match self.increment {
+            Increment::Fischer(increment) => out += &format!(" + {:.1}s", increment.as_secs_f64()),
+            Increment::Bronstein(delay) => out += &format!(", {:.1}s delay", delay.as_secs_f64()),
+            Increment::None => (),
+        }
+        out
+    }
+}
+
+// Minutes and seconds, with tenths once under ten seconds
+pub fn clock_repr(time: Duration) -> String {
+    let secs = time.as_secs();
+    if secs < 10 {
+        format!("0:{:02}.{}", secs, time.subsec_millis() / 100)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+// Soft and hard limits for a search given the time left. The soft limit is an
+// even share of what remains plus most of the increment, the hard one allows
+// running over that when an iteration needs it but never uses up the clock
+pub fn allocate(
+    remaining: Duration,
+    moves_to_go: Option<u32>,
+    increment: Increment,
+) -> (Duration, Duration) {
+    let available = remaining.saturating_sub(MOVE_OVERHEAD);
+    let moves_to_go = moves_to_go.unwrap_or(DEFAULT_MOVES_TO_GO).max(1);
+    let bonus = match increment {
+        Increment::None => Duration::ZERO,
+        Increment::Fischer(increment) => increment * 3 / 4,
+        Increment::Bronstein(delay) => delay,
+    };
+    let hard = available * 4 / 5;
+    let soft = (available / moves_to_go + bonus).min(hard);
+    (soft, (soft * 4).min(hard))
+}
+
+// Both players' clocks, white first. Only one runs at a time
+pub struct Clock {
+    pub control: TimeControl,
+    remaining: [Duration; 2],
+    // Moves made by each side, for when the base is added again
+    moves: [u32; 2],
+    // Side whose clock is running, since when, and the time its move has
+    // taken before that
+    running: Option<(bool, Instant, Duration)>,
+    // Side whose flag fell
+    flagged: Option<bool>,
+}
+
+impl Clock {
+    pub fn new(control: TimeControl) -> Self {
+        Self {
+            control,
+            remaining: [control.base; 2],
+            moves: [0; 2],
+            running: None,
+            flagged: None,
+        }
+    }
+
+    // Time the running side has used since it was started
+    fn charge(&mut self) -> Option<(bool, Duration)> {
+        let (black, since, before) = self.running.take()?;
+        let elapsed = since.elapsed();
+        let remaining = &mut self.remaining[black as usize];
+        if elapsed >= *remaining {
+            *remaining = Duration::ZERO;
+            self.flagged.get_or_insert(black);
+        } else {
+            *remaining -= elapsed;
+        }
+        Some((black, before + elapsed))
+    }
+
+    // Runs the given side's clock, as when a game starts or moves are taken
+    // back. Time the other side used so far is still charged
+    pub fn start(&mut self, black: bool) {
+        let taken = match self.charge() {
+            Some((running, taken)) if running == black => taken,
+            _ => Duration::ZERO,
+        };
+        self.running = Some((black, Instant::now(), taken));
+    }
+
+    pub fn stop(&mut self) {
+        self.charge();
+    }
+
+    // Ends the running side's move and starts the other side's clock.
+    // Returns false if its flag fell first
+    pub fn press(&mut self) -> bool {
+        let Some((black, taken)) = self.charge() else {
+            return true;
+        };
+        if self.flagged == Some(black) {
+            return false;
+        }
+        let side = black as usize;
+        self.remaining[side] += match self.control.increment {
+            Increment::None => Duration::ZERO,
+            Increment::Fischer(increment) => increment,
+            Increment::Bronstein(delay) => taken.min(delay),
+        };
+        self.moves[side] += 1;
+        if let Some(moves) = self.control.moves {
+            if self.moves[side].is_multiple_of(moves) {
+                self.remaining[side] += self.control.base;
+            }
+        }
+        self.running = Some((!black, Instant::now(), Duration::ZERO));
+        true
+    }
+
+    pub fn running(&self) -> bool {
+        self.running.is_some()
+    }
+
+    pub fn remaining(&self, black: bool) -> Duration {
+        let remaining = self.remaining[black as usize];
+        match self.running {
+            Some((running, since, _)) if running == black => {
+                remaining.saturating_sub(since.elapsed())
+            }
+            _ => remaining,
+        }
+    }
+
+    // Side out of time, including the running side if it is now
+    pub fn flagged(&self) -> Option<bool> {
+        self.flagged.or_else(|| {
+            let (black, _, _) = self.running?;
+            self.remaining(black).is_zero().then_some(black)
+        })
+    }
+
+    // Moves until the base is added again
+    pub fn moves_to_go(&self, black: bool) -> Option<u32> {
+        let moves = self.control.moves?;
+        Some(moves - self.moves[black as usize] % moves)
+    }
+
+    // Soft and hard limits for the given side's search
+    pub fn search_times(&self, black: bool) -> (Duration, Duration) {
+        allocate(
+            self.remaining(black),
+            self.moves_to_go(black),
+            self.control.increment,
+        )
+    }
+
+    pub fn repr(&self) -> String {
+        format!(
+            "White {}  Black {}",
+            clock_repr(self.remaining(false)),
+            clock_repr(self.remaining(true))
+        )
+    }
+}
+
+// Result once a side's flag has fallen
+pub fn time_result(black: bool) -> &'static str {
+    if black {
+        "1-0 {Black loses on time}"
+    } else {
+        "0-1 {White loses on time}"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    fn secs(secs: f64) -> Duration {
+        Duration::from_secs_f64(secs)
+    }
+
+    #[test]
+    fn parse_time_controls() {
+        let control = TimeControl::parse("90m").unwrap();
+        assert_eq!(control.base, secs(5400.0));
+        assert_eq!(control.moves, None);
+        assert!(control.increment == Increment::None);
+
+        let control = TimeControl::parse("40/90m").unwrap();
+        assert_eq!(control.moves, Some(40));
+        assert_eq!(control.pgn_tag().as_deref(), Some("40/5400"));
+
+        let control = TimeControl::parse("40/90m+30s").unwrap();
+        assert_eq!(control.moves, Some(40));
+        assert!(control.increment == Increment::Fischer(secs(30.0)));
+        assert_eq!(control.pgn_tag().as_deref(), Some("40/5400+30"));
+
+        let control = TimeControl::parse("5m+3s").unwrap();
+        assert!(control.increment == Increment::Fischer(secs(3.0)));
+        assert_eq!(control.pgn_tag().as_deref(), Some("300+3"));
+        assert_eq!(control.repr(), "5:00 + 3.0s");
+
+        let control = TimeControl::parse("15m delay 5s").unwrap();
+        assert!(control.increment == Increment::Bronstein(secs(5.0)));
+        assert_eq!(control.pgn_tag(), None);
+        assert_eq!(control.repr(), "15:00, 5.0s delay");
+
+        assert_eq!(
+            TimeControl::parse("90").unwrap().pgn_tag().as_deref(),
+            Some("90")
+        );
+    }
+
+    #[test]
+    fn invalid_time_controls() {
+        for text in [
+            "",
+            "0m",
+            "0/90m",
+            "x/90m",
+            "5m+",
+            "5m+3s delay 2s",
+            "15m delay",
+            "15m wait 5s",
+            "5h",
+        ] {
+            assert!(TimeControl::parse(text).is_none(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn clock_text() {
+        assert_eq!(clock_repr(secs(5400.0)), "90:00");
+        assert_eq!(clock_repr(secs(65.0)), "1:05");
+        assert_eq!(clock_repr(secs(9.87)), "0:09.8");
+        assert_eq!(clock_repr(Duration::ZERO), "0:00.0");
+    }
+
+    #[test]
+    fn allocation() {
+        // 30 seconds once the move overhead is taken off
+        let remaining = secs(30.05);
+        assert_eq!(
+            allocate(remaining, None, Increment::None),
+            (secs(1.0), secs(4.0))
+        );
+        assert_eq!(
+            allocate(remaining, Some(10), Increment::None),
+            (secs(3.0), secs(12.0))
+        );
+        assert_eq!(
+            allocate(remaining, None, Increment::Fischer(secs(2.0))),
+            (secs(2.5), secs(10.0))
+        );
+        assert_eq!(
+            allocate(remaining, Some(0), Increment::Bronstein(secs(1.0))),
+            (secs(24.0), secs(24.0))
+        );
+        // Never more than most of what is left
+        assert_eq!(
+            allocate(secs(1.05), Some(1), Increment::Fischer(secs(10.0))),
+            (secs(0.8), secs(0.8))
+        );
+        assert_eq!(
+            allocate(secs(0.01), None, Increment::None),
+            (Duration::ZERO, Duration::ZERO)
+        );
+    }
+
+    #[test]
+    fn increments_and_periods() {
+        let mut clock = Clock::new(TimeControl {
+            base: secs(60.0),
+            moves: Some(2),
+            increment: Increment::Fischer(secs(2.0)),
+        });
+        assert!(clock.press());
+        assert!(!clock.running());
+        clock.start(false);
+        assert!(clock.press());
+        assert!(clock.remaining(false) > secs(61.0));
+        assert!(clock.remaining(false) <= secs(62.0));
+        assert_eq!(clock.moves_to_go(false), Some(1));
+        assert_eq!(clock.moves_to_go(true), Some(2));
+        assert!(clock.press());
+        clock.start(false);
+        assert!(clock.press());
+        // Second move of the period adds the base again
+        assert!(clock.remaining(false) > secs(123.0));
+        assert_eq!(clock.moves_to_go(false), Some(2));
+    }
+
+    #[test]
+    fn delay_and_flag() {
+        let mut clock = Clock::new(TimeControl::parse("1m delay 5s").unwrap());
+        clock.start(false);
+        thread::sleep(Duration::from_millis(20));
+        assert!(clock.press());
+        // The whole move was given back
+        assert_eq!(clock.remaining(false), secs(60.0));
+
+        let mut clock = Clock::new(TimeControl {
+            base: Duration::from_millis(1),
+            moves: None,
+            increment: Increment::None,
+        });
+        clock.start(true);
+        thread::sleep(Duration::from_millis(5));
+        assert_eq!(clock.flagged(), Some(true));
+        assert!(!clock.press());
+        assert_eq!(clock.remaining(true), Duration::ZERO);
+        assert_eq!(time_result(true), "1-0 {Black loses on time}");
+    }
+}
@@ -1,21 +1,55 @@
 use rand::Rng;
 
 use crate::{
+    evaluation::params,
     gamestate::GameState,
     moves::{Move, MoveType},
-    pieces::PieceClass,
-    settings::{BOARD_WIDTH, RANDOM_FACTOR},
-    simulation::analyse,
+    pieces::{Piece, PieceClass},
+    see::see,
+    settings::RANDOM_FACTOR,
     utils::Vect,
 };
 
-fn get_move_type_data(mov: &Move) -> Option<(Vect, Vect, bool)> {
+pub const DEVELOPMENT_PARAM_COUNT: usize = 7;
+
+// Tuned along with the evaluation weights and kept in the same file
+pub const DEVELOPMENT_PARAM_NAMES: [&str; DEVELOPMENT_PARAM_COUNT] = [
+    "dev_trap",
+    "dev_trap_ahead",
+    "dev_pawn",
+    "dev_safety",
+    "dev_position",
+    "dev_trap_margin",
+    "dev_pawn_margin",
+];
+
+// Indices into the development weights
+pub const TRAP: usize = 0;
+pub const TRAP_AHEAD: usize = 1;
+pub const PAWN: usize = 2;
+pub const SAFETY: usize = 3;
+pub const POSITION: usize = 4;
+// Pawns ahead before setting traps, and before pawn moves stop being preferred
+pub const TRAP_MARGIN: usize = 5;
+pub const PAWN_MARGIN: usize = 6;
+
+// Weights are in 120ths, so the hand picked 1/60, 1/40, 1/3, 1/3 and 1/6 are
+// exact
+pub const DEVELOPMENT_SCALE: i32 = 120;
+pub const DEFAULT_DEVELOPMENT_WEIGHTS: [i32; DEVELOPMENT_PARAM_COUNT] = [3, 2, 40, 40, 20, 12, 25];
+
+// Traps score how far the opponent's moves fall short of this
+const MAX_TRAP: i32 = 20;
+// Position scores above this count the same
+const MAX_POSITION: f32 = 4.;
+
+fn get_move_type_data(mov: &Move) -> Option<(Vect, Vect)> {
     Some(match mov.move_type {
-        MoveType::Standard(from, to, subtract) => (from, to, subtract),
-        MoveType::DoubleAdvance(from, to) => (from, to, false),
-        MoveType::EnPassant(from, to, _) => (from, to, true),
+        MoveType::Standard(from, to) => (from, to),
+        MoveType::DoubleAdvance(from, to) => (from, to),
+        MoveType::EnPassant(from, to, _) => (from, to),
         MoveType::Castling(_) => return None,
-        MoveType::Promotion(from, to, _, subtract) => (from, to, subtract),
+        MoveType::Promotion(from, to, _) => (from, to),
         MoveType::Null => panic!("Engine provided move should not be null"),
     })
 }
@@ -25,7 +59,7 @@ fn get_pawn_score(game_state: &GameState, mov: &Move) -> f32 {
         return 0.;
     }
     let from = match get_move_type_data(mov) {
-        Some((f, _, _)) => f,
+        Some((f, _)) => f,
         None => return 0.,
     };
     let piece = game_state.board[from.y as usize][from.x as usize].unwrap();
@@ -35,36 +69,31 @@ fn get_pawn_score(game_state: &GameState, mov: &Move) -> f32 {
     }
 }
 
-fn get_defended_score(
-    defended_matrix: &[[f32; BOARD_WIDTH as usize]; BOARD_WIDTH as usize],
-    mov: &Move,
-) -> f32 {
-    // Prefer moves to squares protected by multiple pieces
-    let (dest, subtract) = match get_move_type_data(mov) {
-        Some((_, to, sub)) => (to, sub),
-        None => return 0.,
-    };
-
-    let mut score = defended_matrix[dest.y as usize][dest.x as usize];
-    if subtract {
-        score -= 1.;
+fn get_safety_score(game_state: &GameState, mov: &Move) -> f32 {
+    // Prefer moves which don't lose material on the destination square
+    if get_move_type_data(mov).is_none() {
+        return 0.;
+    }
+    if see(game_state, mov) >= 0 {
+        1.
+    } else {
+        0.
     }
-    f32::max(score, 0.)
 }
 
 fn get_position_score(game_state: &GameState, mov: &Move) -> f32 {
     let (from, to) = match get_move_type_data(mov) {
-        Some((from, to, _)) => (from, to),
+        Some((from, to)) => (from, to),
         None => return 0.,
     };
 
     let square = game_state.board[from.y as usize][from.x as usize];
-    match square {
-        Some(piece) => match piece.class {
-            PieceClass::King => return if from.y != 0 && to.y == 0 { 1. } else { 0. },
-            _ => (),
-        },
-        None => (),
+    if let Some(Piece {
+        class: PieceClass::King,
+        ..
+    }) = square
+    {
+        return if from.y != 0 && to.y == 0 { 1. } else { 0. };
     }
 
     let mut score = 0.;
@@ -83,53 +112,90 @@ fn get_position_score(game_state: &GameState, mov: &Move) -> f32 {
     score
 }
 
-fn get_double_move_score(new_state: &GameState) -> f32 {
-    let result = analyse(new_state, 1, false);
-    let score_delta = result.end_score - new_state.score;
-    i16::max(0, i16::min(5, score_delta)) as f32;
-    0.
+fn get_opponent_trap_score(new_state: &GameState) -> f32 {
+    let count = new_state.get_possible_moves(true).len() as i32;
+    i32::max(0, MAX_TRAP - count) as f32
 }
 
-fn get_opponent_trap_score(new_state: &GameState) -> f32 {
-    let count = new_state.get_possible_moves(true).len() as i16;
-    i16::max(0, 20 - count) as f32
+// Weighted components, before any randomness is added
+#[derive(Default)]
+pub struct Development {
+    pub trap: f32,
+    pub pawn: f32,
+    pub safety: f32,
+    pub position: f32,
+}
+
+impl Development {
+    pub fn total(&self) -> f32 {
+        self.trap + self.pawn + self.safety + self.position
+    }
 }
 
-pub fn find_best_development(game_state: &GameState, moves: Vec<Move>) -> Move {
-    let mut rng = rand::thread_rng();
+// Components of a move's development before weighting, with the material
+// balance that decides which of them count
+#[derive(Copy, Clone)]
+pub struct DevelopmentTerms {
+    pub material: i16,
+    pub trap: f32,
+    pub pawn: f32,
+    pub safety: f32,
+    pub position: f32,
+}
 
+impl DevelopmentTerms {
+    pub fn new(game_state: &GameState, mov: &Move) -> Self {
+        let new_state = &game_state.perform_move(mov);
+        Self {
+            material: game_state.score,
+            trap: get_opponent_trap_score(new_state),
+            pawn: get_pawn_score(game_state, mov),
+            safety: get_safety_score(game_state, mov),
+            position: f32::min(get_position_score(game_state, mov), MAX_POSITION),
+        }
+    }
+
+    pub fn weigh(&self, weights: &[i32; DEVELOPMENT_PARAM_COUNT]) -> Development {
+        let weight = |i: usize| weights[i] as f32 / DEVELOPMENT_SCALE as f32;
+        let material = self.material as i32;
+        let mut dev = Development::default();
+        if material > weights[TRAP_MARGIN] {
+            if material < weights[PAWN_MARGIN] {
+                dev.trap = self.trap * weight(TRAP);
+                dev.pawn = self.pawn * weight(PAWN);
+            } else {
+                dev.trap = self.trap * weight(TRAP_AHEAD);
+            }
+        } else {
+            dev.safety = self.safety * weight(SAFETY);
+            dev.position = self.position * weight(POSITION);
+        }
+        dev
+    }
+}
+
+// Highest total a move can get, in 120ths. At most DEVELOPMENT_SCALE keeps
+// every total within 0..1
+pub fn max_development(weights: &[i32; DEVELOPMENT_PARAM_COUNT]) -> i32 {
+    let early = MAX_TRAP * weights[TRAP] + weights[PAWN];
+    let ahead = MAX_TRAP * weights[TRAP_AHEAD];
+    let even = weights[SAFETY] + MAX_POSITION as i32 * weights[POSITION];
+    early.max(ahead).max(even)
+}
+
+pub fn get_development(game_state: &GameState, mov: &Move) -> Development {
+    DevelopmentTerms::new(game_state, mov).weigh(&params().development)
+}
+
+pub fn find_best_development(game_state: &GameState, moves: Vec<Move>, rng: &mut impl Rng) -> Move {
     let mut best_dev = f32::MIN;
     let mut best_move = Move {
         enemy: false,
         move_type: MoveType::Null,
     };
 
-    let defended_matrix = game_state.get_defended_matrix();
     for mov in moves {
-        let mut dev = 0.;
-        let new_state = &game_state.perform_move(&mov);
-
-        // Apply weightings to each component
-        if game_state.score > 12 {
-            let trap = get_opponent_trap_score(new_state);
-            dev += trap * 1. / 60.;
-
-            let double_move = get_double_move_score(new_state);
-            dev += double_move * 1. / 30.;
-
-            if game_state.score < 25 {
-                let pawn = get_pawn_score(game_state, &mov);
-                dev += pawn * 1. / 3.;
-            } else {
-                dev *= 3. / 2.;
-            }
-        } else {
-            let defended = get_defended_score(&defended_matrix, &mov);
-            dev += f32::min(defended, 1.) * 1. / 3.;
-
-            let position = get_position_score(game_state, &mov);
-            dev += f32::min(position, 4.) * 1. / 6.;
-        }
+        let mut dev = get_development(game_state, &mov).total();
 
         assert!(dev < 1.1);
 
@@ -146,3 +212,35 @@ pub fn find_best_development(game_state: &GameState, moves: Vec<Move>) -> Move {
 
     best_move
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terms(material: i16) -> DevelopmentTerms {
+        DevelopmentTerms {
+            material,
+            trap: 10.,
+            pawn: 1.,
+            safety: 1.,
+            position: 2.,
+        }
+    }
+
+    #[test]
+    fn default_weights() {
+        let weights = &DEFAULT_DEVELOPMENT_WEIGHTS;
+        // Safety and position when even, at a third and a sixth
+        let even = terms(0).weigh(weights);
+        assert!((even.safety - 1. / 3.).abs() < 1e-6);
+        assert!((even.position - 1. / 3.).abs() < 1e-6);
+        assert_eq!(even.trap + even.pawn, 0.);
+        // Traps at a fortieth and pawn moves at a third when ahead
+        let ahead = terms(15).weigh(weights);
+        assert!((ahead.total() - (10. / 40. + 1. / 3.)).abs() < 1e-6);
+        // Only traps, at a sixtieth, when far ahead
+        let far_ahead = terms(30).weigh(weights);
+        assert!((far_ahead.total() - 10. / 60.).abs() < 1e-6);
+        assert_eq!(max_development(weights), DEVELOPMENT_SCALE);
+    }
+}
@@ -0,0 +1,387 @@
+use std::sync::OnceLock;
+
+use crate::{
+    evaluation::piece_value, gamestate::GameState, pieces::PieceClass, settings::BOARD_WIDTH,
+    utils::Vect,
+};
+
+// Score for a won king and pawn ending, well clear of any material balance
+// but below mate scores
+const KNOWN_WIN: i32 = 1000;
+
+// Positions in the KPK bitbase: side to move, both kings, and the pawn on
+// files a-d and ranks 2-7
+const KPK_SIZE: usize = 2 * 64 * 64 * 24;
+
+const INVALID: u8 = 0;
+const UNKNOWN: u8 = 1;
+const DRAW: u8 = 2;
+const WIN: u8 = 4;
+
+#[derive(Default)]
+struct Material {
+    king: Option<Vect>,
+    pawns: Vec<Vect>,
+    knights: i32,
+    bishops: Vec<Vect>,
+    rooks: i32,
+    queens: i32,
+}
+
+impl Material {
+    // Pieces other than pawns and the king
+    fn pieces(&self) -> i32 {
+        self.knights + self.bishops.len() as i32 + self.rooks + self.queens
+    }
+
+    fn lone_king(&self) -> bool {
+        self.pawns.is_empty() && self.pieces() == 0
+    }
+
+    fn piece_value(&self) -> i32 {
+        self.knights * piece_value(PieceClass::Knight)
+            + self.bishops.len() as i32 * piece_value(PieceClass::Bishop)
+            + self.rooks * piece_value(PieceClass::Rook)
+            + self.queens * piece_value(PieceClass::Queen)
+    }
+}
+
+// Engine's material first, then the opponent's
+fn material(game_state: &GameState) -> [Material; 2] {
+    let mut sides = [Material::default(), Material::default()];
+    for (y, row) in game_state.board.iter().enumerate() {
+        for (x, square) in row.iter().enumerate() {
+            let Some(piece) = square else {
+                continue;
+            };
+            let side = &mut sides[piece.enemy as usize];
+            let pos = Vect {
+                x: x as i8,
+                y: y as i8,
+            };
+            match piece.class {
+                PieceClass::Pawn => side.pawns.push(pos),
+                PieceClass::Knight => side.knights += 1,
+                PieceClass::Bishop => side.bishops.push(pos),
+                PieceClass::Rook => side.rooks += 1,
+                PieceClass::Queen => side.queens += 1,
+                PieceClass::King => side.king = Some(pos),
+            }
+        }
+    }
+    sides
+}
+
+fn square_colour(pos: &Vect) -> i8 {
+    (pos.x + pos.y) % 2
+}
+
+fn distance(a: &Vect, b: &Vect) -> i8 {
+    i8::max((a.x - b.x).abs(), (a.y - b.y).abs())
+}
+
+fn is_insufficient(sides: &[Material; 2]) -> bool {
+    let minors_only = sides
+        .iter()
+        .all(|side| side.pawns.is_empty() && side.rooks == 0 && side.queens == 0);
+    if !minors_only {
+        return false;
+    }
+    // A lone minor piece can't mate, nor can bishops all on one colour
+    let minors: i32 = sides.iter().map(|side| side.pieces()).sum();
+    let knights: i32 = sides.iter().map(|side| side.knights).sum();
+    let mut bishop_colours = sides
+        .iter()
+        .flat_map(|side| side.bishops.iter().map(square_colour));
+    let first_colour = bishop_colours.next();
+    minors <= 1 || (knights == 0 && bishop_colours.all(|colour| Some(colour) == first_colour))
+}
+
+// Neither side can possibly mate
+pub fn insufficient_material(game_state: &GameState) -> bool {
+    is_insufficient(&material(game_state))
+}
+
+// KPK squares are numbered rank * 8 + file, with the pawn moving up the board
+fn kpk_distance(a: usize, b: usize) -> usize {
+    usize::max((a % 8).abs_diff(b % 8), (a / 8).abs_diff(b / 8))
+}
+
+fn kpk_pawn_attacks(pawn: usize, square: usize) -> bool {
+    square / 8 == pawn / 8 + 1 && (square % 8).abs_diff(pawn % 8) == 1
+}
+
+fn kpk_king_moves(square: usize) -> impl Iterator<Item = usize> {
+    (0..64).filter(move |&s| kpk_distance(s, square) == 1)
+}
+
+fn kpk_index(strong_to_move: bool, strong_king: usize, weak_king: usize, pawn: usize) -> usize {
+    strong_to_move as usize
+        | strong_king << 1
+        | weak_king << 7
+        | (pawn % 8) << 13
+        | (6 - pawn / 8) << 15
+}
+
+// Results that follow from the position alone, before looking at any moves
+fn kpk_initial(strong_to_move: bool, strong_king: usize, weak_king: usize, pawn: usize) -> u8 {
+    let promotion = pawn + 8;
+    if kpk_distance(strong_king, weak_king) <= 1
+        || strong_king == pawn
+        || weak_king == pawn
+        || (strong_to_move && kpk_pawn_attacks(pawn, weak_king))
+    {
+        INVALID
+    } else if strong_to_move
+        && pawn / 8 == 6
+        && strong_king != promotion
+        && (kpk_distance(weak_king, promotion) > 1 || kpk_distance(strong_king, promotion) == 1)
+    {
+        // Promotes and the queen can't be taken
+        WIN
+    } else if !strong_to_move
+        && (kpk_king_moves(weak_king)
+            .all(|square| kpk_distance(square, strong_king) <= 1 || kpk_pawn_attacks(pawn, square))
+            || (kpk_distance(weak_king, pawn) == 1 && kpk_distance(strong_king, pawn) > 1))
+    {
+        // Stalemated, or the pawn is lost
+        DRAW
+    } else {
+        UNKNOWN
+    }
+}
+
+// Result from the positions each move leads to. Moves into illegal positions
+// find them marked invalid, which has no bits set
+fn kpk_classify(db: &[u8], index: usize) -> u8 {
+    let strong_to_move = index & 1 == 1;
+    let strong_king = (index >> 1) & 63;
+    let weak_king = (index >> 7) & 63;
+    let pawn = (6 - (index >> 15)) * 8 + ((index >> 13) & 3);
+
+    let (good, bad) = if strong_to_move {
+        (WIN, DRAW)
+    } else {
+        (DRAW, WIN)
+    };
+    let mut results = INVALID;
+    if strong_to_move {
+        for square in kpk_king_moves(strong_king) {
+            results |= db[kpk_index(false, square, weak_king, pawn)];
+        }
+        if pawn / 8 < 6 {
+            results |= db[kpk_index(false, strong_king, weak_king, pawn + 8)];
+        }
+        if pawn / 8 == 1 && pawn + 8 != strong_king && pawn + 8 != weak_king {
+            results |= db[kpk_index(false, strong_king, weak_king, pawn + 16)];
+        }
+    } else {
+        for square in kpk_king_moves(weak_king) {
+            results |= db[kpk_index(true, strong_king, square, pawn)];
+        }
+    }
+
+    if results & good != 0 {
+        good
+    } else if results & UNKNOWN != 0 {
+        UNKNOWN
+    } else {
+        bad
+    }
+}
+
+// Generated on first use by repeatedly resolving positions from their moves
+// until nothing changes. Unresolved positions are draws
+fn kpk() -> &'static Vec<bool> {
+    static KPK: OnceLock<Vec<bool>> = OnceLock::new();
+    KPK.get_or_init(|| {
+        let mut db = vec![INVALID; KPK_SIZE];
+        for (index, result) in db.iter_mut().enumerate() {
+            let pawn = (6 - (index >> 15)) * 8 + ((index >> 13) & 3);
+            *result = kpk_initial(index & 1 == 1, (index >> 1) & 63, (index >> 7) & 63, pawn);
+        }
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for index in 0..KPK_SIZE {
+                if db[index] == UNKNOWN {
+                    let result = kpk_classify(&db, index);
+                    if result != UNKNOWN {
+                        db[index] = result;
+                        changed = true;
+                    }
+                }
+            }
+        }
+        db.into_iter().map(|result| result == WIN).collect()
+    })
+}
+
+// Builds the bitbases now rather than in the first search that needs them
+pub fn generate_bitbases() {
+    kpk();
+}
+
+// Whether the side with the pawn wins
+fn kpk_win(
+    strong_king: &Vect,
+    pawn: &Vect,
+    weak_king: &Vect,
+    up: bool,
+    strong_to_move: bool,
+) -> bool {
+    // Turn the board so the pawn moves up on files a-d
+    let square = |pos: &Vect| {
+        let y = if up { pos.y } else { BOARD_WIDTH - 1 - pos.y };
+        let x = if pawn.x > 3 {
+            BOARD_WIDTH - 1 - pos.x
+        } else {
+            pos.x
+        };
+        (y * 8 + x) as usize
+    };
+    kpk()[kpk_index(
+        strong_to_move,
+        square(strong_king),
+        square(weak_king),
+        square(pawn),
+    )]
+}
+
+// Drives the losing king to the edge and brings the winning king closer
+fn mop_up(strong_king: &Vect, weak_king: &Vect) -> i32 {
+    let centre_distance = (2 * weak_king.x - 7).abs() + (2 * weak_king.y - 7).abs();
+    10 * centre_distance as i32 + 10 * (7 - distance(strong_king, weak_king)) as i32
+}
+
+// Score scaled down for material that is hard to win with
+fn scale_drawish(score: i32, sides: &[Material; 2]) -> i32 {
+    let strong_enemy = score < 0;
+    let strong = &sides[strong_enemy as usize];
+    let weak = &sides[!strong_enemy as usize];
+
+    // A rook pawn with the bishop of the wrong colour can't be forced
+    // through once the defending king reaches the corner
+    if let (Some(weak_king), [bishop]) = (weak.king, &strong.bishops[..]) {
+        let file = strong.pawns.first().map(|pawn| pawn.x);
+        let rook_pawns = file.is_some_and(|file| {
+            (file == 0 || file == BOARD_WIDTH - 1) && strong.pawns.iter().all(|p| p.x == file)
+        });
+        if rook_pawns && strong.pieces() == 1 && weak.lone_king() {
+            let promotion = Vect {
+                x: file.unwrap(),
+                y: if strong_enemy { 0 } else { BOARD_WIDTH - 1 },
+            };
+            if square_colour(bishop) != square_colour(&promotion)
+                && distance(&weak_king, &promotion) <= 1
+            {
+                return 0;
+            }
+        }
+    }
+
+    // Bishops of opposite colours and nothing else but pawns
+    if let ([engine_bishop], [opponent_bishop]) = (&sides[0].bishops[..], &sides[1].bishops[..]) {
+        if sides.iter().all(|side| side.pieces() == 1)
+            && square_colour(engine_bishop) != square_colour(opponent_bishop)
+        {
+            return score / 2;
+        }
+    }
+
+    // Without pawns a single minor piece ahead is rarely enough
+    if strong.pawns.is_empty()
+        && strong.piece_value() - weak.piece_value() <= piece_value(PieceClass::Bishop)
+    {
+        return score / 4;
+    }
+    score
+}
+
+// Static evaluation from the engine's perspective with endgame knowledge
+// applied, given the side to move
+pub fn endgame_evaluation(game_state: &GameState, enemy: bool, score: i32) -> i32 {
+    let sides = material(game_state);
+    if is_insufficient(&sides) {
+        return 0;
+    }
+
+    for strong_enemy in [false, true] {
+        let strong = &sides[strong_enemy as usize];
+        let weak = &sides[!strong_enemy as usize];
+        let (Some(strong_king), Some(weak_king)) = (strong.king, weak.king) else {
+            continue;
+        };
+        if !weak.lone_king() {
+            continue;
+        }
+        let sign = if strong_enemy { -1 } else { 1 };
+
+        if let ([pawn], 0) = (&strong.pawns[..], strong.pieces()) {
+            let up = !strong_enemy;
+            let rank = if up { pawn.y } else { BOARD_WIDTH - 1 - pawn.y };
+            // Pawns can only be on the back ranks in set up positions
+            if !(1..BOARD_WIDTH - 1).contains(&rank) {
+                continue;
+            }
+            return if kpk_win(&strong_king, pawn, &weak_king, up, enemy == strong_enemy) {
+                sign * (KNOWN_WIN + 20 * rank as i32)
+            } else {
+                0
+            };
+        }
+
+        if strong.pawns.is_empty() && strong.rooks + strong.queens > 0 {
+            return score + sign * mop_up(&strong_king, &weak_king);
+        }
+    }
+
+    scale_drawish(score, &sides)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fen::parse_fen;
+
+    fn evaluation(fen: &str) -> i32 {
+        endgame_evaluation(&parse_fen(fen).unwrap(), false, 0)
+    }
+
+    #[test]
+    fn kpk_wins() {
+        // King in front of its pawn on the sixth rank wins with either side to move
+        assert_eq!(evaluation("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1"), 1080);
+        assert_eq!(evaluation("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1"), -1080);
+        // Taking the opposition
+        assert_eq!(evaluation("8/4k3/8/4K3/4P3/8/8/8 b - - 0 1"), -1060);
+        // The pawn runs outside the king's square
+        assert_eq!(evaluation("8/8/6k1/2P5/8/8/8/7K w - - 0 1"), 1080);
+        // Black's pawn, mirrored
+        assert_eq!(evaluation("8/8/8/8/4p3/4k3/8/4K3 b - - 0 1"), 1080);
+        assert_eq!(evaluation("8/8/8/8/4p3/4k3/8/4K3 w - - 0 1"), -1080);
+    }
+
+    #[test]
+    fn kpk_draws() {
+        // The defender has the opposition
+        assert_eq!(evaluation("8/4k3/8/4K3/4P3/8/8/8 w - - 0 1"), 0);
+        // The king catches the pawn
+        assert_eq!(evaluation("8/8/6k1/2P5/8/8/8/7K b - - 0 1"), 0);
+        // Rook pawn with the king in the corner
+        assert_eq!(evaluation("k7/8/K7/P7/8/8/8/8 w - - 0 1"), 0);
+        // Stalemate
+        assert_eq!(evaluation("5k2/5P2/5K2/8/8/8/8/8 b - - 0 1"), 0);
+    }
+
+    #[test]
+    fn insufficient() {
+        let insufficient = |fen: &str| insufficient_material(&parse_fen(fen).unwrap());
+        assert!(insufficient("4k3/8/8/8/8/8/8/3NK3 w - - 0 1"));
+        assert!(insufficient("4k3/8/8/8/8/8/8/2B1K3 w - - 0 1"));
+        assert!(insufficient("4kb2/8/8/8/8/8/8/2B1K3 w - - 0 1"));
+        assert!(!insufficient("4k1b1/8/8/8/8/8/8/2B1K3 w - - 0 1"));
+        assert!(!insufficient("4k3/8/8/8/8/8/8/R3K3 w - - 0 1"));
+        assert!(!insufficient("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1"));
+    }
+}
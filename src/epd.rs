@@ -0,0 +1,165 @@
+use std::{fs, time::Instant};
+
+use crate::{
+    fen::{parse_epd, EpdRecord},
+    gamestate::GameState,
+    moves::Move,
+    san::{parse_move, to_san},
+    simulation::{analyse_with_limits, score_repr, Progress, SearchLimits, SearchOptions},
+    transposition::table,
+};
+
+// Used when no limit is given
+pub const DEFAULT_EPD_DEPTH: u8 = 6;
+
+fn epd_moves(record: &EpdRecord, opcode: &str) -> Result<Vec<Move>, String> {
+    let Some(operands) = record.operation(opcode) else {
+        return Ok(Vec::new());
+    };
+    operands
+        .iter()
+        .map(|text| {
+            parse_move(text, &record.game_state)
+                .ok_or_else(|| format!("{} move {} is not legal", opcode, text))
+        })
+        .collect()
+}
+
+fn san_list(game_state: &GameState, moves: &[Move]) -> String {
+    let sans: Vec<String> = moves.iter().map(|mov| to_san(game_state, mov)).collect();
+    sans.join(" ")
+}
+
+// Searches every position with a bm (best move) or am (avoid move) operation
+// and checks the move chosen. Machine output is one tab separated line per
+// position, so runs can be diffed between versions
+pub fn run_epd(path: &str, limits: &SearchLimits, options: &SearchOptions, machine: bool) {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => {
+            println!("Couldn't read {}: {}", path, e);
+            return;
+        }
+    };
+    let mut options = *options;
+    options.progress = Progress::Quiet;
+
+    if machine {
+        println!("id\tresult\tmove\texpected\tscore\tdepth\tnodes\tms");
+    }
+    let (mut solved, mut total, mut total_nodes) = (0, 0, 0);
+    let timer = Instant::now();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let record = match parse_epd(line) {
+            Ok(record) => record,
+            Err(e) => {
+                println!("Line {}: {}", i + 1, e);
+                continue;
+            }
+        };
+        let (best, avoid) = match (epd_moves(&record, "bm"), epd_moves(&record, "am")) {
+            (Ok(best), Ok(avoid)) if !best.is_empty() || !avoid.is_empty() => (best, avoid),
+            (Err(e), _) | (_, Err(e)) => {
+                println!("Line {}: {}", i + 1, e);
+                continue;
+            }
+            _ => {
+                println!("Line {}: no bm or am operation", i + 1);
+                continue;
+            }
+        };
+        let id = record
+            .operation("id")
+            .and_then(|ops| ops.first())
+            .cloned()
+            .unwrap_or_else(|| format!("line {}", i + 1));
+
+        let game_state = &record.game_state;
+        table().clear();
+        let position_timer = Instant::now();
+        let analysis = analyse_with_limits(game_state, limits, &options);
+        let ms = position_timer.elapsed().as_millis();
+        let Some(&mov) = analysis.best_moves.first() else {
+            println!("Line {}: no legal moves", i + 1);
+            continue;
+        };
+
+        let found = (best.is_empty() || best.contains(&mov)) && !avoid.contains(&mov);
+        total += 1;
+        total_nodes += analysis.sim_moves;
+        if found {
+            solved += 1;
+        }
+        let mut expected = Vec::new();
+        if !best.is_empty() {
+            expected.push(format!("bm {}", san_list(game_state, &best)));
+        }
+        if !avoid.is_empty() {
+            expected.push(format!("am {}", san_list(game_state, &avoid)));
+        }
+        let expected = expected.join(", ");
+        let san = to_san(game_state, &mov);
+        let result = if found { "solved" } else { "failed" };
+
+        if machine {
+            println!(
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                id, result, san, expected, analysis.score, analysis.depth, analysis.sim_moves, ms
+            );
+        } else {
+            println!(
+                "{}: {} with {}, expected {} ({}, depth {}, {} moves, {} ms)",
+                id,
+                result,
+                san,
+                expected,
+                score_repr(analysis.score),
+                analysis.depth,
+                analysis.sim_moves,
+                ms
+            );
+        }
+    }
+
+    let ms = timer.elapsed().as_millis();
+    if machine {
+        println!(
+            "total\t{}/{}\t\t\t\t\t{}\t{}",
+            solved, total, total_nodes, ms
+        );
+    } else {
+        println!(
+            "Solved {} of {}, {} moves simulated in {} ms",
+            solved, total, total_nodes, ms
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn best_and_avoid_moves() {
+        let record =
+            parse_epd("4k3/8/8/8/8/8/8/R3K2R w KQ - bm O-O Ra8+; am Kd1; id \"test\";").unwrap();
+        let best = epd_moves(&record, "bm").unwrap();
+        assert_eq!(san_list(&record.game_state, &best), "O-O Ra8+");
+        let avoid = epd_moves(&record, "am").unwrap();
+        assert_eq!(san_list(&record.game_state, &avoid), "Kd1");
+        assert!(epd_moves(&record, "pm").unwrap().is_empty());
+    }
+
+    #[test]
+    fn illegal_moves_are_reported() {
+        let record = parse_epd("4k3/8/8/8/8/8/8/4K3 w - - bm Qd1;").unwrap();
+        assert_eq!(
+            epd_moves(&record, "bm").err().as_deref(),
+            Some("bm move Qd1 is not legal")
+        );
+    }
+}
@@ -0,0 +1,368 @@
+use std::{fs, sync::OnceLock};
+
+use crate::{
+    development::{
+        max_development, DEFAULT_DEVELOPMENT_WEIGHTS, DEVELOPMENT_PARAM_COUNT,
+        DEVELOPMENT_PARAM_NAMES, DEVELOPMENT_SCALE,
+    },
+    gamestate::GameState,
+    pieces::{Board, PieceClass},
+    settings::BOARD_WIDTH,
+    utils::Vect,
+};
+
+pub const PARAM_COUNT: usize = 15;
+
+// Evaluation is linear: each weight multiplies the difference in a feature count
+pub const PARAM_NAMES: [&str; PARAM_COUNT] = [
+    "pawn_value",
+    "knight_value",
+    "bishop_value",
+    "rook_value",
+    "queen_value",
+    "centre",
+    "advancement",
+    "doubled_pawn",
+    "isolated_pawn",
+    "passed_pawn",
+    "king_shield",
+    "king_open_file",
+    "mobility",
+    "developed_minor",
+    "defended",
+];
+
+// Indices into weights and features
+pub const PAWN_VALUE: usize = 0;
+pub const KNIGHT_VALUE: usize = 1;
+pub const BISHOP_VALUE: usize = 2;
+pub const ROOK_VALUE: usize = 3;
+pub const QUEEN_VALUE: usize = 4;
+pub const CENTRE: usize = 5;
+pub const ADVANCEMENT: usize = 6;
+pub const DOUBLED_PAWN: usize = 7;
+pub const ISOLATED_PAWN: usize = 8;
+pub const PASSED_PAWN: usize = 9;
+pub const KING_SHIELD: usize = 10;
+pub const KING_OPEN_FILE: usize = 11;
+pub const MOBILITY: usize = 12;
+pub const DEVELOPED_MINOR: usize = 13;
+pub const DEFENDED: usize = 14;
+
+// Terms grouped for display
+pub const TERM_GROUPS: [(&str, &[usize]); 6] = [
+    (
+        "Material",
+        &[
+            PAWN_VALUE,
+            KNIGHT_VALUE,
+            BISHOP_VALUE,
+            ROOK_VALUE,
+            QUEEN_VALUE,
+        ],
+    ),
+    ("Piece-square", &[CENTRE, ADVANCEMENT]),
+    ("Pawns", &[DOUBLED_PAWN, ISOLATED_PAWN, PASSED_PAWN]),
+    ("King safety", &[KING_SHIELD, KING_OPEN_FILE]),
+    ("Mobility", &[MOBILITY]),
+    ("Development", &[DEVELOPED_MINOR, DEFENDED]),
+];
+
+// Centipawns
+const DEFAULT_WEIGHTS: [i32; PARAM_COUNT] = [
+    100, 300, 300, 500, 900, 8, 4, -12, -10, 8, 10, -15, 3, 12, 4,
+];
+
+// High enough that giving up the king is never a good trade
+pub const KING_VALUE: i32 = 20000;
+
+pub type Features = [i32; PARAM_COUNT];
+
+#[derive(Clone)]
+pub struct EvalParams {
+    pub weights: [i32; PARAM_COUNT],
+    // Weights for choosing between moves the search scores equally
+    pub development: [i32; DEVELOPMENT_PARAM_COUNT],
+}
+
+impl Default for EvalParams {
+    fn default() -> Self {
+        Self {
+            weights: DEFAULT_WEIGHTS,
+            development: DEFAULT_DEVELOPMENT_WEIGHTS,
+        }
+    }
+}
+
+impl EvalParams {
+    // One "name value" pair per line, missing names keep their defaults
+    pub fn load(path: &str) -> Result<EvalParams, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let mut params = EvalParams::default();
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.split_whitespace();
+            let (name, value) = match (parts.next(), parts.next()) {
+                (Some(name), Some(value)) => (name, value),
+                _ => return Err(format!("Malformed line '{}'", line)),
+            };
+            let weight = if let Some(i) = PARAM_NAMES.iter().position(|&n| n == name) {
+                &mut params.weights[i]
+            } else if let Some(i) = DEVELOPMENT_PARAM_NAMES.iter().position(|&n| n == name) {
+                &mut params.development[i]
+            } else {
+                return Err(format!("Unknown parameter '{}'", name));
+            };
+            *weight = match value.parse() {
+                Ok(v) => v,
+                Err(_) => return Err(format!("'{}' is not an integer", value)),
+            };
+        }
+        if max_development(&params.development) > DEVELOPMENT_SCALE {
+            return Err(format!(
+                "Development weights add up to more than {}",
+                DEVELOPMENT_SCALE
+            ));
+        }
+        Ok(params)
+    }
+
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        let mut out = String::new();
+        for (name, weight) in PARAM_NAMES.iter().zip(self.weights) {
+            out += &format!("{} {}\n", name, weight);
+        }
+        for (name, weight) in DEVELOPMENT_PARAM_NAMES.iter().zip(self.development) {
+            out += &format!("{} {}\n", name, weight);
+        }
+        fs::write(path, out)
+    }
+
+    pub fn score(&self, features: &Features) -> i32 {
+        self.weights
+            .iter()
+            .zip(features)
+            .map(|(weight, feature)| weight * feature)
+            .sum()
+    }
+}
+
+static PARAMS: OnceLock<EvalParams> = OnceLock::new();
+
+pub fn piece_value(class: PieceClass) -> i32 {
+    let weights = &params().weights;
+    match class {
+        PieceClass::Pawn => weights[PAWN_VALUE],
+        PieceClass::Knight => weights[KNIGHT_VALUE],
+        PieceClass::Bishop => weights[BISHOP_VALUE],
+        PieceClass::Rook => weights[ROOK_VALUE],
+        PieceClass::Queen => weights[QUEEN_VALUE],
+        PieceClass::King => KING_VALUE,
+    }
+}
+
+pub fn params() -> &'static EvalParams {
+    PARAMS.get_or_init(EvalParams::default)
+}
+
+// Only has an effect before the first evaluation
+pub fn set_params(params: EvalParams) {
+    let _ = PARAMS.set(params);
+}
+
+fn relative_rank(y: i8, enemy: bool) -> i8 {
+    if enemy {
+        BOARD_WIDTH - 1 - y
+    } else {
+        y
+    }
+}
+
+fn is_side_piece(board: &Board, x: i8, y: i8, enemy: bool, class: PieceClass) -> bool {
+    if !(0..BOARD_WIDTH).contains(&x) || !(0..BOARD_WIDTH).contains(&y) {
+        return false;
+    }
+    match board[y as usize][x as usize] {
+        Some(piece) => piece.enemy == enemy && piece.class == class,
+        None => false,
+    }
+}
+
+fn pawn_features(board: &Board, enemy: bool, features: &mut Features) {
+    let forward: i8 = if enemy { -1 } else { 1 };
+    let mut file_counts = [0; BOARD_WIDTH as usize];
+    for x in 0..BOARD_WIDTH {
+        for y in 0..BOARD_WIDTH {
+            if is_side_piece(board, x, y, enemy, PieceClass::Pawn) {
+                file_counts[x as usize] += 1;
+            }
+        }
+    }
+
+    for x in 0..BOARD_WIDTH {
+        let count = file_counts[x as usize];
+        if count == 0 {
+            continue;
+        }
+        features[DOUBLED_PAWN] += count - 1;
+
+        let left = if x > 0 {
+            file_counts[x as usize - 1]
+        } else {
+            0
+        };
+        let right = if x < BOARD_WIDTH - 1 {
+            file_counts[x as usize + 1]
+        } else {
+            0
+        };
+        if left == 0 && right == 0 {
+            features[ISOLATED_PAWN] += count;
+        }
+
+        for y in 0..BOARD_WIDTH {
+            if !is_side_piece(board, x, y, enemy, PieceClass::Pawn) {
+                continue;
+            }
+            let rank = relative_rank(y, enemy);
+            features[ADVANCEMENT] += rank as i32 - 1;
+
+            // Passed if no opposing pawn ahead on this or neighbouring files
+            let mut passed = true;
+            let mut ahead = y + forward;
+            while (0..BOARD_WIDTH).contains(&ahead) {
+                for file in [x - 1, x, x + 1] {
+                    if is_side_piece(board, file, ahead, !enemy, PieceClass::Pawn) {
+                        passed = false;
+                    }
+                }
+                ahead += forward;
+            }
+            if passed {
+                features[PASSED_PAWN] += rank as i32 - 1;
+            }
+        }
+    }
+}
+
+fn king_features(board: &Board, enemy: bool, king: Option<Vect>, features: &mut Features) {
+    let king = match king {
+        Some(pos) => pos,
+        None => return,
+    };
+    let forward: i8 = if enemy { -1 } else { 1 };
+
+    for file in [king.x - 1, king.x, king.x + 1] {
+        if is_side_piece(board, file, king.y + forward, enemy, PieceClass::Pawn)
+            || is_side_piece(board, file, king.y + 2 * forward, enemy, PieceClass::Pawn)
+        {
+            features[KING_SHIELD] += 1;
+        }
+    }
+
+    let own_pawn_on_file =
+        (0..BOARD_WIDTH).any(|y| is_side_piece(board, king.x, y, enemy, PieceClass::Pawn));
+    if !own_pawn_on_file {
+        features[KING_OPEN_FILE] += 1;
+    }
+}
+
+pub fn side_features(game_state: &GameState, enemy: bool) -> Features {
+    let board = &game_state.board;
+    let mut features = [0; PARAM_COUNT];
+    let mut defended = [[false; BOARD_WIDTH as usize]; BOARD_WIDTH as usize];
+    let mut king = None;
+
+    let castling = if enemy {
+        &game_state.opponent_castling
+    } else {
+        &game_state.engine_castling
+    };
+
+    for x in 0..BOARD_WIDTH {
+        for y in 0..BOARD_WIDTH {
+            let piece = match board[y as usize][x as usize] {
+                Some(piece) if piece.enemy == enemy => piece,
+                _ => continue,
+            };
+
+            match piece.class {
+                PieceClass::Pawn => features[PAWN_VALUE] += 1,
+                PieceClass::Knight => features[KNIGHT_VALUE] += 1,
+                PieceClass::Bishop => features[BISHOP_VALUE] += 1,
+                PieceClass::Rook => features[ROOK_VALUE] += 1,
+                PieceClass::Queen => features[QUEEN_VALUE] += 1,
+                PieceClass::King => king = Some(Vect { x, y }),
+            }
+
+            if piece.class != PieceClass::King {
+                // Extended centre, with the four middle squares counting twice
+                if (2..=5).contains(&x) && (2..=5).contains(&y) {
+                    features[CENTRE] += 1;
+                    if (3..=4).contains(&x) && (3..=4).contains(&y) {
+                        features[CENTRE] += 1;
+                    }
+                }
+            }
+
+            if matches!(piece.class, PieceClass::Knight | PieceClass::Bishop)
+                && relative_rank(y, enemy) != 0
+            {
+                features[DEVELOPED_MINOR] += 1;
+            }
+
+            let (moves, defended_squares) = piece.all_moves(
+                board,
+                Vect { x, y },
+                true,
+                &game_state.en_passant_midpoint,
+                castling,
+            );
+            features[MOBILITY] += moves.len() as i32;
+            for square in defended_squares {
+                defended[square.y as usize][square.x as usize] = true;
+            }
+        }
+    }
+
+    for x in 0..BOARD_WIDTH {
+        for y in 0..BOARD_WIDTH {
+            if let Some(piece) = board[y as usize][x as usize] {
+                if piece.enemy == enemy
+                    && piece.class != PieceClass::King
+                    && defended[y as usize][x as usize]
+                {
+                    features[DEFENDED] += 1;
+                }
+            }
+        }
+    }
+
+    pawn_features(board, enemy, &mut features);
+    king_features(board, enemy, king, &mut features);
+
+    features
+}
+
+// Engine features minus opponent features
+pub fn feature_difference(game_state: &GameState) -> Features {
+    let engine = side_features(game_state, false);
+    let opponent = side_features(game_state, true);
+    let mut difference = [0; PARAM_COUNT];
+    for i in 0..PARAM_COUNT {
+        difference[i] = engine[i] - opponent[i];
+    }
+    difference
+}
+
+pub fn evaluate_with(game_state: &GameState, params: &EvalParams) -> i32 {
+    params.score(&feature_difference(game_state))
+}
+
+// Centipawns from the engine's perspective
+pub fn evaluate(game_state: &GameState) -> i32 {
+    evaluate_with(game_state, params())
+}
@@ -0,0 +1,168 @@
+use crate::{
+    development::get_development,
+    endgame::endgame_evaluation,
+    evaluation::{evaluate, params, side_features, PARAM_NAMES, TERM_GROUPS},
+    gamestate::GameState,
+    moves::parse_coordinate,
+    render::RenderOptions,
+    settings::RANDOM_FACTOR,
+    simulation::{analyse, pv_repr, score_repr, SearchOptions},
+};
+
+fn colour_names(game_state: &GameState) -> (&str, &str) {
+    if game_state.engine_black {
+        ("Black", "White")
+    } else {
+        ("White", "Black")
+    }
+}
+
+pub fn print_eval(game_state: &GameState) {
+    game_state.print(&RenderOptions::new(game_state.engine_black));
+
+    let weights = &params().weights;
+    let engine = side_features(game_state, false);
+    let opponent = side_features(game_state, true);
+    let (engine_name, opponent_name) = colour_names(game_state);
+
+    println!(
+        "{:<18}{:>14}{:>14}{:>8}",
+        "Term", engine_name, opponent_name, "Total"
+    );
+    for (group, indices) in TERM_GROUPS {
+        let mut group_total = 0;
+        println!("{}", group);
+        for &i in indices {
+            let engine_score = weights[i] * engine[i];
+            let opponent_score = weights[i] * opponent[i];
+            group_total += engine_score - opponent_score;
+            println!(
+                "  {:<16}{:>14}{:>14}{:>8}",
+                PARAM_NAMES[i],
+                format!("{} ({})", engine_score, engine[i]),
+                format!("{} ({})", opponent_score, opponent[i]),
+                engine_score - opponent_score
+            );
+        }
+        println!("  {:<44}{:>8}", "", group_total);
+    }
+    let score = evaluate(game_state);
+    println!(
+        "Evaluation for {} (centipawns, feature counts in brackets): {}",
+        engine_name, score
+    );
+    let endgame_score = endgame_evaluation(game_state, false, score);
+    if endgame_score != score {
+        println!(
+            "With endgame knowledge, {} to move: {}",
+            engine_name, endgame_score
+        );
+    }
+}
+
+pub fn explain(game_state: &GameState, depth: u8, move_text: &str, options: &SearchOptions) {
+    let engine_black = game_state.engine_black;
+    let target = match parse_coordinate(move_text, game_state, false) {
+        Some(mov) => mov,
+        None => {
+            println!("'{}' is not a move in this position", move_text);
+            return;
+        }
+    };
+    if depth == 0 {
+        println!("Depth must be at least 1");
+        return;
+    }
+
+    let analysis = analyse(game_state, depth, options);
+    let best_moves = analysis.best_moves;
+    let mut move_scores = analysis.move_scores;
+    move_scores.sort_by_key(|s| std::cmp::Reverse(s.score));
+
+    let is_best = |coordinate: &str| {
+        best_moves
+            .iter()
+            .any(|m| m.coordinate(engine_black) == coordinate)
+    };
+
+    println!(
+        "\n  {:<8}{:>8}{:>10}{:>8}{:>8}{:>8}{:>8}{:>8}",
+        "Move", "Search", "Material", "Dev", "Trap", "Pawn", "Safe", "Place"
+    );
+    let mut target_found = false;
+    for score in move_scores.iter() {
+        let coordinate = score.mov.coordinate(engine_black);
+        let dev = get_development(game_state, &score.mov);
+        let marker = if coordinate == target.coordinate(engine_black) {
+            target_found = true;
+            '>'
+        } else if is_best(&coordinate) {
+            '*'
+        } else {
+            ' '
+        };
+        let search = score_repr(score.score);
+        println!(
+            "{} {:<8}{:>8}{:>10}{:>8.3}{:>8.3}{:>8.3}{:>8.3}{:>8.3}",
+            marker,
+            coordinate,
+            if score.exact {
+                search
+            } else {
+                format!("<={}", search)
+            },
+            game_state.perform_move(&score.mov).score - game_state.score,
+            dev.total(),
+            dev.trap,
+            dev.pawn,
+            dev.safety,
+            dev.position
+        );
+    }
+
+    println!("\n* best search score, > {}", move_text);
+    for score in move_scores.iter() {
+        if score.mov == target || best_moves.first() == Some(&score.mov) {
+            println!(
+                "{}: {}",
+                score.mov.coordinate(engine_black),
+                pv_repr(&score.pv, engine_black)
+            );
+        }
+    }
+    if !target_found {
+        println!("{} leaves the king in check", move_text);
+    } else if is_best(&target.coordinate(engine_black)) {
+        println!(
+            "{} shares the best search score with {} other moves, development decides between them with random factor {}",
+            move_text,
+            best_moves.len() - 1,
+            RANDOM_FACTOR
+        );
+    } else {
+        println!(
+            "{} has a worse search score than the best moves, development is not considered",
+            move_text
+        );
+    }
+}
+
+// Top root moves, each with its own score and line
+pub fn print_lines(game_state: &GameState, depth: u8, options: &SearchOptions) {
+    game_state.print(&RenderOptions::new(game_state.engine_black));
+    let analysis = analyse(game_state, depth, options);
+    if analysis.engine_no_moves {
+        println!("No legal moves");
+        return;
+    }
+    println!();
+    for (i, line) in analysis.lines.iter().enumerate() {
+        println!(
+            "{:>2}. {:>7}  depth {}  {}",
+            i + 1,
+            score_repr(line.score),
+            line.depth,
+            pv_repr(&line.pv, game_state.engine_black)
+        );
+    }
+}
@@ -0,0 +1,421 @@
+use crate::{
+    gamestate::{material_score, GameState},
+    pieces::{Board, Piece, PieceClass},
+    settings::BOARD_WIDTH,
+    utils::{parse_pos_notation, pos_notation, CastlingPossibilities},
+};
+
+pub struct EpdRecord {
+    pub game_state: GameState,
+    // Opcode followed by its operands, quotes removed
+    pub operations: Vec<(String, Vec<String>)>,
+}
+
+impl EpdRecord {
+    pub fn operation(&self, opcode: &str) -> Option<&Vec<String>> {
+        self.operations
+            .iter()
+            .find(|(code, _)| code == opcode)
+            .map(|(_, operands)| operands)
+    }
+}
+
+fn castling_allowed(board: &Board, enemy: bool, queenside: bool) -> bool {
+    // Only keep rights which the pieces on the board can actually use
+    let y = if enemy { BOARD_WIDTH - 1 } else { 0 } as usize;
+    let rook_x = if queenside { 0 } else { BOARD_WIDTH - 1 } as usize;
+    let king_ok =
+        matches!(board[y][4], Some(Piece { class: PieceClass::King, enemy: e }) if e == enemy);
+    let rook_ok =
+        matches!(board[y][rook_x], Some(Piece { class: PieceClass::Rook, enemy: e }) if e == enemy);
+    king_ok && rook_ok
+}
+
+// Side to move becomes the engine, so the board is mirrored when black is to move
+pub fn parse_fen(fen: &str) -> Result<GameState, String> {
+    let fields: Vec<&str> = fen.split_whitespace().collect();
+    if fields.len() < 4 {
+        return Err(format!("FEN '{}' has too few fields", fen));
+    }
+
+    let engine_black = match fields[1] {
+        "w" => false,
+        "b" => true,
+        other => return Err(format!("'{}' is not a side to move", other)),
+    };
+
+    let mut board: Board = [[None; BOARD_WIDTH as usize]; BOARD_WIDTH as usize];
+    let ranks: Vec<&str> = fields[0].split('/').collect();
+    if ranks.len() != BOARD_WIDTH as usize {
+        return Err(format!(
+            "'{}' does not have {} ranks",
+            fields[0], BOARD_WIDTH
+        ));
+    }
+    // White then black
+    let mut kings = [0; 2];
+    for (j, rank) in ranks.iter().enumerate() {
+        let rank_index = BOARD_WIDTH - 1 - j as i8;
+        let y = if engine_black {
+            BOARD_WIDTH - 1 - rank_index
+        } else {
+            rank_index
+        };
+        let mut x = 0;
+        for char in rank.chars() {
+            if let Some(skip) = char.to_digit(10) {
+                x += skip as i8;
+                continue;
+            }
+            let class = match PieceClass::from_char(char) {
+                Some(class) => class,
+                None => return Err(format!("'{}' is not a valid piece", char)),
+            };
+            if x >= BOARD_WIDTH {
+                return Err(format!("Rank '{}' is too long", rank));
+            }
+            let white = char.is_ascii_uppercase();
+            match class {
+                PieceClass::King => kings[!white as usize] += 1,
+                PieceClass::Pawn if rank_index == 0 || rank_index == BOARD_WIDTH - 1 => {
+                    return Err(String::from("Pawns cannot be on the first or last rank"))
+                }
+                _ => (),
+            }
+            board[y as usize][x as usize] = Some(Piece {
+                class,
+                enemy: white == engine_black,
+            });
+            x += 1;
+        }
+        if x != BOARD_WIDTH {
+            return Err(format!("Rank '{}' is not {} squares", rank, BOARD_WIDTH));
+        }
+    }
+    if kings != [1, 1] {
+        return Err(String::from("Each side must have exactly one king"));
+    }
+
+    let mut engine_castling = CastlingPossibilities {
+        queenside: false,
+        kingside: false,
+    };
+    let mut opponent_castling = engine_castling;
+    if fields[2] != "-" {
+        for char in fields[2].chars() {
+            let white = match char {
+                'K' | 'Q' => true,
+                'k' | 'q' => false,
+                _ => return Err(format!("'{}' is not a castling right", char)),
+            };
+            let castling = if white == engine_black {
+                &mut opponent_castling
+            } else {
+                &mut engine_castling
+            };
+            if char.eq_ignore_ascii_case(&'q') {
+                castling.queenside = true;
+            } else {
+                castling.kingside = true;
+            }
+        }
+    }
+    engine_castling.queenside &= castling_allowed(&board, false, true);
+    engine_castling.kingside &= castling_allowed(&board, false, false);
+    opponent_castling.queenside &= castling_allowed(&board, true, true);
+    opponent_castling.kingside &= castling_allowed(&board, true, false);
+
+    let en_passant_midpoint = match fields[3] {
+        "-" => None,
+        square => match parse_pos_notation(square, engine_black) {
+            Some(pos) => Some(pos),
+            None => return Err(format!("'{}' is not a square", square)),
+        },
+    };
+
+    // The move counters are optional, as in EPD
+    let halfmove_clock = match fields.get(4) {
+        Some(text) => match text.parse() {
+            Ok(clock) => clock,
+            Err(_) => return Err(format!("'{}' is not a halfmove clock", text)),
+        },
+        None => 0,
+    };
+    let fullmove_number = match fields.get(5) {
+        Some(text) => match text.parse() {
+            Ok(number) if number > 0 => number,
+            _ => return Err(format!("'{}' is not a move number", text)),
+        },
+        None => 1,
+    };
+
+    Ok(GameState {
+        board,
+        score: material_score(&board),
+        kings_alive: true,
+        engine_castling,
+        opponent_castling,
+        en_passant_midpoint,
+        engine_black,
+        halfmove_clock,
+        fullmove_number,
+    })
+}
+
+// Inverse of parse_fen, the engine is the side to move
+pub fn to_fen(game_state: &GameState) -> String {
+    let engine_black = game_state.engine_black;
+    let mut ranks = Vec::new();
+    for rank_index in (0..BOARD_WIDTH).rev() {
+        let y = if engine_black {
+            BOARD_WIDTH - 1 - rank_index
+        } else {
+            rank_index
+        };
+        let mut rank = String::new();
+        let mut empty = 0;
+        for square in game_state.board[y as usize].iter() {
+            match square {
+                Some(piece) => {
+                    if empty > 0 {
+                        rank.push_str(&empty.to_string());
+                        empty = 0;
+                    }
+                    let char = Piece {
+                        enemy: false,
+                        ..*piece
+                    }
+                    .repr();
+                    let white = piece.enemy == engine_black;
+                    rank.push(if white {
+                        char.to_ascii_uppercase()
+                    } else {
+                        char
+                    });
+                }
+                None => empty += 1,
+            }
+        }
+        if empty > 0 {
+            rank.push_str(&empty.to_string());
+        }
+        ranks.push(rank);
+    }
+
+    let (white_castling, black_castling) = if engine_black {
+        (game_state.opponent_castling, game_state.engine_castling)
+    } else {
+        (game_state.engine_castling, game_state.opponent_castling)
+    };
+    let mut castling = String::new();
+    for (allowed, char) in [
+        (white_castling.kingside, 'K'),
+        (white_castling.queenside, 'Q'),
+        (black_castling.kingside, 'k'),
+        (black_castling.queenside, 'q'),
+    ] {
+        if allowed {
+            castling.push(char);
+        }
+    }
+    if castling.is_empty() {
+        castling.push('-');
+    }
+
+    let en_passant = match &game_state.en_passant_midpoint {
+        Some(pos) => pos_notation(pos, engine_black),
+        None => String::from("-"),
+    };
+    format!(
+        "{} {} {} {} {} {}",
+        ranks.join("/"),
+        if engine_black { "b" } else { "w" },
+        castling,
+        en_passant,
+        game_state.halfmove_clock,
+        game_state.fullmove_number
+    )
+}
+
+fn parse_operations(text: &str) -> Result<Vec<(String, Vec<String>)>, String> {
+    let mut operations = Vec::new();
+    let mut tokens: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut was_quoted = false;
+
+    for char in text.chars() {
+        match char {
+            '"' => {
+                quoted = !quoted;
+                was_quoted = true;
+            }
+            ';' if !quoted => {
+                if !current.is_empty() || was_quoted {
+                    tokens.push(current.clone());
+                }
+                current.clear();
+                was_quoted = false;
+                if !tokens.is_empty() {
+                    let opcode = tokens.remove(0);
+                    operations.push((opcode, tokens.clone()));
+                    tokens.clear();
+                }
+            }
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() || was_quoted {
+                    tokens.push(current.clone());
+                }
+                current.clear();
+                was_quoted = false;
+            }
+            c => current.push(c),
+        }
+    }
+    if quoted {
+        return Err(String::from("Unterminated string in EPD operations"));
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    if !tokens.is_empty() {
+        let opcode = tokens.remove(0);
+        operations.push((opcode, tokens));
+    }
+    Ok(operations)
+}
+
+// EPD is the first four FEN fields followed by semicolon terminated operations
+pub fn parse_epd(line: &str) -> Result<EpdRecord, String> {
+    let fields: Vec<&str> = line.trim().splitn(5, char::is_whitespace).collect();
+    if fields.len() < 4 {
+        return Err(format!("EPD '{}' has too few fields", line));
+    }
+    let game_state = parse_fen(&fields[..4].join(" "))?;
+    let operations = match fields.get(4) {
+        Some(rest) => {
+            // Some files keep the FEN move counters in front of the operations
+            let mut rest = rest.trim_start();
+            while let Some(token) = rest.split_whitespace().next() {
+                if token.parse::<u32>().is_err() {
+                    break;
+                }
+                rest = rest[token.len()..].trim_start();
+            }
+            parse_operations(rest)?
+        }
+        None => Vec::new(),
+    };
+    Ok(EpdRecord {
+        game_state,
+        operations,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moves::parse_coordinate;
+
+    #[test]
+    fn fen_round_trip() {
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "rnbqkbnr/pppp1ppp/8/8/3Pp3/8/PPP1PPPP/RNBQKBNR b Kq d3 0 3",
+            "8/5pk1/6p1/8/3B4/8/5PPP/6K1 b - - 12 40",
+        ] {
+            assert_eq!(to_fen(&parse_fen(fen).unwrap()), fen);
+        }
+    }
+
+    #[test]
+    fn castling_rights_need_king_and_rook() {
+        let game_state = parse_fen("r3k3/8/8/8/8/8/8/4K2R w KQkq - 0 1").unwrap();
+        assert_eq!(to_fen(&game_state), "r3k3/8/8/8/8/8/8/4K2R w Kq - 0 1");
+    }
+
+    #[test]
+    fn invalid_fens() {
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/7/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnx/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "Pnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkx - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e9 0 1",
+        ] {
+            assert!(parse_fen(fen).is_err(), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn epd_operations() {
+        let record = parse_epd(
+            "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - \
+             bm Bb5 Bc4; id \"test; one\"; c0 \"\";",
+        )
+        .unwrap();
+        assert_eq!(
+            to_fen(&record.game_state),
+            "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 0 1"
+        );
+        assert_eq!(record.operation("bm").unwrap(), &["Bb5", "Bc4"]);
+        assert_eq!(record.operation("id").unwrap(), &["test; one"]);
+        assert_eq!(record.operation("c0").unwrap(), &[""]);
+        assert!(record.operation("am").is_none());
+    }
+
+    #[test]
+    fn epd_with_move_counters() {
+        let record = parse_epd("4k3/8/8/8/8/8/8/4K3 b - - 0 1 c9 \"1/2-1/2\";").unwrap();
+        assert!(record.game_state.engine_black);
+        assert_eq!(record.operation("c9").unwrap(), &["1/2-1/2"]);
+        assert!(parse_epd("4k3/8/8/8/8/8/8/4K3 w - - id \"open;").is_err());
+        assert!(parse_epd("4k3/8/8/8/8/8/8/4K3 w -").is_err());
+    }
+
+    #[test]
+    fn parse_fen_needs_one_king_each() {
+        assert!(parse_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").is_ok());
+        for fen in [
+            "8/8/8/8/8/8/8/3KK3 w - - 0 1",
+            "3kk3/8/8/8/8/8/8/8 w - - 0 1",
+            "4k3/8/8/8/8/8/8/8 w - - 0 1",
+            "4k3/8/8/8/8/8/8/3KK3 w - - 0 1",
+        ] {
+            assert!(parse_fen(fen).is_err(), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn move_counters_carry_through_moves() {
+        let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 5 20";
+        let game_state = parse_fen(fen).unwrap();
+        assert_eq!(to_fen(&game_state), fen);
+
+        let mut game_state = game_state;
+        for (text, expected) in [
+            ("e2e4", "b KQkq e3 0 20"),
+            ("g8f6", "w KQkq - 1 21"),
+            ("g1f3", "b KQkq - 2 21"),
+            ("f6e4", "w KQkq - 0 22"),
+        ] {
+            let mov = parse_coordinate(text, &game_state, false).unwrap();
+            game_state = game_state.perform_move(&mov).flipped();
+            assert!(to_fen(&game_state).ends_with(expected), "{}", text);
+        }
+    }
+
+    #[test]
+    fn missing_move_counters_default() {
+        let game_state = parse_fen("4k3/8/8/8/8/8/8/4K3 b - -").unwrap();
+        assert_eq!(to_fen(&game_state), "4k3/8/8/8/8/8/8/4K3 b - - 0 1");
+        assert!(parse_fen("4k3/8/8/8/8/8/8/4K3 b - - x 1").is_err());
+        assert!(parse_fen("4k3/8/8/8/8/8/8/4K3 b - - 0 0").is_err());
+    }
+}
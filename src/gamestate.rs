@@ -1,10 +1,14 @@
 use crate::{
+    endgame::insufficient_material,
     moves::{Move, MoveType},
-    pieces::{Board, Piece, PieceClass, SCORE_BOUND, SCORE_RANGE},
+    pieces::{Board, Piece, PieceClass},
+    render::{render_board, RenderOptions},
+    see::is_attacked,
     settings::{BOARD_WIDTH, CASTLING, ENGINE_BLACK, LAYOUT, STANDARD_BOARD},
-    utils::{CastlingPossibilities, Vect, LETTERS},
+    utils::{CastlingPossibilities, Vect},
 };
 
+#[derive(Clone)]
 pub struct GameState {
     pub board: Board,
     pub score: i16,
@@ -12,45 +16,11 @@ pub struct GameState {
     pub engine_castling: CastlingPossibilities,
     pub opponent_castling: CastlingPossibilities,
     pub en_passant_midpoint: Option<Vect>,
-}
-
-fn print_board(board: &Board, direct: bool) {
-    println!();
-    for j in 0..BOARD_WIDTH {
-        let mut out = if direct {
-            String::new()
-        } else {
-            String::from(if ENGINE_BLACK { j + 1 } else { BOARD_WIDTH - j }.to_string())
-        };
-        let row = board[(BOARD_WIDTH - 1 - j) as usize];
-        for i in 0..BOARD_WIDTH {
-            let square = row[if ENGINE_BLACK && !direct {
-                BOARD_WIDTH - 1 - i
-            } else {
-                i
-            } as usize];
-            let char = match square {
-                Some(piece) => piece.repr(),
-                None => '.',
-            };
-            if !direct || i != 0 {
-                out += " ";
-            }
-            out += &format!("{}", char);
-        }
-        println!("{}", out);
-    }
-    if !direct {
-        let mut out = String::from(" ");
-        for i in 0..BOARD_WIDTH {
-            out += &format!(
-                " {}",
-                LETTERS[if ENGINE_BLACK { BOARD_WIDTH - i - 1 } else { i } as usize]
-            );
-        }
-        println!("{}", out);
-    }
-    println!();
+    pub engine_black: bool,
+    // Plies since the last capture or pawn move, and the number of the
+    // current move, as in FEN
+    pub halfmove_clock: u16,
+    pub fullmove_number: u16,
 }
 
 fn standard_move(
@@ -59,7 +29,7 @@ fn standard_move(
     to: &Vect,
     new_piece: Option<Piece>,
     castling: &mut CastlingPossibilities,
-) -> (i16, bool, bool) {
+) -> (i16, bool) {
     let start_piece = board[from.y as usize][from.x as usize];
     let end_piece = board[to.y as usize][to.x as usize];
     board[from.y as usize][from.x as usize] = None;
@@ -67,8 +37,6 @@ fn standard_move(
     let mut king_killed = false;
     let mut score_delta = 0;
 
-    let mut subtract = true;
-
     // Disable castling if piece moved
     match &start_piece {
         Some(piece) => match piece.class {
@@ -83,10 +51,6 @@ fn standard_move(
                     castling.kingside = false;
                 }
             }
-            PieceClass::Pawn => match &end_piece {
-                Some(_) => (),
-                None => subtract = false,
-            },
             _ => (),
         },
         None => {
@@ -95,20 +59,16 @@ fn standard_move(
     }
 
     // Find change in score and check if king was killed
-    match end_piece {
-        Some(piece) => {
-            if piece.enemy {
-                score_delta = piece.get_value();
-            } else {
-                score_delta = -piece.get_value();
-            }
+    if let Some(piece) = end_piece {
+        if piece.enemy {
+            score_delta = piece.get_value();
+        } else {
+            score_delta = -piece.get_value();
+        }
 
-            match piece.class {
-                PieceClass::King => king_killed = true,
-                _ => (),
-            }
+        if let PieceClass::King = piece.class {
+            king_killed = true;
         }
-        None => (),
     };
 
     // Handle promotion
@@ -125,95 +85,128 @@ fn standard_move(
     };
     board[to.y as usize][to.x as usize] = replacement_piece;
 
-    (score_delta, king_killed, subtract)
+    (score_delta, king_killed)
 }
 
 impl GameState {
-    pub fn get_normalized(&self) -> u64 {
-        let unclamped = self.score + SCORE_BOUND;
-        let clamped = u64::min(i16::max(0, unclamped) as u64, SCORE_RANGE - 1);
-        clamped
-    }
-
-    pub fn get_possible_moves(&self, enemy: bool) -> Vec<Move> {
-        let mut moves = Vec::new();
-
+    pub fn king_position(&self, enemy: bool) -> Option<Vect> {
         for x in 0..BOARD_WIDTH {
             for y in 0..BOARD_WIDTH {
-                let square = self.board[y as usize][x as usize];
-                match square {
-                    Some(piece) => {
-                        if piece.enemy == enemy {
-                            // Ignore defended positions and pass castling possibilities for given player
-                            let (mut new_moves, _) = piece.all_moves(
-                                &self.board,
-                                Vect { x, y },
-                                false,
-                                &self.en_passant_midpoint,
-                                if enemy {
-                                    &self.opponent_castling
-                                } else {
-                                    &self.engine_castling
-                                },
-                            );
-                            moves.append(&mut new_moves);
-                        }
+                if let Some(piece) = self.board[y as usize][x as usize] {
+                    if piece.enemy == enemy && piece.class == PieceClass::King {
+                        return Some(Vect { x, y });
                     }
-                    None => (),
                 }
             }
         }
-        moves
+        None
     }
 
-    pub fn get_defended_matrix(&self) -> [[f32; BOARD_WIDTH as usize]; BOARD_WIDTH as usize] {
-        let mut defended: Vec<Vect> = Vec::new();
-        let enemy = false;
+    pub fn in_check(&self, enemy: bool) -> bool {
+        match self.king_position(enemy) {
+            Some(king) => is_attacked(&self.board, king, !enemy),
+            None => true,
+        }
+    }
+
+    // Takes the state after the move, as callers have usually made it already
+    pub fn is_legal(&self, mov: &Move, next_state: &GameState) -> bool {
+        if let MoveType::Castling(queenside) = mov.move_type {
+            // The king can't castle out of or through check
+            let y = if mov.enemy { BOARD_WIDTH - 1 } else { 0 };
+            let passed = Vect {
+                x: if queenside { 3 } else { 5 },
+                y,
+            };
+            if self.in_check(mov.enemy) || is_attacked(&self.board, passed, !mov.enemy) {
+                return false;
+            }
+        }
+        !next_state.in_check(mov.enemy)
+    }
+
+    pub fn get_legal_moves(&self, enemy: bool) -> Vec<Move> {
+        self.get_possible_moves(enemy)
+            .into_iter()
+            .filter(|mov| self.is_legal(mov, &self.perform_move(mov)))
+            .collect()
+    }
+
+    // Result and reason if the game is over, the engine being to move
+    pub fn game_result(&self) -> Option<&'static str> {
+        if insufficient_material(self) {
+            return Some("1/2-1/2 {Insufficient material}");
+        }
+        if !self.get_legal_moves(false).is_empty() {
+            return None;
+        }
+        Some(match (self.in_check(false), self.engine_black) {
+            (true, true) => "1-0 {White mates}",
+            (true, false) => "0-1 {Black mates}",
+            (false, _) => "1/2-1/2 {Stalemate}",
+        })
+    }
+
+    pub fn get_possible_moves(&self, enemy: bool) -> Vec<Move> {
+        let mut moves = Vec::new();
 
         for x in 0..BOARD_WIDTH {
             for y in 0..BOARD_WIDTH {
                 let square = self.board[y as usize][x as usize];
-                match square {
-                    Some(piece) => {
-                        if piece.enemy == enemy {
-                            // Ignore defended positions and pass castling possibilities for given player
-                            let (_, mut new_defended) = piece.all_moves(
-                                &self.board,
-                                Vect { x, y },
-                                true,
-                                &self.en_passant_midpoint,
-                                if enemy {
-                                    &self.opponent_castling
-                                } else {
-                                    &self.engine_castling
-                                },
-                            );
-                            defended.append(&mut new_defended);
-                        }
+                if let Some(piece) = square {
+                    if piece.enemy == enemy {
+                        // Ignore defended positions and pass castling possibilities for given player
+                        let (mut new_moves, _) = piece.all_moves(
+                            &self.board,
+                            Vect { x, y },
+                            false,
+                            &self.en_passant_midpoint,
+                            if enemy {
+                                &self.opponent_castling
+                            } else {
+                                &self.engine_castling
+                            },
+                        );
+                        moves.append(&mut new_moves);
                     }
-                    None => (),
                 }
             }
         }
-
-        let mut matrix = [[0.; BOARD_WIDTH as usize]; BOARD_WIDTH as usize];
-
-        for pos in defended {
-            matrix[pos.y as usize][pos.x as usize] += 1.;
-        }
-
-        matrix
+        moves
     }
 
-    pub fn perform_move_subtract(&self, mov: &Move) -> (GameState, bool) {
-        // Subtract false if move is a pawn not attacking
-        let mut board = self.board.clone();
+    pub fn perform_move(&self, mov: &Move) -> GameState {
+        let mut board = self.board;
         let mut score = self.score;
         let mut kings_alive = self.kings_alive;
         let mut engine_castling = self.engine_castling;
         let mut opponent_castling = self.opponent_castling;
         let mut en_passant_midpoint = None;
 
+        // Pawn moves and captures reset the clock, and the move number goes
+        // up once black has moved
+        let resets_clock = match &mov.move_type {
+            MoveType::Standard(from, to) => {
+                let piece = self.board[from.y as usize][from.x as usize];
+                matches!(
+                    piece,
+                    Some(Piece {
+                        class: PieceClass::Pawn,
+                        ..
+                    })
+                ) || self.board[to.y as usize][to.x as usize].is_some()
+            }
+            MoveType::DoubleAdvance(..) | MoveType::EnPassant(..) | MoveType::Promotion(..) => true,
+            MoveType::Castling(_) | MoveType::Null => false,
+        };
+        let halfmove_clock = if resets_clock {
+            0
+        } else {
+            self.halfmove_clock.saturating_add(1)
+        };
+        let black_moved = mov.enemy != self.engine_black;
+        let fullmove_number = self.fullmove_number + black_moved as u16;
+
         // Get reference to castling possiblities for given player
         let castling = if mov.enemy {
             &mut opponent_castling
@@ -221,18 +214,15 @@ impl GameState {
             &mut engine_castling
         };
 
-        let subtract;
-
         match &mov.move_type {
-            MoveType::Standard(from, to, _) => {
-                let (score_delta, king_killed, sub) =
+            MoveType::Standard(from, to) => {
+                let (score_delta, king_killed) =
                     standard_move(&mut board, from, to, None, castling);
                 score += score_delta;
                 kings_alive = !king_killed && kings_alive;
-                subtract = sub;
             }
             MoveType::DoubleAdvance(from, to) => {
-                let (score_delta, king_killed, _) =
+                let (score_delta, king_killed) =
                     standard_move(&mut board, from, to, None, castling);
                 score += score_delta;
                 kings_alive = !king_killed && kings_alive;
@@ -242,26 +232,22 @@ impl GameState {
                     x: (from.x + to.x) / 2,
                     y: (from.y + to.y) / 2,
                 });
-
-                subtract = false;
             }
             MoveType::EnPassant(from, to, target) => {
                 // Simply move piece, score delta will always be 0 and kings won't be killed
                 standard_move(&mut board, from, to, None, castling);
                 score += 1;
                 board[target.y as usize][target.x as usize] = None;
-
-                subtract = true;
             }
-            MoveType::Promotion(from, to, piece, _) => {
-                let (score_delta, king_killed, sub) =
+            MoveType::Promotion(from, to, piece) => {
+                let (score_delta, king_killed) =
                     standard_move(&mut board, from, to, Some(*piece), castling);
                 score += score_delta;
                 kings_alive = !king_killed && kings_alive;
-                subtract = sub;
             }
             MoveType::Castling(queenside) => {
-                assert!(BOARD_WIDTH == 8, "Board with must be 8 for castling");
+                // Checked when compiling, as the ranks and files are fixed
+                const { assert!(BOARD_WIDTH == 8, "Board width must be 8 for castling") };
                 let y = if mov.enemy { 7 } else { 0 };
                 let rook_start = Vect {
                     x: if *queenside { 0 } else { 7 },
@@ -286,38 +272,55 @@ impl GameState {
                     engine_castling.kingside = false;
                     engine_castling.queenside = false;
                 }
-
-                subtract = false
-            }
-            MoveType::Null => {
-                subtract = true;
             }
+            MoveType::Null => (),
         }
 
-        (
-            Self {
-                board,
-                score,
-                kings_alive,
-                engine_castling,
-                opponent_castling,
-                en_passant_midpoint,
-            },
-            subtract,
-        )
-    }
-
-    pub fn perform_move(&self, mov: &Move) -> GameState {
-        let (game_state, _) = self.perform_move_subtract(mov);
-        game_state
+        Self {
+            board,
+            score,
+            kings_alive,
+            engine_castling,
+            opponent_castling,
+            en_passant_midpoint,
+            engine_black: self.engine_black,
+            halfmove_clock,
+            fullmove_number,
+        }
     }
 
-    pub fn print(&self) {
-        print_board(&self.board, false);
+    // Same position with the other side as the engine, used to hand the move
+    // over when the engine isn't always the same colour
+    pub fn flipped(&self) -> GameState {
+        let mut board: Board = [[None; BOARD_WIDTH as usize]; BOARD_WIDTH as usize];
+        for (y, row) in self.board.iter().enumerate() {
+            board[BOARD_WIDTH as usize - 1 - y] = row.map(|square| {
+                square.map(|piece| Piece {
+                    enemy: !piece.enemy,
+                    ..piece
+                })
+            });
+        }
+        GameState {
+            board,
+            score: -self.score,
+            kings_alive: self.kings_alive,
+            engine_castling: self.opponent_castling,
+            opponent_castling: self.engine_castling,
+            en_passant_midpoint: self.en_passant_midpoint.map(|pos| Vect {
+                x: pos.x,
+                y: BOARD_WIDTH - 1 - pos.y,
+            }),
+            engine_black: !self.engine_black,
+            halfmove_clock: self.halfmove_clock,
+            fullmove_number: self.fullmove_number,
+        }
     }
 
-    pub fn print_direct(&self) {
-        print_board(&self.board, true);
+    pub fn print(&self, options: &RenderOptions) {
+        println!();
+        print!("{}", render_board(self, options));
+        println!();
     }
 }
 
@@ -334,7 +337,6 @@ r n b q k b n r
 
 pub fn parse_layout() -> GameState {
     let mut board: Board = [[None; BOARD_WIDTH as usize]; BOARD_WIDTH as usize];
-    let mut score = 0;
 
     let _layout_string = if STANDARD_BOARD {
         DEFAULT_LAYOUT
@@ -357,29 +359,18 @@ pub fn parse_layout() -> GameState {
                 None
             } else {
                 let enemy = char.is_ascii_uppercase();
-                let class = match char.to_ascii_lowercase() {
-                    'p' => PieceClass::Pawn,
-                    'b' => PieceClass::Bishop,
-                    'n' => PieceClass::Knight,
-                    'r' => PieceClass::Rook,
-                    'q' => PieceClass::Queen,
-                    'k' => PieceClass::King,
-                    _ => panic!("'{}' is not a valid piece", char),
+                let class = match PieceClass::from_char(char) {
+                    Some(class) => class,
+                    None => panic!("'{}' is not a valid piece", char),
                 };
-                let piece = Piece { enemy, class };
-                if enemy {
-                    score -= piece.get_value();
-                } else {
-                    score += piece.get_value();
-                }
-                Some(piece)
+                Some(Piece { enemy, class })
             };
             board[BOARD_WIDTH as usize - 1 - j][i as usize] = square;
         }
     }
     GameState {
         board,
-        score,
+        score: material_score(&board),
         kings_alive: true,
         engine_castling: CastlingPossibilities {
             queenside: CASTLING,
@@ -390,5 +381,20 @@ pub fn parse_layout() -> GameState {
             kingside: CASTLING,
         },
         en_passant_midpoint: None,
+        engine_black: ENGINE_BLACK,
+        halfmove_clock: 0,
+        fullmove_number: 1,
+    }
+}
+
+pub fn material_score(board: &Board) -> i16 {
+    let mut score = 0;
+    for piece in board.iter().flatten().flatten() {
+        if piece.enemy {
+            score -= piece.get_value();
+        } else {
+            score += piece.get_value();
+        }
     }
+    score
 }
@@ -0,0 +1,295 @@
+use crate::{
+    fen::to_fen,
+    gamestate::GameState,
+    moves::Move,
+    pgn::{parse_pgn, write_pgn, PgnGame, START_FEN},
+    san::{parse_san, to_san},
+    simulation::{pv_repr, score_repr, AnalysisResult},
+};
+
+pub struct HistoryEntry {
+    // Position after the move, with the side to move as the engine
+    pub game_state: GameState,
+    pub mov: Move,
+    pub san: String,
+    // The engine's search, for moves it chose
+    pub analysis: Option<AnalysisResult>,
+}
+
+impl HistoryEntry {
+    // Engine's score, depth and expected continuation for the move
+    pub fn analysis_repr(&self) -> Option<String> {
+        let analysis = self.analysis.as_ref()?;
+        // The side that moved, which made the search
+        let engine_black = !self.game_state.engine_black;
+        let score = analysis.move_scores.iter().find(|s| s.mov == self.mov)?;
+        Some(format!(
+            "{} depth {}: {}",
+            score_repr(score.score),
+            analysis.depth,
+            pv_repr(&score.pv, engine_black)
+        ))
+    }
+}
+
+// Moves played in the interactive game. Undone moves are kept until a
+// different move is played, so they can be redone
+pub struct History {
+    start: GameState,
+    entries: Vec<HistoryEntry>,
+    ply: usize,
+}
+
+impl History {
+    pub fn new(start: GameState) -> Self {
+        Self {
+            start,
+            entries: Vec::new(),
+            ply: 0,
+        }
+    }
+
+    // First game in the text, replayed up to any move that can't be played,
+    // which is returned with the history
+    pub fn from_pgn(text: &str) -> Result<(Self, Option<String>), String> {
+        let game = parse_pgn(text).into_iter().next().ok_or("No game found")?;
+        Self::from_game(&game)
+    }
+
+    // As above for a game already parsed
+    pub fn from_game(game: &PgnGame) -> Result<(Self, Option<String>), String> {
+        let mut history = Self::new(game.start_position()?);
+        for san in &game.moves {
+            match parse_san(san, &history.current(), false) {
+                Some(mov) => history.push(mov, None),
+                None => return Ok((history, Some(san.clone()))),
+            }
+        }
+        Ok((history, None))
+    }
+
+    // Including moves undone but not yet replaced
+    pub fn entries(&self) -> &[HistoryEntry] {
+        &self.entries
+    }
+
+    // Moves played to reach the current position
+    pub fn ply(&self) -> usize {
+        self.ply
+    }
+
+    pub fn start(&self) -> &GameState {
+        &self.start
+    }
+
+    pub fn current(&self) -> GameState {
+        match self.last_entry() {
+            Some(entry) => entry.game_state.clone(),
+            None => self.start.clone(),
+        }
+    }
+
+    // Move that led to the current position
+    pub fn last_entry(&self) -> Option<&HistoryEntry> {
+        self.ply.checked_sub(1).map(|i| &self.entries[i])
+    }
+
+    // Plays a move from the current position, dropping any undone moves
+    pub fn push(&mut self, mov: Move, analysis: Option<AnalysisResult>) {
+        let before = self.current();
+        let san = to_san(&before, &mov);
+        self.entries.truncate(self.ply);
+        self.entries.push(HistoryEntry {
+            game_state: before.perform_move(&mov).flipped(),
+            mov,
+            san,
+            analysis,
+        });
+        self.ply += 1;
+    }
+
+    pub fn undo(&mut self) -> bool {
+        self.goto(self.ply.wrapping_sub(1))
+    }
+
+    pub fn redo(&mut self) -> bool {
+        self.goto(self.ply + 1)
+    }
+
+    // Between 0, the start position, and the last move played or undone
+    pub fn goto(&mut self, ply: usize) -> bool {
+        if ply > self.entries.len() {
+            return false;
+        }
+        self.ply = ply;
+        true
+    }
+
+    // Move number and dots for the move at the given index, white first
+    pub fn move_number(&self, index: usize) -> String {
+        let plies = index + self.start.engine_black as usize;
+        let number = plies / 2 + self.start.fullmove_number as usize;
+        if plies.is_multiple_of(2) {
+            format!("{}.", number)
+        } else {
+            format!("{}...", number)
+        }
+    }
+
+    pub fn print(&self) {
+        if self.entries.is_empty() {
+            println!("No moves played");
+            return;
+        }
+        for (i, entry) in self.entries.iter().enumerate() {
+            let mut line = format!("{:>4}  {:<7}{:<8}", i + 1, self.move_number(i), entry.san);
+            if let Some(analysis) = entry.analysis_repr() {
+                line += &format!(" engine {}", analysis);
+            }
+            if i >= self.ply {
+                line += " (undone)";
+            }
+            println!("{}", line.trim_end());
+        }
+        println!("At ply {} of {}", self.ply, self.entries.len());
+    }
+
+    // Moves played to reach the current position as PGN movetext, with a
+    // number before every white move and before black's if it comes first
+    pub fn movetext(&self) -> String {
+        let mut tokens = Vec::new();
+        for (i, entry) in self.entries[..self.ply].iter().enumerate() {
+            let number = self.move_number(i);
+            if i == 0 || !number.ends_with("...") {
+                tokens.push(number);
+            }
+            tokens.push(entry.san.clone());
+        }
+        tokens.join(" ")
+    }
+
+    // The moves played so far, with the result if the game is over
+    // The result is taken from the position unless one is given, as when a
+    // flag falls. Extra tags such as TimeControl go after the standard ones
+    pub fn to_pgn(
+        &self,
+        engine_black: bool,
+        result: Option<&str>,
+        extra_tags: Vec<(&str, String)>,
+    ) -> String {
+        let result = result
+            .or_else(|| self.current().game_result())
+            .and_then(|result| result.split_whitespace().next())
+            .unwrap_or("*");
+        let player = |black: bool| {
+            if black == engine_black {
+                String::from("rust-chess-engine")
+            } else {
+                String::from("Player")
+            }
+        };
+        let mut tags = vec![
+            ("Event", String::from("Interactive game")),
+            ("Site", String::from("?")),
+            ("Date", String::from("????.??.??")),
+            ("Round", String::from("-")),
+            ("White", player(false)),
+            ("Black", player(true)),
+            ("Result", String::from(result)),
+        ];
+        let start_fen = to_fen(&self.start);
+        if start_fen != START_FEN {
+            tags.push(("SetUp", String::from("1")));
+            tags.push(("FEN", start_fen));
+        }
+        tags.extend(extra_tags);
+        write_pgn(&tags, &self.movetext(), result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fen::parse_fen;
+
+    fn history(fen: &str, sans: &[&str]) -> History {
+        let mut history = History::new(parse_fen(fen).unwrap());
+        for san in sans {
+            play(&mut history, san);
+        }
+        history
+    }
+
+    fn play(history: &mut History, san: &str) {
+        let mov = parse_san(san, &history.current(), false).unwrap();
+        history.push(mov, None);
+    }
+
+    fn sans(history: &History) -> Vec<&str> {
+        history
+            .entries()
+            .iter()
+            .map(|entry| entry.san.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        let mut history = history(START_FEN, &["e4", "e5", "Nf3"]);
+        assert!(!history.redo());
+        assert_eq!(history.ply(), 3);
+        assert!(history.undo());
+        assert!(history.undo());
+        assert_eq!(history.ply(), 1);
+        assert_eq!(history.last_entry().unwrap().san, "e4");
+        assert!(history.undo());
+        assert!(!history.undo());
+        assert_eq!(history.ply(), 0);
+        assert!(history.last_entry().is_none());
+        assert_eq!(to_fen(&history.current()), START_FEN);
+        assert!(history.redo());
+        assert_eq!(history.ply(), 1);
+        assert_eq!(sans(&history), ["e4", "e5", "Nf3"]);
+    }
+
+    #[test]
+    fn goto_ply() {
+        let mut history = history(START_FEN, &["e4", "e5", "Nf3"]);
+        assert!(history.goto(0));
+        assert!(history.goto(3));
+        assert!(!history.goto(4));
+        assert_eq!(history.ply(), 3);
+        assert!(history.goto(2));
+        assert_eq!(
+            to_fen(&history.current()),
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2"
+        );
+    }
+
+    #[test]
+    fn moving_after_undo_drops_the_rest() {
+        let mut history = history(START_FEN, &["e4", "e5", "Nf3"]);
+        history.goto(1);
+        play(&mut history, "c5");
+        assert_eq!(sans(&history), ["e4", "c5"]);
+        assert_eq!(history.ply(), 2);
+        assert!(!history.redo());
+        assert_eq!(history.movetext(), "1. e4 c5");
+    }
+
+    #[test]
+    fn movetext_from_black() {
+        let history = history(
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 7",
+            &["e5", "Nf3", "Nc6"],
+        );
+        assert_eq!(history.move_number(0), "7...");
+        assert_eq!(history.move_number(1), "8.");
+        assert_eq!(history.movetext(), "7... e5 8. Nf3 Nc6");
+        let pgn = history.to_pgn(true, None, Vec::new());
+        assert!(pgn.contains("[SetUp \"1\"]"));
+        let (replayed, stopped) = History::from_pgn(&pgn).unwrap();
+        assert!(stopped.is_none());
+        assert_eq!(replayed.movetext(), history.movetext());
+    }
+}
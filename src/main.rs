@@ -1,111 +1,407 @@
 #![allow(unused)]
 
+mod annotate;
+mod bench;
+mod book;
+mod clock;
 mod development;
+mod endgame;
+mod epd;
+mod evaluation;
+mod explain;
+mod fen;
 mod gamestate;
+mod history;
+mod mate;
 mod moves;
+mod pgn;
 mod pieces;
+mod polyglot;
+mod ponder;
+mod render;
+mod repl;
+mod rng;
+mod san;
+mod see;
+mod serve;
 mod settings;
 mod simulation;
+mod skill;
+mod syzygy;
+mod transposition;
+mod tui;
+mod tuning;
+mod uci;
 mod utils;
+mod xboard;
+mod zobrist;
 
-use std::time::Instant;
+use std::{env, path::Path};
 
+use annotate::{annotate, DEFAULT_ANNOTATE_DEPTH};
+use bench::bench;
+use book::{build_book, set_book, Book, BookWeight, BuildOptions};
+use epd::{run_epd, DEFAULT_EPD_DEPTH};
+use evaluation::{set_params, EvalParams};
+use explain::{explain, print_eval, print_lines};
+use fen::parse_fen;
 use gamestate::{parse_layout, GameState};
-use moves::{input_move, Move};
-use settings::GAME_LOOP;
-use simulation::analyse;
-use utils::input;
+use mate::{mate, mate_file};
+use render::set_board_style;
+use repl::play;
+use serve::serve;
+use settings::{BOOK_FILE, PARAMS_FILE, SYZYGY_PATH};
+use simulation::{SearchLimits, SearchOptions};
+use skill::{Skill, MAX_SKILL};
+use syzygy::{set_tablebases, Tablebases};
+use tui::tui;
+use tuning::tune;
+use uci::uci;
+use xboard::xboard;
 
-use crate::development::find_best_development;
-
-fn find_best_move(game_state: &GameState, recurision_depth: u8) -> Option<Move> {
-    game_state.print_direct();
-
-    let timer = Instant::now();
-    let analysis = analyse(&game_state, recurision_depth, true);
-
-    let moves = analysis.best_moves.unwrap();
-
-    /*for mov in moves.iter() {
-        assert!(!mov.enemy);
-        println!("{}", mov.repr());
-    }*/
-
-    if analysis.end_score > 250 {
-        println!("Checkmate found!");
-    } else if analysis.engine_no_moves {
-        println!("No moves found, game over?");
-        return None;
+fn load_params() {
+    if !Path::new(PARAMS_FILE).exists() {
+        return;
+    }
+    match EvalParams::load(PARAMS_FILE) {
+        Ok(params) => {
+            println!("Loaded evaluation weights from {}", PARAMS_FILE);
+            set_params(params);
+        }
+        Err(e) => println!("Ignoring {}: {}", PARAMS_FILE, e),
     }
-    println!(
-        "Best score delta: {}",
-        analysis.end_score - game_state.score
-    );
+}
 
-    println!("Analysis found {} moves", moves.len());
+// An explicit path must load, the default file is only used if present
+fn load_book(path: Option<&str>, best_only: bool) {
+    let path = match path {
+        Some(path) => path,
+        None if Path::new(BOOK_FILE).exists() => BOOK_FILE,
+        None => return,
+    };
+    match Book::load(path) {
+        Ok(mut book) => {
+            println!("Loaded {} book entries from {}", book.entry_count(), path);
+            book.best_only = best_only;
+            set_book(book);
+        }
+        Err(e) => println!("Ignoring book: {}", e),
+    }
+}
 
-    let best_move = find_best_development(game_state, moves);
+// Same as the book, an explicit directory must open
+fn load_tablebases(path: Option<&str>) {
+    let path = match path {
+        Some(path) => path,
+        None if Path::new(SYZYGY_PATH).is_dir() => SYZYGY_PATH,
+        None => return,
+    };
+    match Tablebases::open(path) {
+        Ok(tablebases) => {
+            println!(
+                "Found {} tablebase files in {}, up to {} pieces",
+                tablebases.table_count(),
+                path,
+                tablebases.max_pieces
+            );
+            set_tablebases(tablebases);
+        }
+        Err(e) => println!("Ignoring tablebases: {}", e),
+    }
+}
 
-    game_state.perform_move(&best_move).print();
+fn load_position(fen: Option<&str>) -> Option<GameState> {
+    match fen {
+        Some(fen) => match parse_fen(fen) {
+            Ok(game_state) => Some(game_state),
+            Err(e) => {
+                println!("Invalid FEN: {}", e);
+                None
+            }
+        },
+        None => Some(parse_layout()),
+    }
+}
 
-    println!(
-        "{} selected from {} valid moves.",
-        best_move.repr(),
-        analysis.valid_moves
-    );
-    println!(
-        "Simulated {} moves, took {} seconds or {} ms",
-        analysis.sim_moves,
-        timer.elapsed().as_secs(),
-        timer.elapsed().as_millis()
-    );
-    Some(best_move)
+// Search options may appear anywhere, the remaining arguments are returned
+fn parse_options<'a>(args: &[&'a str]) -> Result<(SearchOptions, Vec<&'a str>), String> {
+    let mut options = SearchOptions::default();
+    let mut rest = Vec::new();
+    let mut iter = args.iter();
+    while let Some(&arg) = iter.next() {
+        match arg {
+            "--no-null-move" => options.null_move = false,
+            "--no-lmr" => options.late_move_reductions = false,
+            "--no-futility" => options.futility = false,
+            "--threads" => {
+                options.threads = match iter.next().map(|n| n.parse::<usize>()) {
+                    Some(Ok(n)) if n > 0 => n,
+                    _ => return Err(String::from("--threads needs a positive int")),
+                }
+            }
+            "--multipv" => {
+                options.multi_pv = match iter.next().map(|n| n.parse::<usize>()) {
+                    Some(Ok(n)) if n > 0 => n,
+                    _ => return Err(String::from("--multipv needs a positive int")),
+                }
+            }
+            "--skill" => {
+                options.skill = match iter.next().and_then(|n| n.parse::<u8>().ok()) {
+                    Some(level) => Skill::new(level)
+                        .ok_or_else(|| format!("--skill must be 0 to {}", MAX_SKILL))?,
+                    None => return Err(format!("--skill needs an int from 0 to {}", MAX_SKILL)),
+                }
+            }
+            "--seed" => {
+                options.seed = match iter.next().map(|n| n.parse::<u64>()) {
+                    Some(Ok(seed)) => Some(seed),
+                    _ => return Err(String::from("--seed needs a non-negative int")),
+                }
+            }
+            "--elo" => {
+                options.skill = match iter.next().map(|n| n.parse::<u32>()) {
+                    Some(Ok(elo)) => Skill::from_elo(elo),
+                    _ => return Err(String::from("--elo needs a positive int")),
+                }
+            }
+            _ => rest.push(arg),
+        }
+    }
+    Ok((options, rest))
 }
 
-fn get_recursion_depth() -> u8 {
-    loop {
-        let res = input("Recursion depth: ").parse::<u8>();
-        match res {
-            Ok(depth) => return depth,
-            Err(_) => {
-                println!("Must be non-negative int");
-                continue;
+// Options for `book build`, the remaining arguments are returned
+fn parse_build_options<'a>(args: &[&'a str]) -> Result<(BuildOptions, Vec<&'a str>), String> {
+    let mut options = BuildOptions::default();
+    let mut rest = Vec::new();
+    let mut iter = args.iter();
+    while let Some(&arg) = iter.next() {
+        match arg {
+            "--max-ply" => {
+                options.max_ply = match iter.next().map(|n| n.parse::<usize>()) {
+                    Some(Ok(n)) => n,
+                    _ => return Err(String::from("--max-ply needs a non-negative int")),
+                }
+            }
+            "--min-games" => {
+                options.min_games = match iter.next().map(|n| n.parse::<u32>()) {
+                    Some(Ok(n)) => n,
+                    _ => return Err(String::from("--min-games needs a non-negative int")),
+                }
+            }
+            "--weight" => {
+                options.weight = match iter.next() {
+                    Some(&"results") => BookWeight::Results,
+                    Some(&"games") => BookWeight::Games,
+                    _ => return Err(String::from("--weight must be results or games")),
+                }
             }
-        };
+            "--side" => {
+                (options.white, options.black) = match iter.next() {
+                    Some(&"white") => (true, false),
+                    Some(&"black") => (false, true),
+                    Some(&"both") => (true, true),
+                    _ => return Err(String::from("--side must be white, black or both")),
+                }
+            }
+            _ => rest.push(arg),
+        }
     }
+    Ok((options, rest))
 }
 
 fn main() {
-    let mut game_state = parse_layout();
+    load_params();
 
-    if !GAME_LOOP {
-        let depth = get_recursion_depth();
-        loop {
-            let mov_option = find_best_move(&game_state, depth);
-            input("Press enter to continue");
-            game_state = game_state.perform_move(&mov_option.unwrap());
+    let args: Vec<String> = env::args().collect();
+    let args: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    let (options, mut args) = match parse_options(&args) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            println!("{}", e);
+            return;
+        }
+    };
+    // Mate solving only tries checking moves unless told otherwise
+    let checks_only = !args.contains(&"--all-moves");
+    let no_book = args.contains(&"--no-book");
+    let book_best = args.contains(&"--book-best");
+    set_board_style(args.contains(&"--ascii"));
+    let book_path = match args.iter().position(|arg| *arg == "--book") {
+        Some(i) if i + 1 < args.len() => Some(args.remove(i + 1)),
+        Some(_) => {
+            println!("--book needs a path");
+            return;
         }
+        None => None,
+    };
+    let syzygy_path = match args.iter().position(|arg| *arg == "--syzygy") {
+        Some(i) if i + 1 < args.len() => Some(args.remove(i + 1)),
+        Some(_) => {
+            println!("--syzygy needs a directory");
+            return;
+        }
+        None => None,
+    };
+    args.retain(|arg| {
+        ![
+            "--all-moves",
+            "--no-book",
+            "--book-best",
+            "--book",
+            "--syzygy",
+            "--ascii",
+        ]
+        .contains(arg)
+    });
+    if !no_book {
+        load_book(book_path, book_best);
     }
-
-    loop {
-        let opponent_move = input_move("opponent", &game_state, true);
-        game_state = game_state.perform_move(&opponent_move);
-
-        let depth = get_recursion_depth();
-        let mov_option = find_best_move(&game_state, depth);
-        let engine_move = match mov_option {
-            Some(mov) => {
-                if input("Accept this move? y/n: ") == "n" {
-                    input_move("engine", &game_state, false)
-                } else {
-                    mov
+    load_tablebases(syzygy_path);
+    match args[1..] {
+        [] => play(&options),
+        ["tui"] => tui(&options),
+        ["tune", data] => tune(data, PARAMS_FILE),
+        ["tune", data, out] => tune(data, out),
+        ["eval"] | ["eval", _] => {
+            if let Some(game_state) = load_position(args.get(2).copied()) {
+                print_eval(&game_state);
+            }
+        }
+        ["explain", depth, mov] | ["explain", depth, mov, _] => {
+            let depth = match depth.parse::<u8>() {
+                Ok(depth) => depth,
+                Err(_) => {
+                    println!("Depth must be non-negative int");
+                    return;
                 }
+            };
+            if let Some(game_state) = load_position(args.get(4).copied()) {
+                explain(&game_state, depth, mov, &options);
+            }
+        }
+        ["analyse", depth] | ["analyse", depth, _] => {
+            let depth = match depth.parse::<u8>() {
+                Ok(depth) => depth,
+                Err(_) => {
+                    println!("Depth must be non-negative int");
+                    return;
+                }
+            };
+            if let Some(game_state) = load_position(args.get(3).copied()) {
+                print_lines(&game_state, depth, &options);
+            }
+        }
+        ["mate", moves, fen] => match moves.parse::<u8>() {
+            Ok(moves) => mate(fen, moves, checks_only),
+            Err(_) => println!("Moves must be non-negative int"),
+        },
+        ["mate-file", path] => mate_file(path, None, checks_only),
+        ["mate-file", path, moves] => match moves.parse::<u8>() {
+            Ok(moves) => mate_file(path, Some(moves), checks_only),
+            Err(_) => println!("Moves must be non-negative int"),
+        },
+        ["epd", path, ref rest @ ..] => {
+            let machine = rest.contains(&"--machine");
+            let limit: Vec<&str> = rest.iter().copied().filter(|a| *a != "--machine").collect();
+            let limits = if limit.is_empty() {
+                Some(SearchLimits::depth(DEFAULT_EPD_DEPTH))
+            } else {
+                SearchLimits::parse(&limit.join(" "))
+            };
+            match limits {
+                Some(limits) => run_epd(path, &limits, &options, machine),
+                None => println!("Limit must be a depth, or depth/nodes/movetime followed by one"),
             }
-            None => input_move("engine", &game_state, false),
-        };
-        game_state = game_state.perform_move(&engine_move);
-        /*for mov in game_state.get_possible_moves(true) {
-            println!("{}", mov.repr());
-        }*/
+        }
+        ["annotate", path, out, ref limit @ ..] => {
+            let limits = if limit.is_empty() {
+                Some(SearchLimits::depth(DEFAULT_ANNOTATE_DEPTH))
+            } else {
+                SearchLimits::parse(&limit.join(" "))
+            };
+            match limits {
+                Some(limits) => annotate(path, out, &limits, &options),
+                None => println!("Limit must be a depth, or depth/nodes/movetime followed by one"),
+            }
+        }
+        ["bench"] | ["bench", _] => {
+            let depth = match args.get(2).map_or(Ok(4), |d| d.parse::<u8>()) {
+                Ok(depth) => depth,
+                Err(_) => {
+                    println!("Depth must be non-negative int");
+                    return;
+                }
+            };
+            bench(depth, &options);
+        }
+        ["book", "build", out, ref rest @ ..] => match parse_build_options(rest) {
+            Ok((_, pgn_paths)) if pgn_paths.is_empty() => println!("No PGN files given"),
+            Ok((build_options, pgn_paths)) => build_book(&pgn_paths, out, &build_options),
+            Err(e) => println!("{}", e),
+        },
+        ["xboard"] => xboard(&options),
+        ["uci"] => uci(&options),
+        ["serve"] | ["serve", _] => match args.get(2).map_or(Ok(8080), |p| p.parse::<u16>()) {
+            Ok(port) => serve(port, &options),
+            Err(_) => println!("Port must be an int below 65536"),
+        },
+        _ => {
+            println!("Usage:");
+            println!("  rust-chess-engine                       Play a game");
+            println!("  rust-chess-engine tui                   Play or analyse full screen");
+            println!("  rust-chess-engine tune <epd|pgn> [<out>]");
+            println!("                                          Tune evaluation weights");
+            println!("  rust-chess-engine eval [<fen>]          Break down the static evaluation");
+            println!("  rust-chess-engine explain <depth> <move> [<fen>]");
+            println!(
+                "                                          Compare a move with the alternatives"
+            );
+            println!("  rust-chess-engine analyse <depth> [<fen>]");
+            println!(
+                "                                          Show the best lines, see --multipv"
+            );
+            println!("  rust-chess-engine mate <moves> <fen>    Solve a mate in n problem");
+            println!("  rust-chess-engine mate-file <epd> [<moves>]");
+            println!("                                          Solve problems with dm operations");
+            println!("  rust-chess-engine epd <epd> [<limit>] [--machine]");
+            println!(
+                "                                          Run a test suite with bm/am operations"
+            );
+            println!("  rust-chess-engine annotate <pgn> <out> [<limit>]");
+            println!(
+                "                                          Mark mistakes and write them as PGN"
+            );
+            println!("  rust-chess-engine bench [<depth>]       Time a fixed set of searches");
+            println!(
+                "  rust-chess-engine book build <bin> <pgn>... [--max-ply <n>] [--min-games <n>]"
+            );
+            println!("                    [--weight results|games] [--side white|black|both]");
+            println!("                                          Build a Polyglot book from games");
+            println!("  rust-chess-engine xboard                Talk to XBoard/WinBoard over CECP");
+            println!("  rust-chess-engine uci                   Talk to a UCI GUI");
+            println!("  rust-chess-engine serve [<port>]        Serve the HTTP API on localhost");
+            println!("Search options:");
+            println!("  --no-null-move  --no-lmr  --no-futility  --threads <n>  --multipv <n>");
+            println!("  --skill <0-20>  Play weaker moves, 20 is full strength, or --elo <rating>");
+            println!("  --seed <n>      Seed for the book, development and skill choices");
+            println!("  --all-moves     Let the mate solver try quiet moves as well as checks");
+            println!("Opening book options:");
+            println!(
+                "  --book <bin>    Polyglot book to use instead of {}",
+                BOOK_FILE
+            );
+            println!("  --no-book       Never play book moves");
+            println!("  --book-best     Play the highest weighted book move instead of by weight");
+            println!("Display options:");
+            println!(
+                "  --ascii         Print boards as letters, as when not writing to a terminal"
+            );
+            println!("Endgame tablebase options:");
+            println!(
+                "  --syzygy <dir>  Syzygy tables to use instead of those in {}",
+                SYZYGY_PATH
+            );
+        }
     }
 }
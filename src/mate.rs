@@ -0,0 +1,263 @@
+use std::{collections::HashSet, fs, time::Instant};
+
+use crate::{
+    fen::{parse_epd, parse_fen},
+    gamestate::GameState,
+    moves::Move,
+    render::RenderOptions,
+    see::is_capture,
+    simulation::pv_repr,
+    zobrist::hash,
+};
+
+struct Solver {
+    checks_only: bool,
+    // Attacker positions already shown to have no mate in the given moves
+    refuted: HashSet<(u64, u8)>,
+    nodes: u64,
+}
+
+impl Solver {
+    // Mating line for the engine within the given moves, with the longest
+    // defence, or None if there isn't one
+    fn attack(&mut self, game_state: &GameState, moves: u8) -> Option<Vec<Move>> {
+        let key = (hash(game_state, false), moves);
+        if self.refuted.contains(&key) {
+            return None;
+        }
+
+        // Checks first, then captures
+        let mut candidates = Vec::new();
+        for mov in game_state.get_possible_moves(false) {
+            let next_state = game_state.perform_move(&mov);
+            self.nodes += 1;
+            if !game_state.is_legal(&mov, &next_state) {
+                continue;
+            }
+            let check = next_state.in_check(true);
+            // The mating move always gives check
+            if !check && (self.checks_only || moves == 1) {
+                continue;
+            }
+            let order = match (check, is_capture(game_state, &mov)) {
+                (true, _) => 0,
+                (false, true) => 1,
+                (false, false) => 2,
+            };
+            candidates.push((order, mov, next_state));
+        }
+        candidates.sort_by_key(|(order, _, _)| *order);
+
+        for (_, mov, next_state) in candidates {
+            if let Some(line) = self.defend(&next_state, moves) {
+                let mut mating_line = vec![mov];
+                mating_line.extend(line);
+                return Some(mating_line);
+            }
+        }
+        self.refuted.insert(key);
+        None
+    }
+
+    // Every defence must still lose, the one lasting longest is returned
+    fn defend(&mut self, game_state: &GameState, moves: u8) -> Option<Vec<Move>> {
+        let replies = game_state.get_legal_moves(true);
+        self.nodes += replies.len() as u64;
+        if replies.is_empty() {
+            // Stalemate isn't a win
+            return if game_state.in_check(true) {
+                Some(Vec::new())
+            } else {
+                None
+            };
+        }
+        if moves == 1 {
+            return None;
+        }
+
+        let mut longest: Option<Vec<Move>> = None;
+        for reply in replies {
+            let line = self.attack(&game_state.perform_move(&reply), moves - 1)?;
+            if longest.as_ref().is_none_or(|l| line.len() + 1 > l.len()) {
+                let mut defence = vec![reply];
+                defence.extend(line);
+                longest = Some(defence);
+            }
+        }
+        longest
+    }
+}
+
+// Shortest forced mate for the side to move in at most the given moves,
+// returning the line and the moves simulated
+pub fn solve_mate(
+    game_state: &GameState,
+    moves: u8,
+    checks_only: bool,
+) -> (Option<Vec<Move>>, u64) {
+    let mut solver = Solver {
+        checks_only,
+        refuted: HashSet::new(),
+        nodes: 0,
+    };
+    for n in 1..=moves {
+        if let Some(line) = solver.attack(game_state, n) {
+            return (Some(line), solver.nodes);
+        }
+    }
+    (None, solver.nodes)
+}
+
+fn report(game_state: &GameState, moves: u8, checks_only: bool) -> bool {
+    let timer = Instant::now();
+    let (line, nodes) = solve_mate(game_state, moves, checks_only);
+    let found = match line {
+        Some(line) => {
+            println!(
+                "Mate in {}: {}",
+                line.len().div_ceil(2),
+                pv_repr(&line, game_state.engine_black)
+            );
+            true
+        }
+        None if checks_only => {
+            println!("No mate in {} by checks alone", moves);
+            false
+        }
+        None => {
+            println!("No mate in {}", moves);
+            false
+        }
+    };
+    println!(
+        "Simulated {} moves in {} ms",
+        nodes,
+        timer.elapsed().as_millis()
+    );
+    found
+}
+
+pub fn mate(fen: &str, moves: u8, checks_only: bool) {
+    match parse_fen(fen) {
+        Ok(game_state) => {
+            game_state.print(&RenderOptions::new(game_state.engine_black));
+            report(&game_state, moves, checks_only);
+        }
+        Err(e) => println!("Invalid FEN: {}", e),
+    }
+}
+
+// EPD file with a "dm" operation giving the moves to mate, unless overridden
+pub fn mate_file(path: &str, moves: Option<u8>, checks_only: bool) {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => {
+            println!("Couldn't read {}: {}", path, e);
+            return;
+        }
+    };
+
+    let (mut solved, mut total) = (0, 0);
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let record = match parse_epd(line) {
+            Ok(record) => record,
+            Err(e) => {
+                println!("Line {}: {}", i + 1, e);
+                continue;
+            }
+        };
+        let problem_moves = match moves.or_else(|| {
+            record
+                .operation("dm")
+                .and_then(|ops| ops.first())
+                .and_then(|n| n.parse().ok())
+        }) {
+            Some(n) => n,
+            None => {
+                println!("Line {}: no dm operation and no mate length given", i + 1);
+                continue;
+            }
+        };
+        let id = record
+            .operation("id")
+            .and_then(|ops| ops.first())
+            .cloned()
+            .unwrap_or_else(|| format!("line {}", i + 1));
+
+        println!("{} (mate in {})", id, problem_moves);
+        total += 1;
+        if report(&record.game_state, problem_moves, checks_only) {
+            solved += 1;
+        }
+    }
+    println!("Solved {} of {}", solved, total);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{moves::parse_coordinate, san::san_line};
+
+    fn solve(fen: &str, moves: u8, checks_only: bool) -> Option<String> {
+        let game_state = parse_fen(fen).unwrap();
+        let (line, _) = solve_mate(&game_state, moves, checks_only);
+        line.map(|line| san_line(&game_state, &line))
+    }
+
+    #[test]
+    fn mate_in_two() {
+        let fen = "5r1k/6pp/7N/8/8/8/Q7/6K1 w - - 0 1";
+        assert_eq!(solve(fen, 1, true), None);
+        assert_eq!(solve(fen, 2, true).as_deref(), Some("Qg8+ Rxg8 Nf7#"));
+        // Shortest first, however many moves are allowed
+        assert_eq!(solve(fen, 4, false).as_deref(), Some("Qg8+ Rxg8 Nf7#"));
+        assert_eq!(
+            solve("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 3, true).as_deref(),
+            Some("Ra8#")
+        );
+    }
+
+    #[test]
+    fn no_mate() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert_eq!(solve(start, 2, false), None);
+    }
+
+    #[test]
+    fn stalemate_is_not_mate() {
+        // Kc7 leaves black no moves, but not in check
+        let fen = "k7/8/1PK5/8/8/8/8/8 w - - 0 1";
+        let game_state = parse_fen(fen).unwrap();
+        let stalemate =
+            game_state.perform_move(&parse_coordinate("c6c7", &game_state, false).unwrap());
+        let mut solver = Solver {
+            checks_only: false,
+            refuted: HashSet::new(),
+            nodes: 0,
+        };
+        assert!(solver.defend(&stalemate, 1).is_none());
+        assert!(solver.defend(&stalemate, 2).is_none());
+        assert_eq!(solve(fen, 2, false), None);
+    }
+
+    #[test]
+    fn quiet_key_move() {
+        let fen = "6k1/8/8/8/8/8/R7/1R4K1 w - - 0 1";
+        assert_eq!(solve(fen, 2, true), None);
+        let game_state = parse_fen(fen).unwrap();
+        let (line, _) = solve_mate(&game_state, 2, false);
+        let line = line.unwrap();
+        assert_eq!(line.len(), 3);
+        let mut position = game_state.clone();
+        for mov in &line {
+            position = position.perform_move(mov);
+        }
+        assert!(position.in_check(true));
+        assert!(position.get_legal_moves(true).is_empty());
+        assert!(!game_state.perform_move(&line[0]).in_check(true));
+    }
+}
@@ -1,38 +1,46 @@
 use crate::{
     gamestate::GameState,
     pieces::{Board, Piece, PieceClass},
-    settings::{BOARD_WIDTH, ENGINE_BLACK},
-    utils::{input, pos_notation, SquareType, Vect, LETTERS},
+    settings::BOARD_WIDTH,
+    utils::{pos_notation, SquareType, Vect},
 };
 
+#[derive(Copy, Clone, PartialEq, Eq)]
 pub enum MoveType {
-    Standard(Vect, Vect, bool), // True if destination is self defended
+    Standard(Vect, Vect),
     DoubleAdvance(Vect, Vect),
     EnPassant(Vect, Vect, Vect), // Last vect is en_passant_target (piece to remove)
     Castling(bool),              // True if queenside
-    Promotion(Vect, Vect, Piece, bool), // True if destination is self defended
+    Promotion(Vect, Vect, Piece),
     Null,
 }
 
+#[derive(Copy, Clone, PartialEq, Eq)]
 pub struct Move {
     pub enemy: bool,
     pub move_type: MoveType,
 }
 
-fn standard_move_notation(from: &Vect, to: &Vect) -> String {
-    format!("{} -> {}", pos_notation(from), pos_notation(to))
+fn standard_move_notation(from: &Vect, to: &Vect, engine_black: bool) -> String {
+    format!(
+        "{} -> {}",
+        pos_notation(from, engine_black),
+        pos_notation(to, engine_black)
+    )
 }
 
 impl Move {
-    pub fn repr(&self) -> String {
+    pub fn repr(&self, engine_black: bool) -> String {
         let mov = match &self.move_type {
-            MoveType::Standard(from, to, _) => standard_move_notation(from, to),
-            MoveType::DoubleAdvance(from, to) => standard_move_notation(from, to),
-            MoveType::EnPassant(from, to, _) => standard_move_notation(from, to),
+            MoveType::Standard(from, to) => standard_move_notation(from, to, engine_black),
+            MoveType::DoubleAdvance(from, to) => standard_move_notation(from, to, engine_black),
+            MoveType::EnPassant(from, to, _) => standard_move_notation(from, to, engine_black),
             MoveType::Castling(queenside) => String::from(if *queenside { "0-0-0" } else { "0-0" }),
-            MoveType::Promotion(from, to, piece, _) => {
-                format!("{} ({})", standard_move_notation(from, to), piece.repr())
-            }
+            MoveType::Promotion(from, to, piece) => format!(
+                "{} ({})",
+                standard_move_notation(from, to, engine_black),
+                piece.repr()
+            ),
             MoveType::Null => String::from("NULL"),
         };
         format!(
@@ -41,14 +49,78 @@ impl Move {
             mov
         )
     }
+
+    // Origin and destination, except for castling and null moves
+    pub fn squares(&self) -> Option<(Vect, Vect)> {
+        match self.move_type {
+            MoveType::Standard(from, to) => Some((from, to)),
+            MoveType::DoubleAdvance(from, to) => Some((from, to)),
+            MoveType::EnPassant(from, to, _) => Some((from, to)),
+            MoveType::Promotion(from, to, _) => Some((from, to)),
+            MoveType::Castling(_) | MoveType::Null => None,
+        }
+    }
+
+    // Long algebraic as used by engine protocols, e.g. e2e4, e7e8q, e1g1
+    pub fn coordinate(&self, engine_black: bool) -> String {
+        let (from, to, promotion) = match &self.move_type {
+            MoveType::Standard(from, to) => (*from, *to, None),
+            MoveType::DoubleAdvance(from, to) => (*from, *to, None),
+            MoveType::EnPassant(from, to, _) => (*from, *to, None),
+            MoveType::Promotion(from, to, piece) => (*from, *to, Some(piece)),
+            MoveType::Castling(queenside) => {
+                let y = if self.enemy { BOARD_WIDTH - 1 } else { 0 };
+                let to_x = if *queenside { 2 } else { 6 };
+                (Vect { x: 4, y }, Vect { x: to_x, y }, None)
+            }
+            MoveType::Null => return String::from("0000"),
+        };
+        let mut out = pos_notation(&from, engine_black) + &pos_notation(&to, engine_black);
+        if let Some(piece) = promotion {
+            out.push(piece.repr().to_ascii_lowercase());
+        }
+        out
+    }
+}
+
+// Matches text against the moves available to the given side
+pub fn parse_coordinate(text: &str, game_state: &GameState, enemy: bool) -> Option<Move> {
+    let text = text.trim().to_ascii_lowercase();
+    // Byte offsets below are only character boundaries for ASCII
+    if !text.is_ascii() || !(4..=5).contains(&text.len()) {
+        return None;
+    }
+    let (squares, promotion) = text.split_at(4);
+    // Pawns can't promote to pawns or kings
+    let promotion = match promotion.chars().next() {
+        Some(char @ ('q' | 'r' | 'b' | 'n')) => Some(PieceClass::from_char(char)?),
+        Some(_) => return None,
+        None => None,
+    };
+
+    for mov in game_state.get_possible_moves(enemy) {
+        if mov.coordinate(game_state.engine_black)[..4] != *squares {
+            continue;
+        }
+        return match (mov.move_type, promotion) {
+            (MoveType::Promotion(from, to, piece), Some(class)) => Some(Move {
+                enemy,
+                move_type: MoveType::Promotion(from, to, Piece { class, ..piece }),
+            }),
+            // The promotion piece has to be given
+            (MoveType::Promotion(..), None) | (_, Some(_)) => None,
+            _ => Some(mov),
+        };
+    }
+    None
 }
 
 pub fn check_squre(board: &Board, pos: &Vect) -> SquareType {
     let (x, y) = (pos.x, pos.y);
-    if x >= BOARD_WIDTH || x < 0 {
+    if !(0..BOARD_WIDTH).contains(&x) {
         return SquareType::Invalid;
     };
-    if y >= BOARD_WIDTH || y < 0 {
+    if !(0..BOARD_WIDTH).contains(&y) {
         return SquareType::Invalid;
     };
 
@@ -65,110 +137,41 @@ pub fn check_squre(board: &Board, pos: &Vect) -> SquareType {
     }
 }
 
-fn get_position(prompt: &str) -> Vect {
-    loop {
-        let inp = input(prompt);
-        if inp.len() != 2 {
-            println!("Position must be 2 characters");
-            continue;
-        }
-        let chars = inp.as_bytes();
-        let x_res = LETTERS.iter().position(|&r| r == chars[0] as char);
-        let x = match x_res {
-            Some(i) => i as i8,
-            None => {
-                println!("First character must be file");
-                continue;
-            }
-        };
-        let y_res = (chars[1] as char).to_string().parse::<i8>();
-        let y = match y_res {
-            Ok(j) => {
-                if j > 0 && j <= BOARD_WIDTH {
-                    if ENGINE_BLACK {
-                        BOARD_WIDTH - j as i8
-                    } else {
-                        j - 1 as i8
-                    }
-                } else {
-                    {
-                        println!("Second character must be rank");
-                        continue;
-                    }
-                }
-            }
-            Err(_) => {
-                println!("Second character must be int");
-                continue;
-            }
-        };
-        return Vect { x, y };
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fen::parse_fen;
+
+    #[test]
+    fn parse_coordinate_rejects_non_ascii() {
+        let game_state =
+            parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        assert!(parse_coordinate("e2eé", &game_state, false).is_none());
+        assert!(parse_coordinate("é2e4", &game_state, false).is_none());
+        assert!(parse_coordinate("e2e4", &game_state, false).is_some());
     }
-}
 
-fn gen_move(choice: &str, from: Vect, to: Vect, subtract: bool, enemy: bool) -> Move {
-    let move_type = match choice {
-        "1" => MoveType::Standard(from, to, subtract),
-        "2" => MoveType::DoubleAdvance(from, to),
-        "3" => MoveType::EnPassant(from, to, Vect { x: from.y, y: to.x }),
-        "5" => {
-            let class = match input("Character of piece, defaults to queen: ").as_str() {
-                "b" => PieceClass::Bishop,
-                "n" => PieceClass::Knight,
-                "r" => PieceClass::Rook,
-                _ => PieceClass::Queen,
-            };
-            let piece = Piece { class, enemy };
-            MoveType::Promotion(from, to, piece, subtract)
+    #[test]
+    fn parse_coordinate_promotions() {
+        let game_state = parse_fen("8/4P3/8/8/8/8/k7/4K3 w - - 0 1").unwrap();
+        for text in ["e7e8", "e7e8k", "e7e8p", "e7e8x", "e7e8qq"] {
+            assert!(
+                parse_coordinate(text, &game_state, false).is_none(),
+                "{}",
+                text
+            );
         }
-        _ => panic!("Couldn't match move type"),
-    };
-    Move { enemy, move_type }
-}
-
-fn get_move(label: &str, game_state: &GameState, enemy: bool) -> Move {
-    println!("\nSelect move type for {}: ", label);
-    println!("1: Standard");
-    println!("2: Double Advance");
-    println!("3: En Passant");
-    println!("4: Castling");
-    println!("5: Promotion");
-    println!("_: Null");
-    let choice = input("> ");
-    match choice.as_str() {
-        "4" => {
-            let queenside = input("Queenside or Kingside? q/k: ") == "q";
-            return Move {
-                move_type: MoveType::Castling(queenside),
-                enemy,
+        for (text, class) in [
+            ("e7e8q", PieceClass::Queen),
+            ("e7e8r", PieceClass::Rook),
+            ("e7e8b", PieceClass::Bishop),
+            ("E7E8N", PieceClass::Knight),
+        ] {
+            let mov = parse_coordinate(text, &game_state, false).unwrap();
+            let MoveType::Promotion(_, _, piece) = mov.move_type else {
+                panic!("{} is not a promotion", text);
             };
-        }
-        "1" | "2" | "3" | "5" => (),
-        _ => {
-            return Move {
-                move_type: MoveType::Null,
-                enemy,
-            }
-        }
-    };
-
-    let from = get_position("From: ");
-    let to = get_position("To: ");
-
-    let mut mov = gen_move(choice.as_str(), from, to, true, enemy);
-    let (_, subtract) = game_state.perform_move_subtract(&mov);
-    if !subtract {
-        mov = gen_move(choice.as_str(), from, to, false, enemy);
-    }
-    mov
-}
-
-pub fn input_move(label: &str, game_state: &GameState, enemy: bool) -> Move {
-    loop {
-        let mov = get_move(label, game_state, enemy);
-        game_state.perform_move(&mov).print();
-        if input("Are you sure? y/n: ") == "y" {
-            return mov;
+            assert!(piece.class == class, "{}", text);
         }
     }
 }
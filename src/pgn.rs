@@ -0,0 +1,232 @@
+use std::{iter::Peekable, str::Chars};
+
+use crate::{fen::parse_fen, gamestate::GameState};
+
+pub const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+// Lines of movetext are kept within the width export format asks for
+const LINE_WIDTH: usize = 79;
+
+#[derive(Default)]
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    // Main line only, comments and variations are dropped
+    pub moves: Vec<String>,
+    pub result: Option<String>,
+}
+
+impl PgnGame {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    // From the FEN tag if there is one, else the standard start
+    pub fn start_position(&self) -> Result<GameState, String> {
+        parse_fen(self.tag("FEN").unwrap_or(START_FEN))
+    }
+
+    // Termination marker, falling back to the Result tag
+    pub fn result(&self) -> Option<&str> {
+        self.result.as_deref().or(self.tag("Result"))
+    }
+}
+
+fn skip_until(chars: &mut Peekable<Chars>, end: char) {
+    for char in chars.by_ref() {
+        if char == end {
+            break;
+        }
+    }
+}
+
+fn read_tag(chars: &mut Peekable<Chars>) -> Option<(String, String)> {
+    let mut text = String::new();
+    let mut quoted = false;
+    while let Some(char) = chars.next() {
+        match char {
+            '"' => quoted = !quoted,
+            '\\' if quoted => text.extend(chars.next()),
+            ']' if !quoted => break,
+            char => text.push(char),
+        }
+    }
+    let (name, value) = text.trim().split_once(char::is_whitespace)?;
+    Some((name.to_string(), value.trim().to_string()))
+}
+
+// Move text without a leading move number, which may be joined to the move as
+// in 1.e4 or 12...Nf6. Digits alone are kept, so 0-0 stays castling
+fn strip_move_number(token: &str) -> &str {
+    let rest = token.trim_start_matches(|c: char| c.is_ascii_digit());
+    if rest.len() < token.len() && rest.starts_with('.') {
+        rest.trim_start_matches('.')
+    } else {
+        token
+    }
+}
+
+fn push_token(game: &mut PgnGame, games: &mut Vec<PgnGame>, token: &mut String) {
+    let text = strip_move_number(token);
+    // Some files mark en passant captures, as in exd6 e.p., or write
+    // annotation glyphs apart from the move
+    let text = text.strip_suffix("e.p.").unwrap_or(text);
+    let skip = text.chars().all(|c| "!?.".contains(c));
+    if RESULTS.contains(&token.as_str()) {
+        game.result = Some(token.clone());
+        games.push(std::mem::take(game));
+    } else if !skip {
+        game.moves.push(text.to_string());
+    }
+    token.clear();
+}
+
+// Every game in the text, however many moves could be read from each
+pub fn parse_pgn(text: &str) -> Vec<PgnGame> {
+    let mut games = Vec::new();
+    let mut game = PgnGame::default();
+    let mut token = String::new();
+    let mut chars = text.chars().peekable();
+    let mut line_start = true;
+
+    while let Some(char) = chars.next() {
+        if char == '%' && line_start {
+            skip_until(&mut chars, '\n');
+            continue;
+        }
+        line_start = char == '\n';
+        if !token.is_empty() && (char.is_whitespace() || "[{;($)".contains(char)) {
+            push_token(&mut game, &mut games, &mut token);
+        }
+        match char {
+            '[' => {
+                // Tags after moves start a game with no termination marker
+                if !game.moves.is_empty() {
+                    games.push(std::mem::take(&mut game));
+                }
+                game.tags.extend(read_tag(&mut chars));
+            }
+            '{' => skip_until(&mut chars, '}'),
+            ';' => {
+                skip_until(&mut chars, '\n');
+                line_start = true;
+            }
+            '(' => {
+                let mut depth = 1;
+                while depth > 0 {
+                    match chars.next() {
+                        Some('(') => depth += 1,
+                        Some(')') => depth -= 1,
+                        Some('{') => skip_until(&mut chars, '}'),
+                        Some(_) => (),
+                        None => break,
+                    }
+                }
+            }
+            '$' => while chars.next_if(|c| c.is_ascii_digit()).is_some() {},
+            ')' => (),
+            char if char.is_whitespace() => (),
+            char => token.push(char),
+        }
+    }
+    if !token.is_empty() {
+        push_token(&mut game, &mut games, &mut token);
+    }
+    if !game.moves.is_empty() || !game.tags.is_empty() {
+        games.push(game);
+    }
+    games
+}
+
+// Export format PGN for one game. Tags are written in the order given
+pub fn write_pgn(tags: &[(&str, String)], movetext: &str, result: &str) -> String {
+    let mut out = String::new();
+    for (name, value) in tags {
+        let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+        out += &format!("[{} \"{}\"]\n", name, value);
+    }
+    out.push('\n');
+    let mut line = String::new();
+    for token in movetext.split_whitespace().chain([result]) {
+        if !line.is_empty() && line.len() + 1 + token.len() > LINE_WIDTH {
+            out += &line;
+            out.push('\n');
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line += token;
+    }
+    out += &line;
+    out.push('\n');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn move_numbers_are_stripped() {
+        let games = parse_pgn("1.e4 e5 2. Nf3 2...Nc6 3.Bb5 3... a6 12.O-O *");
+        assert_eq!(games.len(), 1);
+        assert_eq!(
+            games[0].moves,
+            ["e4", "e5", "Nf3", "Nc6", "Bb5", "a6", "O-O"]
+        );
+        assert_eq!(games[0].result(), Some("*"));
+    }
+
+    #[test]
+    fn castling_with_zeros() {
+        let games = parse_pgn("1. e4 e5 2. 0-0 0-0-0 3.0-0 1-0");
+        assert_eq!(games[0].moves, ["e4", "e5", "0-0", "0-0-0", "0-0"]);
+        assert_eq!(games[0].result(), Some("1-0"));
+    }
+
+    #[test]
+    fn annotations_are_skipped() {
+        let games = parse_pgn(
+            "1. e4 $1 e5 ! 2. d4 {A comment} exd4 ?! (2... d6 3. dxe5) 3. e5 d5 \
+             4. exd6 e.p. $14 Bxd6 ; rest of line\n5. Qxd4 1/2-1/2",
+        );
+        assert_eq!(
+            games[0].moves,
+            ["e4", "e5", "d4", "exd4", "e5", "d5", "exd6", "Bxd6", "Qxd4"]
+        );
+        assert_eq!(games[0].result(), Some("1/2-1/2"));
+    }
+
+    #[test]
+    fn tags_and_several_games() {
+        let text = "[Event \"One\"]\n[White \"A \\\"B\\\"\"]\n\n1. d4 d5 0-1\n\n\
+                    [Event \"Two\"]\n\n1. c4 *\n";
+        let games = parse_pgn(text);
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].tag("Event"), Some("One"));
+        assert_eq!(games[0].tag("White"), Some("A \"B\""));
+        assert_eq!(games[0].result(), Some("0-1"));
+        assert_eq!(games[1].tag("Event"), Some("Two"));
+        assert_eq!(games[1].moves, ["c4"]);
+    }
+
+    #[test]
+    fn written_games_read_back() {
+        let tags = [
+            ("Event", String::from("Test")),
+            ("White", String::from("A \"B\"")),
+        ];
+        let movetext = "1. e4 e5 2. Nf3 Nc6 ".repeat(10);
+        let text = write_pgn(&tags, &movetext, "*");
+        assert!(text.lines().all(|line| line.len() <= LINE_WIDTH));
+        let games = parse_pgn(&text);
+        assert_eq!(games[0].tag("White"), Some("A \"B\""));
+        assert_eq!(games[0].moves.len(), 40);
+        assert_eq!(games[0].result(), Some("*"));
+    }
+}
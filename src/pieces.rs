@@ -4,9 +4,6 @@ use crate::{
     utils::{CastlingPossibilities, SquareType, Vect},
 };
 
-pub const SCORE_BOUND: i16 = 500;
-pub const SCORE_RANGE: u64 = 2 * SCORE_BOUND as u64;
-
 const ROOK_VECTORS: [Vect; 4] = [
     Vect { x: 1, y: 0 },
     Vect { x: 0, y: 1 },
@@ -34,7 +31,7 @@ const KNIGHT_VECTORS: [Vect; 8] = [
 
 pub type Board = [[Option<Piece>; BOARD_WIDTH as usize]; BOARD_WIDTH as usize];
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Eq)]
 pub enum PieceClass {
     Pawn,
     Bishop,
@@ -44,7 +41,22 @@ pub enum PieceClass {
     King,
 }
 
-#[derive(Copy, Clone)]
+impl PieceClass {
+    // Case insensitive, as used by layouts and FEN
+    pub fn from_char(char: char) -> Option<PieceClass> {
+        match char.to_ascii_lowercase() {
+            'p' => Some(PieceClass::Pawn),
+            'b' => Some(PieceClass::Bishop),
+            'n' => Some(PieceClass::Knight),
+            'r' => Some(PieceClass::Rook),
+            'q' => Some(PieceClass::Queen),
+            'k' => Some(PieceClass::King),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
 pub struct Piece {
     pub class: PieceClass,
     pub enemy: bool,
@@ -113,7 +125,7 @@ impl Piece {
         };
 
         for move_vect in vect_set {
-            let mut square = pos.clone();
+            let mut square = pos;
             loop {
                 square.add(&move_vect);
                 let state = check_squre(board, &square);
@@ -121,14 +133,14 @@ impl Piece {
                     break;
                 }
                 if find_defended {
-                    defended.push(square.clone());
+                    defended.push(square);
                 }
                 if state == square_type {
                     break;
                 }
                 moves.push(Move {
                     enemy: self.enemy,
-                    move_type: MoveType::Standard(pos.clone(), square.clone(), true),
+                    move_type: MoveType::Standard(pos, square),
                 });
                 if state != SquareType::Free {
                     break;
@@ -139,7 +151,7 @@ impl Piece {
     }
 }
 
-fn promotion_or_standard(piece: &Piece, from: Vect, to: Vect, self_defended: bool) -> Move {
+fn promotion_or_standard(piece: &Piece, from: Vect, to: Vect) -> Move {
     Move {
         enemy: piece.enemy,
         move_type: if to.y == 0 || to.y == BOARD_WIDTH - 1 {
@@ -152,10 +164,9 @@ fn promotion_or_standard(piece: &Piece, from: Vect, to: Vect, self_defended: boo
                     class: PieceClass::Queen,
                     enemy: piece.enemy,
                 },
-                self_defended,
             )
         } else {
-            MoveType::Standard(from, to, self_defended)
+            MoveType::Standard(from, to)
         },
     }
 }
@@ -187,12 +198,7 @@ pub fn pawn_moves(
         };
         let state = check_squre(board, &forwards_pos);
         if state == SquareType::Free {
-            moves.push(promotion_or_standard(
-                piece,
-                pos.clone(),
-                forwards_pos,
-                false,
-            ));
+            moves.push(promotion_or_standard(piece, pos, forwards_pos));
             one_forward = true;
         } else if state == SquareType::Invalid {
             println!("UNPROMOTED PAWN!");
@@ -215,35 +221,23 @@ pub fn pawn_moves(
         }
         if state == SquareType::Free {
             if diagonal_pos.y == if piece.enemy { 2 } else { BOARD_WIDTH - 3 } {
-                match en_passant_midpoint {
-                    Some(midpoint) => {
-                        if diagonal_pos.equals(midpoint) {
-                            let en_passant_target = Vect {
-                                x: pos.x + x,
-                                y: pos.y,
-                            };
-                            moves.push(Move {
-                                enemy: piece.enemy,
-                                move_type: MoveType::EnPassant(
-                                    pos.clone(),
-                                    diagonal_pos,
-                                    en_passant_target,
-                                ),
-                            });
-                        }
+                if let Some(midpoint) = en_passant_midpoint {
+                    if diagonal_pos.equals(midpoint) {
+                        let en_passant_target = Vect {
+                            x: pos.x + x,
+                            y: pos.y,
+                        };
+                        moves.push(Move {
+                            enemy: piece.enemy,
+                            move_type: MoveType::EnPassant(pos, diagonal_pos, en_passant_target),
+                        });
                     }
-                    None => (),
                 }
             }
             continue;
         }
         if state != square_type {
-            moves.push(promotion_or_standard(
-                piece,
-                pos.clone(),
-                diagonal_pos,
-                true,
-            ));
+            moves.push(promotion_or_standard(piece, pos, diagonal_pos));
         }
     }
 
@@ -257,7 +251,7 @@ pub fn pawn_moves(
         if state == SquareType::Free {
             moves.push(Move {
                 enemy: piece.enemy,
-                move_type: MoveType::DoubleAdvance(pos.clone(), forwards_pos),
+                move_type: MoveType::DoubleAdvance(pos, forwards_pos),
             });
         }
     }
@@ -280,19 +274,19 @@ pub fn knight_moves(
         SquareType::Own
     };
     for move_vect in KNIGHT_VECTORS {
-        let mut square = pos.clone();
+        let mut square = pos;
         square.add(&move_vect);
         let state = check_squre(board, &square);
         if state == SquareType::Invalid {
             continue;
         }
         if find_defended {
-            defended.push(square.clone());
+            defended.push(square);
         }
         if state != square_type {
             moves.push(Move {
                 enemy: piece.enemy,
-                move_type: MoveType::Standard(pos.clone(), square, true),
+                move_type: MoveType::Standard(pos, square),
             });
         }
     }
@@ -336,17 +330,17 @@ pub fn rook_moves(
         x: if queenside { 1 } else { -1 },
         y: 0,
     };
-    let mut square = pos.clone();
+    let mut square = pos;
     loop {
         // Check path to king is clear and that king is reached
         square.add(&move_vect);
-        if check_squre(&board, &square) == SquareType::Invalid {
+        if check_squre(board, &square) == SquareType::Invalid {
             // TODO: This shouldn't be reached if rook is present
             break;
         }
         let cell = &board[square.y as usize][square.x as usize];
-        match cell {
-            Some(king) => match king.class {
+        if let Some(king) = cell {
+            match king.class {
                 PieceClass::King => {
                     if king.enemy == piece.enemy {
                         moves.push(Move {
@@ -356,8 +350,7 @@ pub fn rook_moves(
                     }
                 }
                 _ => break,
-            },
-            None => (),
+            }
         }
     }
 
@@ -397,19 +390,19 @@ pub fn king_moves(
     // Move radius 1
     for vect_set in [BISHOP_VECTORS, ROOK_VECTORS] {
         for move_vect in vect_set {
-            let mut square = pos.clone();
+            let mut square = pos;
             square.add(&move_vect);
             let state = check_squre(board, &square);
             if state == SquareType::Invalid {
                 continue;
             }
             if find_defended {
-                defended.push(square.clone());
+                defended.push(square);
             }
             if state != square_type {
                 moves.push(Move {
                     enemy: piece.enemy,
-                    move_type: MoveType::Standard(pos.clone(), square, true),
+                    move_type: MoveType::Standard(pos, square),
                 });
             }
         }
@@ -0,0 +1,60 @@
+use std::thread::{self, JoinHandle};
+
+use crate::{
+    gamestate::GameState,
+    moves::Move,
+    simulation::{analyse_with_limits, AnalysisResult, Progress, SearchLimits, SearchOptions},
+};
+
+// Searches the position after the expected reply while the opponent thinks
+pub struct Ponder {
+    predicted: Move,
+    limits: SearchLimits,
+    handle: JoinHandle<AnalysisResult>,
+}
+
+impl Ponder {
+    // Game state is the position before the predicted reply
+    pub fn start(
+        game_state: &GameState,
+        predicted: Move,
+        limits: &SearchLimits,
+        options: &SearchOptions,
+    ) -> Ponder {
+        let ponder_state = game_state.perform_move(&predicted);
+        let mut limits = limits.clone();
+        limits.stop = Default::default();
+        let mut options = *options;
+        options.progress = Progress::Quiet;
+
+        let thread_limits = limits.clone();
+        let handle =
+            thread::spawn(move || analyse_with_limits(&ponder_state, &thread_limits, &options));
+        Ponder {
+            predicted,
+            limits,
+            handle,
+        }
+    }
+
+    // Stops the search, as when the position is changed some other way
+    pub fn cancel(self) {
+        self.limits.stop();
+        let _ = self.handle.join();
+    }
+
+    // On a ponder hit the search is left to reach its limits and the result
+    // returned, otherwise it is stopped and thrown away
+    pub fn finish(self, actual: &Move) -> Option<AnalysisResult> {
+        let hit = *actual == self.predicted;
+        if !hit {
+            self.limits.stop();
+        }
+        let result = self.handle.join().unwrap();
+        if hit {
+            Some(result)
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,219 @@
+use std::{
+    io::{self, IsTerminal},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::{
+    gamestate::GameState,
+    moves::Move,
+    pieces::{Piece, PieceClass},
+    settings::{BOARD_WIDTH, UNICODE_BOARD},
+    utils::{parse_pos_notation, Vect, LETTERS},
+};
+
+// ANSI 256 colour backgrounds, with highlights a shade off each square colour
+pub const LIGHT_SQUARE: u8 = 180;
+pub const DARK_SQUARE: u8 = 137;
+pub const LIGHT_MOVED: u8 = 186;
+pub const DARK_MOVED: u8 = 143;
+pub const CHECK: u8 = 167;
+pub const WHITE_PIECE: u8 = 231;
+pub const BLACK_PIECE: u8 = 16;
+const RESET: &str = "\x1b[0m";
+
+static UNICODE: AtomicBool = AtomicBool::new(UNICODE_BOARD);
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum BoardStyle {
+    // Letters, upper case for white as in FEN, and dots for empty squares
+    Ascii,
+    // Chess glyphs on coloured squares, highlighting the last move and check
+    Unicode,
+}
+
+// Unicode boards are only drawn on a terminal, so logs and pipes stay ASCII
+pub fn set_board_style(ascii: bool) {
+    let unicode = UNICODE_BOARD && !ascii && io::stdout().is_terminal();
+    UNICODE.store(unicode, Ordering::Relaxed);
+}
+
+pub fn board_style() -> BoardStyle {
+    if UNICODE.load(Ordering::Relaxed) {
+        BoardStyle::Unicode
+    } else {
+        BoardStyle::Ascii
+    }
+}
+
+// Squares are counted from white's a1, the way they are written
+#[derive(Copy, Clone)]
+pub struct RenderOptions {
+    // Black at the bottom, files running h to a
+    pub view_black: bool,
+    pub style: BoardStyle,
+    // Rank and file labels
+    pub coordinates: bool,
+    // From and to squares of the move that led to the position
+    pub last_move: Option<(Vect, Vect)>,
+}
+
+impl RenderOptions {
+    pub fn new(view_black: bool) -> Self {
+        Self {
+            view_black,
+            style: board_style(),
+            coordinates: true,
+            last_move: None,
+        }
+    }
+
+    // Takes the colour of the side that made the move, castling shows the
+    // king's squares
+    pub fn with_last_move(mut self, mov: &Move, black: bool) -> Self {
+        let text = mov.coordinate(black);
+        self.last_move = match (text.get(0..2), text.get(2..4)) {
+            (Some(from), Some(to)) => {
+                parse_pos_notation(from, false).zip(parse_pos_notation(to, false))
+            }
+            _ => None,
+        };
+        self
+    }
+}
+
+pub fn glyph(class: PieceClass) -> char {
+    // The filled set for both sides, told apart by colour
+    match class {
+        PieceClass::Pawn => '♟',
+        PieceClass::Knight => '♞',
+        PieceClass::Bishop => '♝',
+        PieceClass::Rook => '♜',
+        PieceClass::Queen => '♛',
+        PieceClass::King => '♚',
+    }
+}
+
+fn ascii(piece: Piece, white: bool) -> char {
+    let char = Piece {
+        enemy: false,
+        ..piece
+    }
+    .repr();
+    if white {
+        char.to_ascii_uppercase()
+    } else {
+        char
+    }
+}
+
+// Piece on a square counted from white's a1, and whether it is white
+pub fn piece_at(game_state: &GameState, square: Vect) -> Option<(Piece, bool)> {
+    let y = if game_state.engine_black {
+        BOARD_WIDTH - 1 - square.y
+    } else {
+        square.y
+    };
+    let piece = game_state.board[y as usize][square.x as usize]?;
+    Some((piece, piece.enemy == game_state.engine_black))
+}
+
+// King of the side to move, if it is in check
+pub fn checked_king(game_state: &GameState) -> Option<Vect> {
+    if !game_state.in_check(false) {
+        return None;
+    }
+    let king = game_state.king_position(false)?;
+    Some(Vect {
+        x: king.x,
+        y: if game_state.engine_black {
+            BOARD_WIDTH - 1 - king.y
+        } else {
+            king.y
+        },
+    })
+}
+
+fn unicode_square(square: Option<(Piece, bool)>, background: u8) -> String {
+    let (char, foreground) = match square {
+        Some((piece, white)) => (
+            glyph(piece.class),
+            if white { WHITE_PIECE } else { BLACK_PIECE },
+        ),
+        None => (' ', WHITE_PIECE),
+    };
+    format!(
+        "\x1b[48;5;{}m\x1b[38;5;{}m {} ",
+        background, foreground, char
+    )
+}
+
+pub fn render_board(game_state: &GameState, options: &RenderOptions) -> String {
+    let unicode = options.style == BoardStyle::Unicode;
+    let check = checked_king(game_state);
+    let moved = |square: Vect| {
+        options
+            .last_move
+            .is_some_and(|(from, to)| from == square || to == square)
+    };
+
+    let mut out = String::new();
+    for row in 0..BOARD_WIDTH {
+        let rank = if options.view_black {
+            row
+        } else {
+            BOARD_WIDTH - 1 - row
+        };
+        if options.coordinates {
+            out += &format!("{} ", rank + 1);
+        }
+        for column in 0..BOARD_WIDTH {
+            let file = if options.view_black {
+                BOARD_WIDTH - 1 - column
+            } else {
+                column
+            };
+            let square = Vect { x: file, y: rank };
+            let contents = piece_at(game_state, square);
+            if unicode {
+                let light = (file + rank) % 2 == 1;
+                let background = if check == Some(square) {
+                    CHECK
+                } else {
+                    match (moved(square), light) {
+                        (true, true) => LIGHT_MOVED,
+                        (true, false) => DARK_MOVED,
+                        (false, true) => LIGHT_SQUARE,
+                        (false, false) => DARK_SQUARE,
+                    }
+                };
+                out += &unicode_square(contents, background);
+            } else {
+                if column > 0 {
+                    out.push(' ');
+                }
+                out.push(contents.map_or('.', |(piece, white)| ascii(piece, white)));
+            }
+        }
+        if unicode {
+            out += RESET;
+        }
+        out.push('\n');
+    }
+    if options.coordinates {
+        out += if unicode { "  " } else { " " };
+        for column in 0..BOARD_WIDTH {
+            let file = if options.view_black {
+                BOARD_WIDTH - 1 - column
+            } else {
+                column
+            };
+            out += &if unicode {
+                format!(" {} ", LETTERS[file as usize])
+            } else {
+                format!(" {}", LETTERS[file as usize])
+            };
+        }
+        out.push('\n');
+    }
+    out
+}
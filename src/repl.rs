@@ -0,0 +1,610 @@
+use std::{fs, path::Path, time::Instant};
+
+use crate::{
+    book::book_move,
+    clock::{time_result, Clock, TimeControl},
+    development::find_best_development,
+    evaluation::evaluate,
+    explain::print_eval,
+    fen::{parse_fen, to_fen},
+    gamestate::{parse_layout, GameState},
+    history::History,
+    moves::{parse_coordinate, Move},
+    ponder::Ponder,
+    render::RenderOptions,
+    rng::GameRng,
+    san::parse_move,
+    settings::{GAME_LOOP, PONDER, SEARCH_DEPTH},
+    simulation::{
+        analyse_with_limits, is_mate_score, pv_repr, score_repr, AnalysisResult, SearchLimits,
+        SearchOptions, MAX_DEPTH,
+    },
+    skill::{Handicap, Skill, MAX_SKILL},
+    transposition::table,
+    utils::{input, parse_duration, read_line},
+};
+
+const HELP: &str = "\
+Moves are given in SAN (e4, Nf3, O-O, e8=Q) or coordinates (e2e4, e7e8q)
+  go               Engine plays the side to move, from now on
+  depth <n>        Search to n plies, also nodes <n> or mate <n>
+  time <t>         Search for a time per move, e.g. 10s, 500ms or 2m
+  clock <control>  Play on a clock, e.g. 5m+3s, 40/90m, 15m delay 5s or off
+  skill <0-20>     Play weaker moves, 20 is full strength, or elo <rating>
+  flip             Turn the board around
+  fen              Print the position as FEN
+  eval             Break down the static evaluation
+  undo, redo       Step back or forward one ply
+  goto <ply>       Jump to a ply, 0 is the start
+  history          List the moves played
+  new [<odds>]     Start a new game, the engine giving pawn, knight or queen odds
+  load <fen|pgn>   Set up a position, or replay a game from a PGN file
+  save <pgn>       Write the game to a PGN file
+  quit";
+
+// Returns the chosen move and its expected continuation
+fn find_best_move(
+    game_state: &GameState,
+    analysis: &AnalysisResult,
+    timer: Instant,
+    skill: Skill,
+    rng: &mut GameRng,
+) -> Option<(Move, Vec<Move>)> {
+    let moves = analysis.best_moves.clone();
+
+    if analysis.engine_no_moves {
+        println!("No moves found, game over?");
+        return None;
+    } else if is_mate_score(analysis.score) && analysis.score > 0 {
+        println!("Checkmate found!");
+    }
+    println!(
+        "Best score delta: {}",
+        analysis.score - evaluate(game_state)
+    );
+
+    println!("Analysis found {} moves", moves.len());
+
+    let best_move = match skill.choose(&analysis.move_scores, rng) {
+        Some(choice) if !skill.is_full() => {
+            let loss = analysis.score - choice.score;
+            if loss > 0 {
+                println!(
+                    "Skill level {} chose a move {} below the best",
+                    skill.level(),
+                    score_repr(loss)
+                );
+            }
+            choice.mov
+        }
+        _ => find_best_development(game_state, moves, rng),
+    };
+
+    println!(
+        "{} selected from {} valid moves.",
+        best_move.repr(game_state.engine_black),
+        analysis.valid_moves
+    );
+    let mut pv = Vec::new();
+    if let Some(score) = analysis.move_scores.iter().find(|s| s.mov == best_move) {
+        println!(
+            "Expected continuation ({}): {}",
+            score_repr(score.score),
+            pv_repr(&score.pv, game_state.engine_black)
+        );
+        pv = score.pv.clone();
+    }
+    println!(
+        "Simulated {} moves to depth {}, took {} seconds or {} ms",
+        analysis.sim_moves,
+        analysis.depth,
+        timer.elapsed().as_secs(),
+        timer.elapsed().as_millis()
+    );
+    Some((best_move, pv))
+}
+
+// Also returns the analysis, unless a book move was played
+fn search_best_move(
+    game_state: &GameState,
+    limits: &SearchLimits,
+    options: &SearchOptions,
+    rng: &mut GameRng,
+) -> Option<(Move, Vec<Move>, Option<AnalysisResult>)> {
+    if let Some(mov) = book_move(game_state, rng) {
+        println!("Book move {}", mov.coordinate(game_state.engine_black));
+        return Some((mov, Vec::new(), None));
+    }
+    let timer = Instant::now();
+    let analysis = analyse_with_limits(game_state, limits, options);
+    let (mov, pv) = find_best_move(game_state, &analysis, timer, options.skill, rng)?;
+    Some((mov, pv, Some(analysis)))
+}
+
+fn colour_name(black: bool) -> &'static str {
+    if black {
+        "Black"
+    } else {
+        "White"
+    }
+}
+
+// The position is kept with the side to move as the engine (enemy = false),
+// as in the history, so engine_black is true when black is to move
+struct Repl {
+    history: History,
+    // Colour the engine plays, it moves whenever this side is to move
+    engine_black: bool,
+    // Board shown with black at the bottom
+    view_black: bool,
+    limits: SearchLimits,
+    // Limits from before the clock was set, put back when it is removed
+    unclocked_limits: Option<SearchLimits>,
+    options: SearchOptions,
+    ponder: Option<Ponder>,
+    clock: Option<Clock>,
+    rng: GameRng,
+}
+
+impl Repl {
+    fn new(options: &SearchOptions) -> Self {
+        Self {
+            history: History::new(parse_layout()),
+            engine_black: true,
+            view_black: false,
+            limits: SearchLimits::depth(SEARCH_DEPTH),
+            unclocked_limits: None,
+            options: *options,
+            ponder: None,
+            clock: None,
+            rng: GameRng::new(options.seed),
+        }
+    }
+
+    fn engine_to_move(&self) -> bool {
+        self.history.current().engine_black == self.engine_black
+    }
+
+    // Any search on the opponent's time is for a position no longer reached
+    fn stop_pondering(&mut self) {
+        if let Some(ponder) = self.ponder.take() {
+            ponder.cancel();
+        }
+    }
+
+    // Over on the board or on time
+    fn result(&self) -> Option<&'static str> {
+        match self.clock.as_ref().and_then(|clock| clock.flagged()) {
+            Some(black) => Some(time_result(black)),
+            None => self.history.current().game_result(),
+        }
+    }
+
+    // On a clock the search is given a share of the engine's remaining time,
+    // and below full strength it is cut short
+    fn search_limits(&self) -> SearchLimits {
+        let mut limits = self.options.skill.limit(&self.limits);
+        if let Some(clock) = &self.clock {
+            let (soft, hard) = clock.search_times(self.engine_black);
+            limits.soft_time = Some(soft);
+            limits.time = Some(limits.time.map_or(hard, |time| time.min(hard)));
+        }
+        limits
+    }
+
+    // Runs the clock of the side to move, or stops it once the game is over
+    fn restart_clock(&mut self) {
+        let over = self.result().is_some();
+        let black = self.history.current().engine_black;
+        if let Some(clock) = &mut self.clock {
+            if over {
+                clock.stop();
+            } else {
+                clock.start(black);
+            }
+        }
+    }
+
+    fn show(&self) {
+        let game_state = self.history.current();
+        let mut render = RenderOptions::new(self.view_black);
+        if let Some(entry) = self.history.last_entry() {
+            render = render.with_last_move(&entry.mov, !game_state.engine_black);
+        }
+        game_state.print(&render);
+        match self.history.last_entry() {
+            Some(entry) => println!("Ply {}, last move {}", self.history.ply(), entry.san),
+            None => println!("Ply 0, start position"),
+        }
+        if let Some(clock) = &self.clock {
+            println!("{}", clock.repr());
+        }
+        match self.result() {
+            Some(result) => println!("{}", result),
+            None if game_state.in_check(false) => {
+                println!("{} is in check", colour_name(game_state.engine_black))
+            }
+            None => (),
+        }
+    }
+
+    fn play_move(&mut self, mov: Move, analysis: Option<AnalysisResult>) {
+        self.history.push(mov, analysis);
+        if let Some(clock) = &mut self.clock {
+            clock.press();
+        }
+        if self.result().is_some() {
+            if let Some(clock) = &mut self.clock {
+                clock.stop();
+            }
+        }
+        self.show();
+    }
+
+    // Searches unless a ponder hit already has, returns false if there was
+    // no move to play
+    fn engine_move(&mut self, pondered: Option<AnalysisResult>) -> bool {
+        let game_state = self.history.current();
+        if self.result().is_some() {
+            return false;
+        }
+        let limits = self.search_limits();
+        let search_options = self.options.skill.options(&self.options);
+        let result = match pondered {
+            Some(analysis) => {
+                println!("Ponder hit, using the search made on the opponent's time");
+                find_best_move(
+                    &game_state,
+                    &analysis,
+                    Instant::now(),
+                    self.options.skill,
+                    &mut self.rng,
+                )
+                .map(|(mov, pv)| (mov, pv, Some(analysis)))
+            }
+            None => search_best_move(&game_state, &limits, &search_options, &mut self.rng),
+        };
+        let Some((mov, pv, analysis)) = result else {
+            return false;
+        };
+        self.play_move(mov, analysis);
+
+        // The expected reply, in the position after the move
+        let next_state = self.history.current();
+        let reply = pv.get(1).and_then(|reply| {
+            parse_coordinate(
+                &reply.coordinate(game_state.engine_black),
+                &next_state,
+                false,
+            )
+        });
+        // A seeded game only replays if no search depends on how long the
+        // player takes to move
+        let ponder = PONDER && self.options.seed.is_none();
+        if let (true, Some(reply), None) = (ponder, reply, self.result()) {
+            println!("Pondering on {}", reply.coordinate(next_state.engine_black));
+            self.ponder = Some(Ponder::start(
+                &next_state,
+                reply,
+                &self.search_limits(),
+                &search_options,
+            ));
+        }
+        true
+    }
+
+    fn user_move(&mut self, mov: Move) {
+        if let Some(result) = self.result() {
+            println!("{}", result);
+            return;
+        }
+        let pondered = self.ponder.take().and_then(|ponder| ponder.finish(&mov));
+        self.play_move(mov, None);
+        if self.engine_to_move() {
+            self.engine_move(pondered);
+        }
+    }
+
+    fn navigate(&mut self, moved: bool, command: &str) {
+        if !moved {
+            println!("Can't {} from ply {}", command, self.history.ply());
+            return;
+        }
+        self.stop_pondering();
+        self.restart_clock();
+        self.show();
+        if let Some(analysis) = self.history.last_entry().and_then(|e| e.analysis_repr()) {
+            println!("Engine's analysis: {}", analysis);
+        }
+    }
+
+    // Each game's random choices are replayed from the seed it logs. Scores
+    // left in the table from the last game would change its searches
+    fn new_seed(&mut self) {
+        table().clear();
+        self.rng = GameRng::new(self.options.seed);
+        println!("Game seed {}", self.rng.seed);
+    }
+
+    fn new_game(&mut self, start: GameState) {
+        self.stop_pondering();
+        self.history = History::new(start);
+        self.new_seed();
+        self.clock = self.clock.as_ref().map(|clock| Clock::new(clock.control));
+        self.restart_clock();
+    }
+
+    // A PGN file if the path exists, else a FEN
+    fn load(&mut self, text: &str) {
+        if !Path::new(text).is_file() {
+            match parse_fen(text) {
+                Ok(game_state) => {
+                    self.new_game(game_state);
+                    self.show();
+                }
+                Err(e) => println!("Invalid FEN: {}", e),
+            }
+            return;
+        }
+        let pgn = match fs::read_to_string(text) {
+            Ok(pgn) => pgn,
+            Err(e) => {
+                println!("Couldn't read {}: {}", text, e);
+                return;
+            }
+        };
+        match History::from_pgn(&pgn) {
+            Ok((history, stopped)) => {
+                self.stop_pondering();
+                self.history = history;
+                self.new_seed();
+                self.clock = self.clock.as_ref().map(|clock| Clock::new(clock.control));
+                self.restart_clock();
+                if let Some(san) = stopped {
+                    println!("Stopped at illegal move {}", san);
+                }
+                self.show();
+            }
+            Err(e) => println!("Couldn't load {}: {}", text, e),
+        }
+    }
+
+    fn save(&self, path: &str) {
+        let flagged = self.clock.as_ref().and_then(|clock| clock.flagged());
+        let mut tags = vec![("Seed", self.rng.seed.to_string())];
+        if let Some(control) = self
+            .clock
+            .as_ref()
+            .and_then(|clock| clock.control.pgn_tag())
+        {
+            tags.push(("TimeControl", control));
+        }
+        let pgn = self
+            .history
+            .to_pgn(self.engine_black, flagged.map(time_result), tags);
+        match fs::write(path, pgn) {
+            Ok(()) => println!("Saved {} plies to {}", self.history.ply(), path),
+            Err(e) => println!("Couldn't write {}: {}", path, e),
+        }
+    }
+
+    // Returns false on quit
+    fn command(&mut self, line: &str) -> bool {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((&command, args)) = words.split_first() else {
+            return true;
+        };
+        match (command, args) {
+            ("go", []) => {
+                self.stop_pondering();
+                self.engine_black = self.history.current().engine_black;
+                if !self.engine_move(None) {
+                    println!("No move to play");
+                }
+            }
+            ("depth" | "nodes" | "mate", [_]) => match SearchLimits::parse(line) {
+                Some(limits) => {
+                    self.limits = limits;
+                    self.unclocked_limits = None;
+                }
+                None => println!("{} must be non-negative int", command),
+            },
+            ("time", [time]) => match parse_duration(time) {
+                Some(time) => {
+                    self.limits = SearchLimits::depth(MAX_DEPTH);
+                    self.limits.time = Some(time);
+                    self.unclocked_limits = None;
+                }
+                None => println!("Time must be a number with s, ms or m, e.g. 10s"),
+            },
+            ("clock", []) => match &self.clock {
+                Some(clock) => println!("{}, {}", clock.control.repr(), clock.repr()),
+                None => println!("No clock, try clock 5m+3s"),
+            },
+            ("clock", ["off"]) => {
+                self.stop_pondering();
+                self.clock = None;
+                if let Some(limits) = self.unclocked_limits.take() {
+                    self.limits = limits;
+                }
+            }
+            ("clock", [_, ..]) => match TimeControl::parse(&args.join(" ")) {
+                Some(control) => {
+                    self.stop_pondering();
+                    let limits =
+                        std::mem::replace(&mut self.limits, SearchLimits::depth(MAX_DEPTH));
+                    self.unclocked_limits.get_or_insert(limits);
+                    self.clock = Some(Clock::new(control));
+                    self.restart_clock();
+                    println!("Clock set to {} each", control.repr());
+                }
+                None => println!("Time control must be like 5m, 5m+3s, 40/90m or 15m delay 5s"),
+            },
+            ("skill", []) => println!(
+                "Skill level {} of {}, about {} Elo",
+                self.options.skill.level(),
+                MAX_SKILL,
+                self.options.skill.elo()
+            ),
+            ("skill", [level]) => match level.parse().ok().and_then(Skill::new) {
+                Some(skill) => self.options.skill = skill,
+                None => println!("Skill must be 0 to {}", MAX_SKILL),
+            },
+            ("elo", [elo]) => match elo.parse() {
+                Ok(elo) => {
+                    self.options.skill = Skill::from_elo(elo);
+                    println!("Playing at skill level {}", self.options.skill.level());
+                }
+                Err(_) => println!("Elo must be non-negative int"),
+            },
+            ("flip", []) => {
+                self.view_black = !self.view_black;
+                self.show();
+            }
+            ("fen", []) => println!("{}", to_fen(&self.history.current())),
+            ("eval", []) => print_eval(&self.history.current()),
+            ("undo", []) => {
+                let moved = self.history.undo();
+                self.navigate(moved, command);
+            }
+            ("redo", []) => {
+                let moved = self.history.redo();
+                self.navigate(moved, command);
+            }
+            ("goto", [ply]) => match ply.parse() {
+                Ok(ply) => {
+                    let moved = self.history.goto(ply);
+                    self.navigate(moved, line);
+                }
+                Err(_) => println!("Ply must be non-negative int"),
+            },
+            ("history", []) => self.history.print(),
+            ("new", []) => {
+                self.new_game(parse_layout());
+                self.engine_black = true;
+                self.show();
+            }
+            ("new", [odds]) => match Handicap::parse(odds) {
+                Some(handicap) => {
+                    self.new_game(handicap.start());
+                    self.engine_black = handicap.engine_black();
+                    println!("Engine gives {}", handicap.name());
+                    self.show();
+                    if self.engine_to_move() {
+                        self.engine_move(None);
+                    }
+                }
+                None => println!("Odds must be pawn, knight or queen"),
+            },
+            ("load", [_, ..]) => self.load(&args.join(" ")),
+            ("save", [path]) => self.save(path),
+            ("help", []) => println!("{}", HELP),
+            ("quit" | "exit", []) => {
+                self.stop_pondering();
+                return false;
+            }
+            _ => match parse_move(line, &self.history.current()) {
+                Some(mov) => self.user_move(mov),
+                None if words.len() == 1 => println!("Illegal move or unknown command: {}", line),
+                None => println!("Unknown command: {}, try help", line),
+            },
+        }
+        true
+    }
+
+    // The engine plays both sides, waiting for enter between moves
+    fn self_play(&mut self) {
+        loop {
+            self.engine_black = self.history.current().engine_black;
+            if !self.engine_move(None) {
+                return;
+            }
+            self.stop_pondering();
+            input("Press enter to continue");
+        }
+    }
+}
+
+// Reads moves and commands until quit or the end of input
+pub fn play(options: &SearchOptions) {
+    let mut repl = Repl::new(options);
+    if !GAME_LOOP {
+        repl.self_play();
+        return;
+    }
+    repl.show();
+    println!("Game seed {}", repl.rng.seed);
+    println!("Enter a move such as e4 or e2e4, or help for the commands");
+    loop {
+        let prompt = format!(
+            "{} to move> ",
+            colour_name(repl.history.current().engine_black)
+        );
+        let Some(line) = read_line(&prompt) else {
+            repl.stop_pondering();
+            return;
+        };
+        if !repl.command(line.trim()) {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repl() -> Repl {
+        Repl::new(&SearchOptions {
+            threads: 1,
+            ..SearchOptions::default()
+        })
+    }
+
+    // The engine plays both sides for a few moves
+    fn seeded_game(seed: u64) -> Vec<String> {
+        let mut repl = Repl::new(&SearchOptions {
+            threads: 1,
+            seed: Some(seed),
+            ..SearchOptions::default()
+        });
+        repl.command("new");
+        repl.command("depth 2");
+        repl.command("skill 8");
+        for _ in 0..8 {
+            repl.engine_black = repl.history.current().engine_black;
+            assert!(repl.engine_move(None));
+            assert!(repl.ponder.is_none());
+        }
+        repl.history
+            .entries()
+            .iter()
+            .map(|entry| entry.san.clone())
+            .collect()
+    }
+
+    #[test]
+    fn clock_off_restores_limits() {
+        let mut repl = repl();
+        repl.command("clock 5m");
+        assert_eq!(repl.search_limits().depth, MAX_DEPTH);
+        assert!(repl.search_limits().time.is_some());
+        repl.command("clock 40/90m");
+        repl.command("clock off");
+        let limits = repl.search_limits();
+        assert_eq!(limits.depth, SEARCH_DEPTH);
+        assert_eq!((limits.time, limits.soft_time), (None, None));
+
+        // Limits set while on the clock are kept after it
+        repl.command("clock 5m");
+        repl.command("depth 3");
+        repl.command("clock off");
+        assert_eq!(repl.search_limits().depth, 3);
+        assert!(repl.search_limits().time.is_none());
+    }
+
+    #[test]
+    fn seeded_games_replay() {
+        assert_eq!(seeded_game(11), seeded_game(11));
+    }
+}
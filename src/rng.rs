@@ -0,0 +1,37 @@
+use rand::{rngs::StdRng, Error, RngCore, SeedableRng};
+
+// Source of every random choice in a game, the book, development and skill,
+// so a game replays move for move from its seed
+pub struct GameRng {
+    pub seed: u64,
+    rng: StdRng,
+}
+
+impl GameRng {
+    // A fresh seed unless one is given
+    pub fn new(seed: Option<u64>) -> Self {
+        let seed = seed.unwrap_or_else(rand::random);
+        Self {
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl RngCore for GameRng {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.rng.try_fill_bytes(dest)
+    }
+}
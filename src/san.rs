@@ -0,0 +1,241 @@
+use crate::{
+    gamestate::GameState,
+    moves::{parse_coordinate, Move, MoveType},
+    pieces::{Piece, PieceClass},
+    see::is_capture,
+    utils::pos_notation,
+};
+
+fn piece_letter(class: PieceClass) -> char {
+    Piece { class, enemy: true }.repr()
+}
+
+fn moved_class(game_state: &GameState, mov: &Move) -> Option<PieceClass> {
+    let (from, _) = mov.squares()?;
+    game_state.board[from.y as usize][from.x as usize].map(|piece| piece.class)
+}
+
+// Standard algebraic notation, e.g. Nbd7, exd5, e8=Q+, O-O#
+pub fn to_san(game_state: &GameState, mov: &Move) -> String {
+    let engine_black = game_state.engine_black;
+    let mut san = match (mov.move_type, mov.squares()) {
+        (MoveType::Castling(queenside), _) => String::from(if queenside { "O-O-O" } else { "O-O" }),
+        (_, Some((from, to))) => {
+            let class = moved_class(game_state, mov).unwrap_or(PieceClass::Pawn);
+            let from_text = pos_notation(&from, engine_black);
+            let capture = is_capture(game_state, mov);
+            let mut san = String::new();
+            if class == PieceClass::Pawn {
+                if capture {
+                    san.push_str(&from_text[0..1]);
+                }
+            } else {
+                san.push(piece_letter(class));
+                // Other pieces of the same kind that can reach the square
+                let rivals: Vec<String> = game_state
+                    .get_legal_moves(mov.enemy)
+                    .iter()
+                    .filter(|other| {
+                        moved_class(game_state, other) == Some(class)
+                            && other.squares().is_some_and(|(f, t)| t == to && f != from)
+                    })
+                    .map(|other| pos_notation(&other.squares().unwrap().0, engine_black))
+                    .collect();
+                if !rivals.is_empty() {
+                    if rivals.iter().all(|r| r[0..1] != from_text[0..1]) {
+                        san.push_str(&from_text[0..1]);
+                    } else if rivals.iter().all(|r| r[1..] != from_text[1..]) {
+                        san.push_str(&from_text[1..]);
+                    } else {
+                        san.push_str(&from_text);
+                    }
+                }
+            }
+            if capture {
+                san.push('x');
+            }
+            san.push_str(&pos_notation(&to, engine_black));
+            if let MoveType::Promotion(_, _, piece) = mov.move_type {
+                san.push('=');
+                san.push(piece_letter(piece.class));
+            }
+            san
+        }
+        _ => return String::from("--"),
+    };
+
+    let next_state = game_state.perform_move(mov);
+    if next_state.in_check(!mov.enemy) {
+        if next_state.get_legal_moves(!mov.enemy).is_empty() {
+            san.push('#');
+        } else {
+            san.push('+');
+        }
+    }
+    san
+}
+
+// Moves played one after another, as in a PV, where sides alternate without
+// the position being flipped
+pub fn san_line(game_state: &GameState, moves: &[Move]) -> String {
+    let mut game_state = game_state.clone();
+    let mut sans = Vec::new();
+    for mov in moves {
+        sans.push(to_san(&game_state, mov));
+        game_state = game_state.perform_move(mov);
+    }
+    sans.join(" ")
+}
+
+// Legal move for the given side matching the text, None if there is no such
+// move or the text is ambiguous
+pub fn parse_san(text: &str, game_state: &GameState, enemy: bool) -> Option<Move> {
+    let text = text.trim().trim_end_matches(['+', '#', '!', '?']);
+    if !text.is_ascii() {
+        return None;
+    }
+    let legal_moves = game_state.get_legal_moves(enemy);
+
+    let castling = match text {
+        "O-O" | "0-0" => Some(false),
+        "O-O-O" | "0-0-0" => Some(true),
+        _ => None,
+    };
+    if let Some(queenside) = castling {
+        return legal_moves
+            .into_iter()
+            .find(|mov| mov.move_type == MoveType::Castling(queenside));
+    }
+
+    let mut text = text.replace(['x', '-'], "");
+    let promotion = match text.char_indices().last() {
+        Some((i, char)) if i >= 2 && "QRBN".contains(char) => {
+            text.truncate(i);
+            if text.ends_with('=') {
+                text.pop();
+            }
+            PieceClass::from_char(char)
+        }
+        _ => None,
+    };
+    let (class, rest) = match text.chars().next() {
+        Some(char) if "KQRBN".contains(char) => (PieceClass::from_char(char)?, &text[1..]),
+        _ => (PieceClass::Pawn, &text[..]),
+    };
+    if rest.len() < 2 {
+        return None;
+    }
+    let (disambiguation, target) = rest.split_at(rest.len() - 2);
+
+    let engine_black = game_state.engine_black;
+    let mut matches = legal_moves.into_iter().filter(|mov| {
+        let Some((from, to)) = mov.squares() else {
+            return false;
+        };
+        let from_text = pos_notation(&from, engine_black);
+        moved_class(game_state, mov) == Some(class)
+            && pos_notation(&to, engine_black) == target
+            && disambiguation.chars().all(|c| from_text.contains(c))
+            && matches!(mov.move_type, MoveType::Promotion(..)) == promotion.is_some()
+    });
+    let mov = matches.next()?;
+    if matches.next().is_some() {
+        return None;
+    }
+    // Only queening is generated, other promotions are substituted in
+    match (mov.move_type, promotion) {
+        (MoveType::Promotion(from, to, piece), Some(class)) => Some(Move {
+            enemy,
+            move_type: MoveType::Promotion(from, to, Piece { class, ..piece }),
+        }),
+        _ => Some(mov),
+    }
+}
+
+// SAN, or coordinate notation such as e2e4 and e7e8q, for the engine
+pub fn parse_move(text: &str, game_state: &GameState) -> Option<Move> {
+    parse_san(text, game_state, false).or_else(|| {
+        parse_coordinate(text, game_state, false)
+            .filter(|mov| game_state.is_legal(mov, &game_state.perform_move(mov)))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fen::parse_fen;
+
+    fn san_of(fen: &str, text: &str) -> String {
+        let game_state = parse_fen(fen).unwrap();
+        to_san(
+            &game_state,
+            &parse_coordinate(text, &game_state, false).unwrap(),
+        )
+    }
+
+    fn coordinate_of(fen: &str, san: &str) -> Option<String> {
+        let game_state = parse_fen(fen).unwrap();
+        parse_san(san, &game_state, false).map(|mov| mov.coordinate(game_state.engine_black))
+    }
+
+    #[test]
+    fn plain_moves() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert_eq!(san_of(start, "e2e4"), "e4");
+        assert_eq!(san_of(start, "g1f3"), "Nf3");
+        let black = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1";
+        assert_eq!(san_of(black, "g8f6"), "Nf6");
+        assert_eq!(coordinate_of(black, "e5").as_deref(), Some("e7e5"));
+        let capture = "4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1";
+        assert_eq!(san_of(capture, "e4d5"), "exd5");
+        assert_eq!(coordinate_of(capture, "exd5").as_deref(), Some("e4d5"));
+        assert_eq!(san_of("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6"), "exd6");
+    }
+
+    #[test]
+    fn disambiguation() {
+        let file = "4k3/8/8/8/8/8/8/R4RK1 w - - 0 1";
+        assert_eq!(san_of(file, "a1c1"), "Rac1");
+        assert_eq!(coordinate_of(file, "Rfc1").as_deref(), Some("f1c1"));
+        assert_eq!(coordinate_of(file, "Rc1"), None);
+        let rank = "4k3/R7/8/8/8/8/8/R3K3 w - - 0 1";
+        assert_eq!(san_of(rank, "a1a4"), "R1a4");
+        assert_eq!(coordinate_of(rank, "R7a4").as_deref(), Some("a7a4"));
+        let square = "4k3/8/8/8/8/Q1Q5/8/Q1Q1K3 w - - 0 1";
+        assert_eq!(san_of(square, "a1b2"), "Qa1b2");
+        assert_eq!(coordinate_of(square, "Qc3xb2").as_deref(), Some("c3b2"));
+    }
+
+    #[test]
+    fn promotions() {
+        let fen = "4k3/P7/8/8/8/8/8/4K3 w - - 0 1";
+        assert_eq!(san_of(fen, "a7a8q"), "a8=Q+");
+        assert_eq!(san_of(fen, "a7a8n"), "a8=N");
+        assert_eq!(coordinate_of(fen, "a8=Q+").as_deref(), Some("a7a8q"));
+        assert_eq!(coordinate_of(fen, "a8N").as_deref(), Some("a7a8n"));
+        assert_eq!(coordinate_of(fen, "a8"), None);
+    }
+
+    #[test]
+    fn castling_and_mate() {
+        let fen = "4k3/8/8/8/8/8/8/R3K2R w KQ - 0 1";
+        assert_eq!(san_of(fen, "e1g1"), "O-O");
+        assert_eq!(san_of(fen, "e1c1"), "O-O-O");
+        assert_eq!(coordinate_of(fen, "0-0").as_deref(), Some("e1g1"));
+        assert_eq!(coordinate_of(fen, "O-O-O").as_deref(), Some("e1c1"));
+        let mate = "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1";
+        assert_eq!(san_of(mate, "a1a8"), "Ra8#");
+        assert_eq!(coordinate_of(mate, "Ra8#").as_deref(), Some("a1a8"));
+    }
+
+    #[test]
+    fn every_legal_move_reads_back() {
+        let game_state =
+            parse_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")
+                .unwrap();
+        for mov in game_state.get_legal_moves(false) {
+            let san = to_san(&game_state, &mov);
+            assert!(parse_san(&san, &game_state, false) == Some(mov), "{}", san);
+        }
+    }
+}
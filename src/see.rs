@@ -0,0 +1,230 @@
+use crate::{
+    evaluation::piece_value,
+    gamestate::GameState,
+    moves::{Move, MoveType},
+    pieces::{Board, Piece, PieceClass},
+    settings::BOARD_WIDTH,
+    utils::Vect,
+};
+
+const ORTHOGONALS: [Vect; 4] = [
+    Vect { x: 1, y: 0 },
+    Vect { x: 0, y: 1 },
+    Vect { x: -1, y: 0 },
+    Vect { x: 0, y: -1 },
+];
+
+const DIAGONALS: [Vect; 4] = [
+    Vect { x: 1, y: 1 },
+    Vect { x: -1, y: 1 },
+    Vect { x: -1, y: -1 },
+    Vect { x: 1, y: -1 },
+];
+
+const KNIGHT_JUMPS: [Vect; 8] = [
+    Vect { x: 2, y: 1 },
+    Vect { x: -2, y: 1 },
+    Vect { x: 2, y: -1 },
+    Vect { x: -2, y: -1 },
+    Vect { x: 1, y: 2 },
+    Vect { x: 1, y: -2 },
+    Vect { x: -1, y: 2 },
+    Vect { x: -1, y: -2 },
+];
+
+fn piece_at(board: &Board, x: i8, y: i8) -> Option<Piece> {
+    if !(0..BOARD_WIDTH).contains(&x) || !(0..BOARD_WIDTH).contains(&y) {
+        return None;
+    }
+    board[y as usize][x as usize]
+}
+
+fn consider(best: &mut Option<(Vect, Piece)>, pos: Vect, piece: Piece) {
+    let better = match best {
+        Some((_, current)) => piece_value(piece.class) < piece_value(current.class),
+        None => true,
+    };
+    if better {
+        *best = Some((pos, piece));
+    }
+}
+
+// Cheapest piece of the given side attacking the target. Captured pieces are
+// removed from the board as the exchange plays out, so sliders hidden behind
+// them are found on later calls
+fn least_valuable_attacker(board: &Board, target: Vect, enemy: bool) -> Option<(Vect, Piece)> {
+    let mut best = None;
+
+    // Pawns attack forwards, so look backwards from the target
+    let pawn_row = target.y + if enemy { 1 } else { -1 };
+    for x in [target.x - 1, target.x + 1] {
+        if let Some(piece) = piece_at(board, x, pawn_row) {
+            if piece.enemy == enemy && piece.class == PieceClass::Pawn {
+                consider(&mut best, Vect { x, y: pawn_row }, piece);
+            }
+        }
+    }
+
+    for jump in KNIGHT_JUMPS {
+        let (x, y) = (target.x + jump.x, target.y + jump.y);
+        if let Some(piece) = piece_at(board, x, y) {
+            if piece.enemy == enemy && piece.class == PieceClass::Knight {
+                consider(&mut best, Vect { x, y }, piece);
+            }
+        }
+    }
+
+    for (vectors, slider) in [
+        (DIAGONALS, PieceClass::Bishop),
+        (ORTHOGONALS, PieceClass::Rook),
+    ] {
+        for direction in vectors {
+            let mut pos = target;
+            let mut distance = 0;
+            loop {
+                pos.add(&direction);
+                distance += 1;
+                if !(0..BOARD_WIDTH).contains(&pos.x) || !(0..BOARD_WIDTH).contains(&pos.y) {
+                    break;
+                }
+                let piece = match board[pos.y as usize][pos.x as usize] {
+                    Some(piece) => piece,
+                    None => continue,
+                };
+                if piece.enemy == enemy
+                    && (piece.class == slider
+                        || piece.class == PieceClass::Queen
+                        || piece.class == PieceClass::King && distance == 1)
+                {
+                    consider(&mut best, pos, piece);
+                }
+                break;
+            }
+        }
+    }
+
+    best
+}
+
+pub fn is_attacked(board: &Board, target: Vect, by_enemy: bool) -> bool {
+    least_valuable_attacker(board, target, by_enemy).is_some()
+}
+
+pub fn is_capture(game_state: &GameState, mov: &Move) -> bool {
+    match mov.move_type {
+        MoveType::Standard(_, to) | MoveType::Promotion(_, to, _) => {
+            game_state.board[to.y as usize][to.x as usize].is_some()
+        }
+        MoveType::EnPassant(..) => true,
+        _ => false,
+    }
+}
+
+// Static exchange evaluation: the material the moving side gains, in
+// centipawns, if both sides keep recapturing on the destination square with
+// their cheapest attacker for as long as it pays off
+pub fn see(game_state: &GameState, mov: &Move) -> i32 {
+    let (from, to) = match mov.squares() {
+        Some(squares) => squares,
+        None => return 0,
+    };
+    let mut board = game_state.board;
+    let mover = match board[from.y as usize][from.x as usize] {
+        Some(piece) => piece,
+        None => return 0,
+    };
+
+    let mut gains = Vec::with_capacity(32);
+    let mut first_gain = match board[to.y as usize][to.x as usize] {
+        Some(piece) => piece_value(piece.class),
+        None => 0,
+    };
+    let mut on_square = mover;
+    match mov.move_type {
+        MoveType::EnPassant(_, _, target) => {
+            first_gain = piece_value(PieceClass::Pawn);
+            board[target.y as usize][target.x as usize] = None;
+        }
+        MoveType::Promotion(_, _, piece) => {
+            first_gain += piece_value(piece.class) - piece_value(PieceClass::Pawn);
+            on_square = piece;
+        }
+        _ => (),
+    }
+    gains.push(first_gain);
+    board[from.y as usize][from.x as usize] = None;
+    board[to.y as usize][to.x as usize] = Some(on_square);
+
+    let mut side = !mov.enemy;
+    while let Some((pos, attacker)) = least_valuable_attacker(&board, to, side) {
+        // Each entry is the gain for the side capturing if the exchange stopped there
+        let previous = *gains.last().unwrap();
+        let gain = piece_value(on_square.class) - previous;
+        gains.push(gain);
+        if i32::max(-previous, gain) < 0 {
+            break;
+        }
+        board[pos.y as usize][pos.x as usize] = None;
+        board[to.y as usize][to.x as usize] = Some(attacker);
+        on_square = attacker;
+        side = !side;
+    }
+
+    // Either side can stop capturing when continuing would lose material
+    while gains.len() > 1 {
+        let last = gains.pop().unwrap();
+        let previous = gains.last_mut().unwrap();
+        *previous = -i32::max(-*previous, last);
+    }
+    gains[0]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fen::parse_fen, moves::parse_coordinate, utils::parse_pos_notation};
+
+    fn see_of(fen: &str, text: &str) -> i32 {
+        let game_state = parse_fen(fen).unwrap();
+        let mov = parse_coordinate(text, &game_state, false).unwrap();
+        see(&game_state, &mov)
+    }
+
+    #[test]
+    fn single_captures() {
+        assert_eq!(see_of("4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1", "e4d5"), 100);
+        assert_eq!(see_of("4k3/8/2p5/3p4/4P3/8/8/4K3 w - - 0 1", "e4d5"), 0);
+        assert_eq!(see_of("4k3/8/2p5/3p4/8/8/8/3QK3 w - - 0 1", "d1d5"), -800);
+        assert_eq!(see_of("4k3/8/4p3/3p4/8/4N3/8/4K3 w - - 0 1", "e3d5"), -200);
+    }
+
+    #[test]
+    fn sliders_behind_the_capture() {
+        // Rook takes, rook takes back, the second rook takes again
+        assert_eq!(see_of("4k3/3r4/8/3p4/8/8/3R4/3RK3 w - - 0 1", "d2d5"), 100);
+        // Black's queen behind its rook joins in, so white stops after one
+        assert_eq!(
+            see_of("3qk3/3r4/8/3p4/8/8/3R4/3RK3 w - - 0 1", "d2d5"),
+            -400
+        );
+    }
+
+    #[test]
+    fn special_moves() {
+        assert_eq!(see_of("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6"), 100);
+        assert_eq!(see_of("4k3/P7/8/8/8/8/8/4K3 w - - 0 1", "a7a8q"), 800);
+        assert_eq!(see_of("1r2k3/P7/8/8/8/8/8/4K3 w - - 0 1", "a7a8q"), -100);
+        assert_eq!(see_of("4k3/8/8/4p3/8/8/8/3QK3 w - - 0 1", "d1d4"), -900);
+        assert_eq!(see_of("4k3/8/8/8/8/8/8/3QK3 w - - 0 1", "d1d4"), 0);
+    }
+
+    #[test]
+    fn attacks() {
+        let game_state = parse_fen("4k3/8/8/8/2b5/8/8/4K3 w - - 0 1").unwrap();
+        let square = |text: &str| parse_pos_notation(text, false).unwrap();
+        assert!(is_attacked(&game_state.board, square("e2"), true));
+        assert!(!is_attacked(&game_state.board, square("e3"), true));
+        assert!(is_attacked(&game_state.board, square("d7"), true));
+        assert!(is_attacked(&game_state.board, square("d2"), false));
+    }
+}
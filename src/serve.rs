@@ -0,0 +1,314 @@
+use std::{sync::Mutex, thread, time::Duration};
+
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::{
+    fen::{parse_fen, to_fen},
+    gamestate::GameState,
+    moves::parse_coordinate,
+    simulation::{
+        analyse_with_limits, mate_moves, Progress, SearchLimits, SearchOptions, MATE_SCORE,
+        MAX_DEPTH,
+    },
+};
+
+// Used when a request gives neither a depth nor a time
+const DEFAULT_DEPTH: u8 = 6;
+
+// Threads answering requests. Further requests wait in a queue
+const WORKERS: usize = 4;
+
+fn position(body: &Value) -> Result<GameState, String> {
+    match body["fen"].as_str() {
+        Some(fen) => parse_fen(fen),
+        None => Err(String::from("Missing \"fen\"")),
+    }
+}
+
+// Coordinate notation of every legal move for the side to move
+fn legal_moves(body: &Value) -> Result<Value, String> {
+    let game_state = position(body)?;
+    let moves: Vec<String> = game_state
+        .get_legal_moves(false)
+        .iter()
+        .map(|mov| mov.coordinate(game_state.engine_black))
+        .collect();
+    Ok(json!({ "moves": moves }))
+}
+
+fn apply_move(body: &Value) -> Result<Value, String> {
+    let game_state = position(body)?;
+    let text = body["move"]
+        .as_str()
+        .ok_or_else(|| String::from("Missing \"move\""))?;
+    let mov = parse_coordinate(text, &game_state, false)
+        .filter(|mov| game_state.is_legal(mov, &game_state.perform_move(mov)))
+        .ok_or_else(|| format!("Illegal move: {}", text))?;
+    let next_state = game_state.perform_move(&mov).flipped();
+    Ok(json!({
+        "fen": to_fen(&next_state),
+        "move": mov.coordinate(game_state.engine_black),
+    }))
+}
+
+// Searches run one at a time, each using every search thread
+fn analyse(body: &Value, options: &SearchOptions, search: &Mutex<()>) -> Result<Value, String> {
+    let game_state = position(body)?;
+    let depth = match &body["depth"] {
+        Value::Null => None,
+        depth => Some(
+            depth
+                .as_u64()
+                .filter(|d| (1..=MAX_DEPTH as u64).contains(d))
+                .ok_or_else(|| format!("\"depth\" must be between 1 and {}", MAX_DEPTH))?
+                as u8,
+        ),
+    };
+    let time = match &body["movetime"] {
+        Value::Null => None,
+        ms => Some(Duration::from_millis(ms.as_u64().ok_or_else(|| {
+            String::from("\"movetime\" must be a non-negative number of ms")
+        })?)),
+    };
+
+    let mut limits = SearchLimits::depth(match (depth, time) {
+        (Some(depth), _) => depth,
+        (None, Some(_)) => MAX_DEPTH,
+        (None, None) => DEFAULT_DEPTH,
+    });
+    limits.time = time;
+    let analysis = {
+        let _searching = search.lock().unwrap_or_else(|e| e.into_inner());
+        analyse_with_limits(&game_state, &limits, options)
+    };
+
+    // Mated, or stalemated, already
+    if analysis.engine_no_moves {
+        let score = if game_state.in_check(false) {
+            -MATE_SCORE
+        } else {
+            0
+        };
+        return Ok(json!({
+            "bestmove": null,
+            "score": score,
+            "mate": mate_moves(score),
+            "pv": [],
+            "depth": 0,
+            "nodes": analysis.sim_moves,
+        }));
+    }
+
+    let engine_black = game_state.engine_black;
+    let best_move = analysis
+        .move_scores
+        .first()
+        .map(|score| score.mov.coordinate(engine_black));
+    Ok(json!({
+        "bestmove": best_move,
+        "score": analysis.score,
+        "mate": mate_moves(analysis.score),
+        "pv": analysis
+            .pv
+            .iter()
+            .map(|mov| mov.coordinate(engine_black))
+            .collect::<Vec<_>>(),
+        "depth": analysis.depth,
+        "nodes": analysis.sim_moves,
+    }))
+}
+
+// Status code and body for a request
+fn handle(request: &mut Request, options: &SearchOptions, search: &Mutex<()>) -> (u16, Value) {
+    if *request.method() != Method::Post {
+        return (405, json!({ "error": "Only POST is supported" }));
+    }
+    let mut text = String::new();
+    if request.as_reader().read_to_string(&mut text).is_err() {
+        return (400, json!({ "error": "Body is not valid UTF-8" }));
+    }
+    let body = match serde_json::from_str::<Value>(&text) {
+        Ok(body) => body,
+        Err(e) => return (400, json!({ "error": format!("Invalid JSON: {}", e) })),
+    };
+    let result = match request.url() {
+        "/analyse" => analyse(&body, options, search),
+        "/legal-moves" => legal_moves(&body),
+        "/move" => apply_move(&body),
+        url => {
+            return (
+                404,
+                json!({ "error": format!("No such endpoint: {}", url) }),
+            )
+        }
+    };
+    match result {
+        Ok(value) => (200, value),
+        Err(e) => (400, json!({ "error": e })),
+    }
+}
+
+fn respond(mut request: Request, options: &SearchOptions, search: &Mutex<()>) {
+    let (status, value) = handle(&mut request, options, search);
+    let response = Response::from_string(value.to_string())
+        .with_status_code(status)
+        .with_header(Header::from_bytes("Content-Type", "application/json").unwrap());
+    let _ = request.respond(response);
+}
+
+// A fixed set of workers takes requests off the server, so the moves
+// endpoints stay quick while a search is running
+fn run(server: &Server, options: &SearchOptions) {
+    let mut options = *options;
+    options.progress = Progress::Quiet;
+    let search = Mutex::new(());
+    thread::scope(|scope| {
+        for _ in 0..WORKERS {
+            scope.spawn(|| {
+                for request in server.incoming_requests() {
+                    respond(request, &options, &search);
+                }
+            });
+        }
+    });
+}
+
+pub fn serve(port: u16, options: &SearchOptions) {
+    let address = format!("127.0.0.1:{}", port);
+    let server = match Server::http(&address) {
+        Ok(server) => server,
+        Err(e) => {
+            println!("Couldn't listen on {}: {}", address, e);
+            return;
+        }
+    };
+    println!("Listening on http://{}", address);
+    run(&server, options);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpStream,
+        sync::OnceLock,
+    };
+
+    use super::*;
+
+    // One server for every test, on a port picked by the system
+    fn address() -> String {
+        static ADDRESS: OnceLock<String> = OnceLock::new();
+        ADDRESS
+            .get_or_init(|| {
+                let server = Server::http("127.0.0.1:0").unwrap();
+                let address = server.server_addr().to_ip().unwrap().to_string();
+                let options = SearchOptions {
+                    threads: 1,
+                    ..SearchOptions::default()
+                };
+                thread::spawn(move || run(&server, &options));
+                address
+            })
+            .clone()
+    }
+
+    fn post(url: &str, body: &str) -> (u16, Value) {
+        let mut stream = TcpStream::connect(address()).unwrap();
+        write!(
+            stream,
+            "POST {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            url,
+            body.len(),
+            body
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.split_whitespace().nth(1).unwrap().parse().unwrap();
+        (status, serde_json::from_str(body).unwrap())
+    }
+
+    #[test]
+    fn legal_moves_endpoint() {
+        let (status, body) = post(
+            "/legal-moves",
+            r#"{"fen": "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"}"#,
+        );
+        assert_eq!(status, 200);
+        let moves = body["moves"].as_array().unwrap();
+        assert_eq!(moves.len(), 20);
+        assert!(moves.contains(&json!("g1f3")));
+
+        let (status, body) = post("/legal-moves", r#"{"fen": "8/8/8/8/8/8/8/8 w - - 0 1"}"#);
+        assert_eq!(status, 400);
+        assert!(body["error"].is_string());
+    }
+
+    #[test]
+    fn move_endpoint() {
+        let (status, body) = post(
+            "/move",
+            r#"{"fen": "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 5 20", "move": "e2e4"}"#,
+        );
+        assert_eq!(status, 200);
+        assert_eq!(
+            body["fen"],
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 20"
+        );
+
+        let (status, body) = post(
+            "/move",
+            r#"{"fen": "8/4P3/8/8/8/8/k7/4K3 w - - 0 1", "move": "e7e8n"}"#,
+        );
+        assert_eq!(status, 200);
+        assert_eq!(body["fen"], "4N3/8/8/8/8/8/k7/4K3 b - - 0 1");
+
+        for mov in ["e7e8k", "e7e8p", "e7eé", "e2e4", "e7e8"] {
+            let body = json!({ "fen": "8/4P3/8/8/8/8/k7/4K3 w - - 0 1", "move": mov });
+            let (status, body) = post("/move", &body.to_string());
+            assert_eq!(status, 400, "{}", mov);
+            assert!(body["error"].is_string(), "{}", mov);
+        }
+    }
+
+    #[test]
+    fn analyse_endpoint() {
+        let (status, body) = post(
+            "/analyse",
+            r#"{"fen": "6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1", "depth": 3}"#,
+        );
+        assert_eq!(status, 200);
+        assert_eq!(body["bestmove"], "d1d8");
+        assert_eq!(body["mate"], 1);
+        assert_eq!(body["depth"], 3);
+
+        let (status, _) = post(
+            "/analyse",
+            r#"{"fen": "6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1", "depth": 0}"#,
+        );
+        assert_eq!(status, 400);
+        let (status, body) = post(
+            "/analyse",
+            r#"{"fen": "3R2k1/5ppp/8/8/8/8/5PPP/6K1 b - - 1 1", "depth": 3}"#,
+        );
+        assert_eq!(status, 200);
+        assert_eq!(body["bestmove"], Value::Null);
+        assert_eq!(body["score"], -MATE_SCORE);
+        assert_eq!(body["mate"], 0);
+
+        let (status, body) = post(
+            "/analyse",
+            r#"{"fen": "7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", "depth": 3}"#,
+        );
+        assert_eq!(status, 200);
+        assert_eq!(body["bestmove"], Value::Null);
+        assert_eq!(body["score"], 0);
+        assert_eq!(body["mate"], Value::Null);
+
+        let (status, _) = post("/unknown", "{}");
+        assert_eq!(status, 404);
+    }
+}
@@ -11,12 +11,39 @@ pub const GAME_LOOP: bool = true;
 // All castling possibilities set to following
 pub const CASTLING: bool = true;
 
-pub const THREADING: bool = true;
+// Search threads, 0 uses every available core
+pub const THREADS: usize = 0;
+
+// Transposition table shared between search threads
+pub const HASH_SIZE_MB: usize = 32;
+
+// Unicode pieces on coloured squares when printing to a terminal, else ASCII
+pub const UNICODE_BOARD: bool = true;
+
+// Search depth in the interactive game until changed with depth or time
+pub const SEARCH_DEPTH: u8 = 5;
+
+// Search the expected reply while waiting for the opponent's move
+pub const PONDER: bool = true;
+
+// Search pruning defaults, each can be turned off from the command line
+pub const NULL_MOVE_PRUNING: bool = true;
+pub const LATE_MOVE_REDUCTIONS: bool = true;
+pub const FUTILITY_PRUNING: bool = true;
 
 // Only affects development, not simulation
 // Between 0 and 1
 pub const RANDOM_FACTOR: f32 = 0.35;
 
+// Evaluation weights, loaded at startup if present and written by `tune`
+pub const PARAMS_FILE: &str = "eval_params.txt";
+
+// Polyglot opening book, used if present unless --no-book is given
+pub const BOOK_FILE: &str = "book.bin";
+
+// Directory of Syzygy tablebase files, used if present unless --syzygy is given
+pub const SYZYGY_PATH: &str = "syzygy";
+
 // Standard layout, else one below
 pub const STANDARD_BOARD: bool = true;
 
@@ -1,211 +1,976 @@
-use std::{thread, time::Instant};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::Sender,
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
 
 use crate::{
+    endgame::{endgame_evaluation, insufficient_material},
+    evaluation::evaluate,
     gamestate::GameState,
-    moves::Move,
-    pieces::{SCORE_BOUND, SCORE_RANGE},
-    settings::THREADING,
+    moves::{Move, MoveType},
+    pieces::PieceClass,
+    see::{is_capture, see},
+    settings::{FUTILITY_PRUNING, LATE_MOVE_REDUCTIONS, NULL_MOVE_PRUNING, THREADS},
+    skill::Skill,
+    syzygy::{tablebases, WDL_LOSS, WDL_WIN},
+    transposition::{table, Bound, Entry},
+    zobrist::hash,
 };
 
+// Mate in n plies scores MATE_SCORE - n
+pub const MATE_SCORE: i32 = 30000;
+// Tablebase win in n plies scores TB_WIN_SCORE - n, below any mate
+pub const TB_WIN_SCORE: i32 = MATE_SCORE - 2000;
+const INFINITY: i32 = 32000;
+
+// Margins per ply of remaining depth, in centipawns
+const FUTILITY_MARGIN: i32 = 150;
+const REVERSE_FUTILITY_MARGIN: i32 = 120;
+
+// Initial half width of the window around the previous iteration's score
+const ASPIRATION_WINDOW: i32 = 50;
+
+// Depth used when only a node or mate limit is given
+pub const MAX_DEPTH: u8 = 64;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Progress {
+    Quiet,
+    // Readable lines for the terminal
+    Text,
+    // Thinking output as expected by XBoard
+    Xboard,
+    // "info" lines for a UCI GUI, one per line with MultiPV
+    Uci,
+}
+
+#[derive(Copy, Clone)]
+pub struct SearchOptions {
+    pub null_move: bool,
+    pub late_move_reductions: bool,
+    pub futility: bool,
+    // Threads searching together, sharing the transposition table
+    pub threads: usize,
+    // How to print the score and continuation after each iteration
+    pub progress: Progress,
+    // Root moves given an exact score and line, best first
+    pub multi_pv: usize,
+    // Weakens the moves played, not the search itself
+    pub skill: Skill,
+    // For the random choices made in each game, a fresh one per game if None
+    pub seed: Option<u64>,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        let threads = if THREADS == 0 {
+            thread::available_parallelism().map_or(1, |n| n.get())
+        } else {
+            THREADS
+        };
+        Self {
+            null_move: NULL_MOVE_PRUNING,
+            late_move_reductions: LATE_MOVE_REDUCTIONS,
+            futility: FUTILITY_PRUNING,
+            threads,
+            progress: Progress::Text,
+            multi_pv: 1,
+            skill: Skill::default(),
+            seed: None,
+        }
+    }
+}
+
+// When to stop searching, whichever limit is reached first. The stop flag can
+// be set from another thread to end the search early
+#[derive(Clone)]
+pub struct SearchLimits {
+    // Plies
+    pub depth: u8,
+    // Moves simulated by the main search thread
+    pub nodes: Option<u64>,
+    // Stop once a mate in this many moves is found
+    pub mate: Option<u8>,
+    pub time: Option<Duration>,
+    // No further iteration is started after this long, as set by the clock
+    pub soft_time: Option<Duration>,
+    pub stop: Arc<AtomicBool>,
+    // Sent each completed iteration, for a display on another thread
+    pub listener: Option<Sender<SearchInfo>>,
+}
+
+// Best line after an iteration of the main search thread
+pub struct SearchInfo {
+    pub depth: u8,
+    pub score: i32,
+    pub pv: Vec<Move>,
+    pub nodes: u64,
+    pub ms: u128,
+}
+
+impl SearchLimits {
+    pub fn depth(depth: u8) -> Self {
+        Self {
+            depth,
+            nodes: None,
+            mate: None,
+            time: None,
+            soft_time: None,
+            stop: Arc::new(AtomicBool::new(false)),
+            listener: None,
+        }
+    }
+
+    // "5", "depth 5", "nodes 20000", "mate 3", "movetime 1000" (ms), optionally
+    // after "go"
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut tokens: Vec<&str> = text.split_whitespace().collect();
+        if tokens.first() == Some(&"go") {
+            tokens.remove(0);
+        }
+        if let [depth] = tokens[..] {
+            return depth.parse().ok().map(SearchLimits::depth);
+        }
+        if tokens.is_empty() || !tokens.len().is_multiple_of(2) {
+            return None;
+        }
+
+        let mut limits = SearchLimits::depth(0);
+        for pair in tokens.chunks(2) {
+            match pair[0] {
+                "depth" => limits.depth = pair[1].parse().ok()?,
+                "nodes" => limits.nodes = Some(pair[1].parse().ok()?),
+                "mate" => limits.mate = Some(pair[1].parse().ok()?),
+                "movetime" => limits.time = Some(Duration::from_millis(pair[1].parse().ok()?)),
+                _ => return None,
+            }
+        }
+        if limits.depth == 0 {
+            // A mate in n moves needs at most 2n - 1 plies
+            limits.depth = match (limits.nodes, limits.mate, limits.time) {
+                (None, Some(mate), None) => {
+                    (2 * mate as u32).saturating_sub(1).min(MAX_DEPTH as u32) as u8
+                }
+                _ => MAX_DEPTH,
+            };
+        }
+        Some(limits)
+    }
+
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+#[derive(Clone)]
+pub struct MoveScore {
+    pub mov: Move,
+    // Centipawns for the engine
+    pub score: i32,
+    // False if the move was refuted early, making the score an upper bound
+    pub exact: bool,
+    // Expected continuation, starting with this move
+    pub pv: Vec<Move>,
+}
+
+pub struct PvLine {
+    pub score: i32,
+    pub depth: u8,
+    pub pv: Vec<Move>,
+}
+
 pub struct AnalysisResult {
-    pub best_moves: Option<Vec<Move>>,
-    pub score_buffer: u64,
-    pub end_score: i16,
-    pub opponent_in_check: bool,
+    pub best_moves: Vec<Move>,
+    // Every valid root move, best first
+    pub move_scores: Vec<MoveScore>,
+    pub score: i32,
+    // Principal variation of the first best move
+    pub pv: Vec<Move>,
+    // The best multi_pv root moves with their own lines
+    pub lines: Vec<PvLine>,
+    // Last iteration completed before a limit or stop
+    pub depth: u8,
     pub engine_no_moves: bool,
-    pub sim_moves: u32,
+    pub sim_moves: u64,
     pub valid_moves: u32,
 }
 
-pub fn analyse(game_state: &GameState, depth: u8, root: bool) -> AnalysisResult {
-    assert!(game_state.kings_alive);
+struct SearchContext<'a> {
+    options: SearchOptions,
+    // Helper threads are told to give up once the main thread finishes
+    stop: &'a AtomicBool,
+    node_limit: Option<u64>,
+    deadline: Option<Instant>,
+    soft_deadline: Option<Instant>,
+    listener: Option<Sender<SearchInfo>>,
+    sim_moves: u64,
+    // Set while searching the reply to a null move, so two aren't played in a row
+    after_null: bool,
+    // Triangular table, pv[ply] is the best line found from that ply
+    pv: Vec<Vec<Move>>,
+    // Line from the previous iteration, tried first at each ply
+    previous_pv: Vec<Move>,
+}
 
-    if depth == 0 {
-        return AnalysisResult {
-            best_moves: None,
-            score_buffer: game_state.get_normalized(),
-            end_score: game_state.score,
-            opponent_in_check: false,
-            engine_no_moves: false,
+impl<'a> SearchContext<'a> {
+    fn new(options: SearchOptions, stop: &'a AtomicBool) -> Self {
+        Self {
+            options,
+            stop,
+            node_limit: None,
+            deadline: None,
+            soft_deadline: None,
+            listener: None,
             sim_moves: 0,
-            valid_moves: 0,
-        };
+            after_null: false,
+            pv: Vec::new(),
+            previous_pv: Vec::new(),
+        }
     }
 
-    let engine_possible_moves: Vec<Move> = game_state.get_possible_moves(false);
-
-    let total_outer = engine_possible_moves.len();
-    let mut completed_outer: usize = 0;
-    let timer = Instant::now();
-
-    let mut sim_moves: u32 = 0;
-    let mut valid_moves: u32 = 0;
+    fn stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+            || self.node_limit.is_some_and(|n| self.sim_moves >= n)
+            || self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+    }
 
-    let mut best_moves: Vec<Move> = Vec::new();
-    let mut best_score_buffer = 0;
+    fn clear_pv(&mut self, ply: usize) {
+        if self.pv.len() <= ply + 1 {
+            self.pv.resize(ply + 2, Vec::new());
+        }
+        self.pv[ply].clear();
+    }
 
-    let mut end_score = 0;
+    fn update_pv(&mut self, ply: usize, mov: Move) {
+        let mut line = vec![mov];
+        line.extend_from_slice(&self.pv[ply + 1]);
+        self.pv[ply] = line;
+    }
+}
 
-    for engine_move in engine_possible_moves {
-        let mut self_check = false;
+pub fn is_mate_score(score: i32) -> bool {
+    score.abs() > MATE_SCORE - 1000
+}
 
-        let game_state_1 = game_state.perform_move(&engine_move);
-        sim_moves += 1;
+// Mate scores are stored relative to the entry's position rather than the root
+fn score_to_table(score: i32, ply: i32) -> i32 {
+    if is_mate_score(score) {
+        score + score.signum() * ply
+    } else {
+        score
+    }
+}
 
-        // If opponent king killable, immediately return
-        if !game_state_1.kings_alive {
-            assert!(!root, "Enemy king is in check");
+fn score_from_table(score: i32, ply: i32) -> i32 {
+    if is_mate_score(score) {
+        score - score.signum() * ply
+    } else {
+        score
+    }
+}
 
-            return AnalysisResult {
-                best_moves: None,
-                score_buffer: game_state_1.get_normalized(),
-                end_score: game_state_1.score,
-                opponent_in_check: true,
-                engine_no_moves: false,
-                sim_moves,
-                valid_moves: 0,
-            };
+// Mate scores are shown as moves to mate, negative when being mated
+pub fn score_repr(score: i32) -> String {
+    if is_mate_score(score) {
+        let plies = MATE_SCORE - score.abs();
+        let moves = (plies + 1) / 2;
+        if score > 0 {
+            format!("M{}", moves)
+        } else {
+            format!("-M{}", moves)
         }
+    } else {
+        format!("{:+}", score)
+    }
+}
 
-        let opponent_possible_moves = game_state_1.get_possible_moves(true);
+// Moves to mate, negative when being mated
+pub fn mate_moves(score: i32) -> Option<i32> {
+    if is_mate_score(score) {
+        Some(score.signum() * ((MATE_SCORE - score.abs() + 1) / 2))
+    } else {
+        None
+    }
+}
 
-        let mut result_handles: Vec<thread::JoinHandle<AnalysisResult>> = Vec::new();
+// Centipawns, with mates as 100000 + moves to mate
+fn xboard_score(score: i32) -> i32 {
+    match mate_moves(score) {
+        Some(moves) => moves.signum() * 100000 + moves,
+        None => score,
+    }
+}
 
-        let mut worst_case_buffer = u64::MAX;
-        let mut cor_end_score = 0;
-        let mut found_valid_opponent_move = false;
+// "cp 35", or "mate -3" when being mated
+fn uci_score(score: i32) -> String {
+    match mate_moves(score) {
+        Some(moves) => format!("mate {}", moves),
+        None => format!("cp {}", score),
+    }
+}
 
-        // Recursively analyse resulting gamestates for all moves
-        for opponent_move in opponent_possible_moves {
-            let game_state_2 = game_state_1.perform_move(&opponent_move);
-            sim_moves += 1;
+// An info line for each of the best multi_pv root moves
+pub fn uci_info(
+    depth: i32,
+    move_scores: &[MoveScore],
+    multi_pv: usize,
+    nodes: u64,
+    ms: u128,
+    engine_black: bool,
+) -> Vec<String> {
+    move_scores
+        .iter()
+        .take(usize::max(multi_pv, 1))
+        .enumerate()
+        .map(|(i, score)| {
+            format!(
+                "info depth {} multipv {} score {} nodes {} time {} pv {}",
+                depth,
+                i + 1,
+                uci_score(score.score),
+                nodes,
+                ms,
+                pv_repr(&score.pv, engine_black)
+            )
+        })
+        .collect()
+}
 
-            if !game_state_2.kings_alive {
-                self_check = true;
-                break;
-            }
+pub fn pv_repr(pv: &[Move], engine_black: bool) -> String {
+    pv.iter()
+        .map(|mov| mov.coordinate(engine_black))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
 
-            if THREADING && root {
-                let func = move || -> AnalysisResult { analyse(&game_state_2, depth - 1, false) };
-                let handle = thread::spawn(func);
-                result_handles.push(handle);
-            } else {
-                let analysis = analyse(&game_state_2, depth - 1, false);
-                sim_moves += analysis.sim_moves;
+fn is_tactical(game_state: &GameState, mov: &Move) -> bool {
+    matches!(mov.move_type, MoveType::Promotion(..)) || is_capture(game_state, mov)
+}
 
-                if analysis.opponent_in_check {
-                    // Opponent can't put self in check
-                    continue;
+// Winning and equal captures first, then quiet moves, then losing captures
+pub fn order_moves(game_state: &GameState, moves: Vec<Move>) -> Vec<Move> {
+    let mut keyed: Vec<(i32, Move)> = moves
+        .into_iter()
+        .map(|mov| {
+            let key = if is_tactical(game_state, &mov) {
+                let exchange = see(game_state, &mov);
+                if exchange >= 0 {
+                    INFINITY + exchange
+                } else {
+                    exchange
                 }
+            } else {
+                0
+            };
+            (key, mov)
+        })
+        .collect();
+    keyed.sort_by_key(|(key, _)| -key);
+    keyed.into_iter().map(|(_, mov)| mov).collect()
+}
 
-                found_valid_opponent_move = true;
+fn side_evaluation(game_state: &GameState, enemy: bool) -> i32 {
+    let score = endgame_evaluation(game_state, enemy, evaluate(game_state));
+    if enemy {
+        -score
+    } else {
+        score
+    }
+}
 
-                if analysis.engine_no_moves {
-                    // Coule be checkmate or stalemate, reject both
-                    worst_case_buffer = 0;
-                    break;
-                }
+fn has_pieces(game_state: &GameState, enemy: bool) -> bool {
+    // Null moves are unsafe with only pawns left, as zugzwang is common
+    game_state.board.iter().flatten().flatten().any(|piece| {
+        piece.enemy == enemy && !matches!(piece.class, PieceClass::Pawn | PieceClass::King)
+    })
+}
 
-                if analysis.score_buffer < worst_case_buffer {
-                    worst_case_buffer = analysis.score_buffer;
-                    cor_end_score = analysis.end_score;
-                }
+// Captures only, scored for the side to move. Captures which lose material by
+// static exchange are skipped
+fn quiesce(
+    ctx: &mut SearchContext,
+    game_state: &GameState,
+    enemy: bool,
+    mut alpha: i32,
+    beta: i32,
+) -> i32 {
+    let stand_pat = side_evaluation(game_state, enemy);
+    if stand_pat >= beta {
+        return stand_pat;
+    }
+    alpha = i32::max(alpha, stand_pat);
+    let mut best_score = stand_pat;
+
+    let captures = game_state
+        .get_possible_moves(enemy)
+        .into_iter()
+        .filter(|mov| is_tactical(game_state, mov) && see(game_state, mov) >= 0)
+        .collect();
+    for mov in order_moves(game_state, captures) {
+        let next_state = game_state.perform_move(&mov);
+        ctx.sim_moves += 1;
+        if !game_state.is_legal(&mov, &next_state) {
+            continue;
+        }
+        let score = -quiesce(ctx, &next_state, !enemy, -beta, -alpha);
+        if score >= beta {
+            return score;
+        }
+        alpha = i32::max(alpha, score);
+        best_score = i32::max(best_score, score);
+    }
+    best_score
+}
 
-                // Prune remaining branches
-                if worst_case_buffer < best_score_buffer {
-                    break;
-                }
+// Negamax principal variation search, scored for the side to move
+fn search(
+    ctx: &mut SearchContext,
+    game_state: &GameState,
+    enemy: bool,
+    depth: i32,
+    ply: i32,
+    mut alpha: i32,
+    beta: i32,
+) -> i32 {
+    let allow_null = !std::mem::replace(&mut ctx.after_null, false);
+    ctx.clear_pv(ply as usize);
+    if ctx.stopped() {
+        return 0;
+    }
+    if depth <= 0 {
+        return quiesce(ctx, game_state, enemy, alpha, beta);
+    }
+
+    let in_check = game_state.in_check(enemy);
+    let pv_node = beta - alpha > 1;
+    let original_alpha = alpha;
+
+    let key = hash(game_state, enemy);
+    let mut table_move = None;
+    if let Some(entry) = table().probe(key) {
+        table_move = entry.move_index;
+        if !pv_node && entry.depth >= depth {
+            let score = score_from_table(entry.score, ply);
+            match entry.bound {
+                Bound::Exact => return score,
+                Bound::Lower if score >= beta => return score,
+                Bound::Upper if score <= alpha => return score,
+                _ => (),
             }
         }
+    }
+
+    if ply > 0 && insufficient_material(game_state) {
+        return 0;
+    }
 
-        // The following is similar to above block, violating DRY,
-        // but performance may be limited by using closures
+    // Perfect knowledge once few enough pieces remain. Cursed wins and
+    // blessed losses are drawn by the fifty move rule
+    if ply > 0 {
+        if let Some(wdl) = tablebases().and_then(|tb| tb.probe_wdl(game_state, enemy)) {
+            return match wdl {
+                WDL_WIN => TB_WIN_SCORE - ply,
+                WDL_LOSS => -TB_WIN_SCORE + ply,
+                _ => 0,
+            };
+        }
+    }
+    let static_eval = side_evaluation(game_state, enemy);
+
+    // Reverse futility: far enough above beta that the opponent can't recover
+    if ctx.options.futility
+        && !pv_node
+        && !in_check
+        && depth <= 3
+        && !is_mate_score(beta)
+        && static_eval - REVERSE_FUTILITY_MARGIN * depth >= beta
+    {
+        return static_eval;
+    }
 
-        // Handle threaded analysis
-        for handle in result_handles {
-            let analysis = handle.join().unwrap();
+    // Null move: if passing still fails high, a real move will too
+    if ctx.options.null_move
+        && allow_null
+        && !pv_node
+        && !in_check
+        && depth >= 3
+        && static_eval >= beta
+        && has_pieces(game_state, enemy)
+    {
+        let null_move = Move {
+            enemy,
+            move_type: MoveType::Null,
+        };
+        let null_state = game_state.perform_move(&null_move);
+        let reduction = 2 + depth / 6;
+        ctx.after_null = true;
+        let score = -search(
+            ctx,
+            &null_state,
+            !enemy,
+            depth - 1 - reduction,
+            ply + 1,
+            -beta,
+            -beta + 1,
+        );
+        if score >= beta {
+            return if is_mate_score(score) { beta } else { score };
+        }
+    }
 
-            sim_moves += analysis.sim_moves;
+    // Futility: quiet moves can't raise a hopeless score near the leaves
+    let futility_value = static_eval + FUTILITY_MARGIN * depth;
+    let futile = ctx.options.futility
+        && !pv_node
+        && !in_check
+        && depth <= 2
+        && !is_mate_score(alpha)
+        && futility_value <= alpha;
+
+    // Previous iteration's line first, then the table's best move, then the rest
+    let possible_moves = game_state.get_possible_moves(enemy);
+    let mut moves = order_moves(game_state, possible_moves.clone());
+    let first_moves = [
+        table_move.and_then(|i| possible_moves.get(i)),
+        ctx.previous_pv.get(ply as usize),
+    ];
+    for first in first_moves.into_iter().flatten() {
+        if let Some(i) = moves.iter().position(|mov| mov == first) {
+            let first = moves.remove(i);
+            moves.insert(0, first);
+        }
+    }
+    let mut best_score = -INFINITY;
+    let mut best_move = None;
+    let mut legal_moves = 0;
+
+    for mov in moves {
+        let next_state = game_state.perform_move(&mov);
+        ctx.sim_moves += 1;
+        if !game_state.is_legal(&mov, &next_state) {
+            continue;
+        }
+        legal_moves += 1;
 
-            if analysis.opponent_in_check {
-                // Opponent can't put self in check
-                continue;
-            }
+        let quiet = !is_tactical(game_state, &mov) && !next_state.in_check(!enemy);
+        if futile && legal_moves > 1 && quiet {
+            best_score = i32::max(best_score, futility_value);
+            continue;
+        }
 
-            found_valid_opponent_move = true;
+        let score = if legal_moves == 1 {
+            -search(ctx, &next_state, !enemy, depth - 1, ply + 1, -beta, -alpha)
+        } else {
+            // Late move reductions: quiet moves ordered late are searched
+            // shallower first, and only fully if they beat alpha
+            let reduction = if ctx.options.late_move_reductions
+                && depth >= 3
+                && legal_moves > 3
+                && quiet
+                && !in_check
+            {
+                if legal_moves > 8 {
+                    2
+                } else {
+                    1
+                }
+            } else {
+                0
+            };
 
-            if analysis.engine_no_moves {
-                // Coule be checkmate or stalemate, reject both
-                worst_case_buffer = 0;
-                break;
+            // Later moves only need to be shown worse than the first, so a
+            // null window is enough unless one turns out better
+            let next_depth = depth - 1 - reduction;
+            let mut score = -search(
+                ctx,
+                &next_state,
+                !enemy,
+                next_depth,
+                ply + 1,
+                -alpha - 1,
+                -alpha,
+            );
+            if score > alpha && reduction > 0 {
+                score = -search(
+                    ctx,
+                    &next_state,
+                    !enemy,
+                    depth - 1,
+                    ply + 1,
+                    -alpha - 1,
+                    -alpha,
+                );
             }
+            if score > alpha && score < beta {
+                score = -search(ctx, &next_state, !enemy, depth - 1, ply + 1, -beta, -alpha);
+            }
+            score
+        };
 
-            if analysis.score_buffer < worst_case_buffer {
-                worst_case_buffer = analysis.score_buffer;
-                cor_end_score = analysis.end_score;
+        if score > best_score {
+            best_score = score;
+            best_move = Some(mov);
+        }
+        if score > alpha {
+            alpha = score;
+            ctx.update_pv(ply as usize, mov);
+            if alpha >= beta {
+                break;
             }
         }
+    }
 
-        completed_outer += 1;
-        if root {
-            let fraction_done = completed_outer as f32 / total_outer as f32;
-            let time_left = timer.elapsed().as_secs_f32() * (1.0 / fraction_done - 1.);
-            println!(
-                "{} / {}, {} secs left",
-                completed_outer,
-                total_outer,
-                time_left.round()
-            );
-        }
+    if legal_moves == 0 {
+        // Checkmate or stalemate
+        return if in_check { -MATE_SCORE + ply } else { 0 };
+    }
+    if ctx.stopped() {
+        return best_score;
+    }
 
-        if self_check {
-            continue;
+    let bound = if best_score >= beta {
+        Bound::Lower
+    } else if best_score > original_alpha {
+        Bound::Exact
+    } else {
+        Bound::Upper
+    };
+    table().store(
+        key,
+        Entry {
+            depth,
+            score: score_to_table(best_score, ply),
+            bound,
+            move_index: best_move.and_then(|best| possible_moves.iter().position(|m| *m == best)),
+        },
+    );
+    best_score
+}
+
+// One iteration over the root moves within the given window, returning scores
+// in the order searched
+fn search_root(
+    ctx: &mut SearchContext,
+    root_moves: &[(Move, GameState)],
+    depth: i32,
+    alpha: i32,
+    beta: i32,
+) -> Vec<MoveScore> {
+    let mut move_scores: Vec<MoveScore> = Vec::new();
+    let lines = usize::max(ctx.options.multi_pv, 1);
+
+    for (engine_move, next_state) in root_moves {
+        // Searching just below the worst of the best lines keeps moves which
+        // equal it exact. Until there are enough lines every move gets the
+        // full window
+        let mut scores: Vec<i32> = move_scores.iter().map(|s| s.score).collect();
+        scores.sort_by_key(|&score| -score);
+        let full_window = scores.len() < lines;
+        let move_alpha = if full_window {
+            alpha
+        } else {
+            i32::max(alpha, scores[lines - 1] - 1)
+        };
+
+        // Null window first, as for later moves inside the search
+        let mut score = if full_window {
+            INFINITY
+        } else {
+            -search(
+                ctx,
+                next_state,
+                true,
+                depth - 1,
+                1,
+                -move_alpha - 1,
+                -move_alpha,
+            )
+        };
+        if score > move_alpha {
+            score = -search(ctx, next_state, true, depth - 1, 1, -beta, -move_alpha);
+        }
+        if ctx.stopped() {
+            // The score of an interrupted search can't be trusted
+            break;
         }
 
-        valid_moves += 1;
+        let mut pv = vec![*engine_move];
+        pv.extend_from_slice(&ctx.pv[1]);
+        move_scores.push(MoveScore {
+            mov: *engine_move,
+            score,
+            exact: score > move_alpha && score < beta,
+            pv,
+        });
+    }
+    move_scores
+}
 
-        // Trapped opponent
-        if !found_valid_opponent_move {
-            let analysis = analyse(&game_state_1, 1, false);
-            sim_moves += analysis.sim_moves;
+// Iterative deepening over the root moves, returning the scores from the last
+// completed iteration, best first, and the depth it reached
+fn iterate(
+    ctx: &mut SearchContext,
+    game_state: &GameState,
+    mut root_moves: Vec<(Move, GameState)>,
+    depth: i32,
+    mate: Option<u8>,
+) -> (Vec<MoveScore>, i32) {
+    let timer = Instant::now();
+    let mut move_scores: Vec<MoveScore> = Vec::new();
+    let mut completed = 0;
+    let mut previous_score = 0;
+
+    for iteration in 1..=depth {
+        if root_moves.is_empty() {
+            break;
+        }
+        let mut delta = ASPIRATION_WINDOW;
+        // Aspiration only narrows the window around the best line
+        let aspirate =
+            iteration >= 3 && !is_mate_score(previous_score) && ctx.options.multi_pv <= 1;
+        let (mut alpha, mut beta) = if aspirate {
+            (previous_score - delta, previous_score + delta)
+        } else {
+            (-INFINITY, INFINITY)
+        };
 
-            // If checkmate push with actual score, else worst possible
-            worst_case_buffer = if analysis.opponent_in_check {
-                // Fill buffer with score to represent depth it is acheived at
-                SCORE_RANGE.pow(depth as u32) - 1
+        let mut iteration_scores;
+        loop {
+            iteration_scores = search_root(ctx, &root_moves, iteration, alpha, beta);
+            if ctx.stopped() {
+                return stopped_scores(move_scores, iteration_scores, &root_moves, completed);
+            }
+            let best = iteration_scores.iter().map(|s| s.score).max().unwrap();
+            // Widen whichever side failed and search again
+            delta *= 2;
+            if best <= alpha && alpha > -INFINITY {
+                alpha = if delta > 8 * ASPIRATION_WINDOW {
+                    -INFINITY
+                } else {
+                    best - delta
+                };
+            } else if best >= beta && beta < INFINITY {
+                beta = if delta > 8 * ASPIRATION_WINDOW {
+                    INFINITY
+                } else {
+                    best + delta
+                };
             } else {
-                0
-            };
-            cor_end_score = analysis.end_score;
+                break;
+            }
         }
 
-        // Prioritising recurisve score followed by immediate score
-        if worst_case_buffer < best_score_buffer {
-            continue;
+        // Stable sort keeps search order between equal scores
+        completed = iteration;
+        move_scores = iteration_scores;
+        move_scores.sort_by_key(|s| -s.score);
+        previous_score = move_scores[0].score;
+        ctx.previous_pv = move_scores[0].pv.clone();
+        root_moves = move_scores
+            .iter()
+            .map(|s| (s.mov, game_state.perform_move(&s.mov)))
+            .collect();
+
+        if ctx.options.progress == Progress::Xboard {
+            println!(
+                "{} {} {} {} {}",
+                iteration,
+                xboard_score(previous_score),
+                timer.elapsed().as_millis() / 10,
+                ctx.sim_moves,
+                pv_repr(&move_scores[0].pv, game_state.engine_black)
+            );
+        } else if ctx.options.progress == Progress::Uci {
+            let lines = uci_info(
+                iteration,
+                &move_scores,
+                ctx.options.multi_pv,
+                ctx.sim_moves,
+                timer.elapsed().as_millis(),
+                game_state.engine_black,
+            );
+            for line in lines {
+                println!("{}", line);
+            }
+        } else if ctx.options.progress == Progress::Text {
+            let lines = usize::min(ctx.options.multi_pv, move_scores.len());
+            for (i, score) in move_scores.iter().take(usize::max(lines, 1)).enumerate() {
+                let line = if lines > 1 {
+                    format!(" line {}", i + 1)
+                } else {
+                    String::new()
+                };
+                println!(
+                    "Depth {}{}: {} {} ({} ms)",
+                    iteration,
+                    line,
+                    score_repr(score.score),
+                    pv_repr(&score.pv, game_state.engine_black),
+                    timer.elapsed().as_millis()
+                );
+            }
+        }
+        if let Some(listener) = &ctx.listener {
+            let _ = listener.send(SearchInfo {
+                depth: iteration as u8,
+                score: previous_score,
+                pv: move_scores[0].pv.clone(),
+                nodes: ctx.sim_moves,
+                ms: timer.elapsed().as_millis(),
+            });
         }
-        if worst_case_buffer > best_score_buffer {
-            best_moves.clear();
-            best_score_buffer = worst_case_buffer;
-            end_score = cor_end_score;
+
+        if let Some(mate) = mate {
+            if previous_score >= MATE_SCORE - (2 * mate as i32 - 1) {
+                break;
+            }
         }
-        if root {
-            best_moves.push(engine_move);
+        // Time is left over for the rest of the game rather than starting an
+        // iteration that would likely be cut short, and a forced move needs
+        // no thought
+        if let Some(deadline) = ctx.soft_deadline {
+            if Instant::now() >= deadline || root_moves.len() == 1 {
+                break;
+            }
         }
     }
+    (move_scores, completed)
+}
 
-    let engine_no_moves = valid_moves == 0;
+// Falls back on a partly searched first iteration, or failing that the first
+// root move, so there is always a move to play
+fn stopped_scores(
+    move_scores: Vec<MoveScore>,
+    mut partial: Vec<MoveScore>,
+    root_moves: &[(Move, GameState)],
+    completed: i32,
+) -> (Vec<MoveScore>, i32) {
+    if !move_scores.is_empty() {
+        return (move_scores, completed);
+    }
+    if partial.is_empty() {
+        partial = root_moves
+            .iter()
+            .take(1)
+            .map(|(mov, _)| MoveScore {
+                mov: *mov,
+                score: 0,
+                exact: false,
+                pv: vec![*mov],
+            })
+            .collect();
+    }
+    partial.sort_by_key(|s| -s.score);
+    (partial, completed)
+}
 
-    assert!(!root || engine_no_moves || !best_moves.is_empty());
+pub fn analyse(game_state: &GameState, depth: u8, options: &SearchOptions) -> AnalysisResult {
+    analyse_with_limits(game_state, &SearchLimits::depth(depth), options)
+}
 
-    best_score_buffer = best_score_buffer * SCORE_RANGE + game_state.get_normalized();
+// Iterative deepening until a limit is reached, scores are from the engine's
+// perspective. Extra threads run the same search (Lazy SMP), only helping
+// through the shared transposition table
+pub fn analyse_with_limits(
+    game_state: &GameState,
+    limits: &SearchLimits,
+    options: &SearchOptions,
+) -> AnalysisResult {
+    let depth = i32::max(limits.depth as i32, 1);
+    let stop_helpers = AtomicBool::new(false);
+    let mut ctx = SearchContext::new(*options, &limits.stop);
+    ctx.node_limit = limits.nodes;
+    ctx.deadline = limits.time.map(|time| Instant::now() + time);
+    ctx.soft_deadline = limits.soft_time.map(|time| Instant::now() + time);
+    ctx.listener = limits.listener.clone();
+
+    let mut root_moves = Vec::new();
+    for mov in order_moves(game_state, game_state.get_possible_moves(false)) {
+        let next_state = game_state.perform_move(&mov);
+        ctx.sim_moves += 1;
+        if game_state.is_legal(&mov, &next_state) {
+            root_moves.push((mov, next_state));
+        }
+    }
+    let valid_moves = root_moves.len() as u32;
+
+    // Only search the moves that keep the tablebase result, unless every
+    // move is wanted for several lines
+    if options.multi_pv <= 1 {
+        let moves: Vec<Move> = root_moves.iter().map(|(mov, _)| *mov).collect();
+        if let Some(keep) = tablebases().and_then(|tb| tb.root_moves(game_state, &moves)) {
+            root_moves.retain(|(mov, _)| keep.contains(mov));
+        }
+    }
+
+    let (move_scores, completed) = thread::scope(|scope| {
+        let helpers: Vec<_> = (1..options.threads)
+            .map(|id| {
+                let mut helper_moves = root_moves.clone();
+                let stop = &stop_helpers;
+                scope.spawn(move || {
+                    let mut helper_options = *options;
+                    helper_options.progress = Progress::Quiet;
+                    let mut helper = SearchContext::new(helper_options, stop);
+                    // Vary the root order and depth so helpers don't all
+                    // search the same moves in step
+                    if !helper_moves.is_empty() {
+                        let len = helper_moves.len();
+                        helper_moves.rotate_left(id % len);
+                    }
+                    iterate(
+                        &mut helper,
+                        game_state,
+                        helper_moves,
+                        depth + (id % 2) as i32,
+                        None,
+                    );
+                    helper.sim_moves
+                })
+            })
+            .collect();
+
+        let result = iterate(&mut ctx, game_state, root_moves, depth, limits.mate);
+        stop_helpers.store(true, Ordering::Relaxed);
+        for helper in helpers {
+            ctx.sim_moves += helper.join().unwrap();
+        }
+        result
+    });
+
+    let score = move_scores.first().map_or(-INFINITY, |s| s.score);
+    let best_moves = move_scores
+        .iter()
+        .filter(|s| s.score == score)
+        .map(|s| s.mov)
+        .collect();
+    let pv = move_scores.first().map_or(Vec::new(), |s| s.pv.clone());
+    let lines = move_scores
+        .iter()
+        .take(usize::max(options.multi_pv, 1))
+        .map(|s| PvLine {
+            score: s.score,
+            depth: completed as u8,
+            pv: s.pv.clone(),
+        })
+        .collect();
 
     AnalysisResult {
-        best_moves: if root { Some(best_moves) } else { None },
-        score_buffer: best_score_buffer,
-        end_score,
-        opponent_in_check: false,
-        engine_no_moves,
-        sim_moves,
+        best_moves,
+        move_scores,
+        score,
+        pv,
+        lines,
+        depth: completed as u8,
+        engine_no_moves: valid_moves == 0,
+        sim_moves: ctx.sim_moves,
         valid_moves,
     }
 }
@@ -0,0 +1,289 @@
+use rand::Rng;
+
+use crate::{
+    fen::parse_fen,
+    gamestate::GameState,
+    simulation::{is_mate_score, MoveScore, SearchLimits, SearchOptions},
+};
+
+pub const MAX_SKILL: u8 = 20;
+
+// Rough ratings for skill 0 and full strength, spread evenly between
+pub const MIN_ELO: u32 = 800;
+pub const MAX_ELO: u32 = 2400;
+
+// Root moves given exact scores to choose between when weakened
+const CANDIDATES: usize = 4;
+
+// Playing strength from 0 to MAX_SKILL, which is unweakened
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct Skill(u8);
+
+impl Default for Skill {
+    fn default() -> Self {
+        Skill(MAX_SKILL)
+    }
+}
+
+impl Skill {
+    pub fn new(level: u8) -> Option<Self> {
+        (level <= MAX_SKILL).then_some(Skill(level))
+    }
+
+    pub fn from_elo(elo: u32) -> Self {
+        let elo = elo.clamp(MIN_ELO, MAX_ELO);
+        let level = (elo - MIN_ELO) * MAX_SKILL as u32 / (MAX_ELO - MIN_ELO);
+        Skill(level as u8)
+    }
+
+    pub fn level(&self) -> u8 {
+        self.0
+    }
+
+    pub fn elo(&self) -> u32 {
+        MIN_ELO + self.0 as u32 * (MAX_ELO - MIN_ELO) / MAX_SKILL as u32
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.0 == MAX_SKILL
+    }
+
+    // Levels below full strength
+    fn weakness(&self) -> i32 {
+        (MAX_SKILL - self.0) as i32
+    }
+
+    // Depth 1 and a thousand nodes at skill 0, doubling the nodes every two
+    // levels
+    pub fn limit(&self, limits: &SearchLimits) -> SearchLimits {
+        let mut limits = limits.clone();
+        if !self.is_full() {
+            limits.depth = limits.depth.min(1 + self.0 / 2);
+            let nodes = 1000 << (self.0 / 2);
+            limits.nodes = Some(limits.nodes.map_or(nodes, |n| n.min(nodes)));
+        }
+        limits
+    }
+
+    // Enough lines searched exactly to pick a weaker move from
+    pub fn options(&self, options: &SearchOptions) -> SearchOptions {
+        let mut options = *options;
+        if !self.is_full() {
+            options.multi_pv = options.multi_pv.max(CANDIDATES);
+        }
+        options
+    }
+
+    // Picks among the best exactly scored moves, after adding noise to their
+    // scores, with a chance falling off exponentially with the centipawns
+    // lost. Both grow as the level drops, full strength plays the best move
+    pub fn choose<'a>(
+        &self,
+        move_scores: &'a [MoveScore],
+        rng: &mut impl Rng,
+    ) -> Option<&'a MoveScore> {
+        let candidates: Vec<&MoveScore> = move_scores
+            .iter()
+            .filter(|score| score.exact)
+            .take(CANDIDATES)
+            .collect();
+        if self.is_full() || candidates.len() < 2 {
+            return move_scores.first();
+        }
+
+        // Two uniform draws make small errors likelier than large ones
+        let noise = self.weakness() * 4;
+        let scores: Vec<i32> = candidates
+            .iter()
+            .map(|candidate| {
+                if is_mate_score(candidate.score) {
+                    candidate.score
+                } else {
+                    candidate.score + rng.gen_range(-noise..=noise) + rng.gen_range(-noise..=noise)
+                }
+            })
+            .collect();
+        let best = *scores.iter().max().unwrap();
+        let temperature = (self.weakness() * 6) as f64;
+        let weights: Vec<f64> = scores
+            .iter()
+            .map(|&score| (-((best - score) as f64) / temperature).exp())
+            .collect();
+
+        let mut pick = rng.gen::<f64>() * weights.iter().sum::<f64>();
+        for (candidate, weight) in candidates.iter().zip(&weights) {
+            if pick < *weight {
+                return Some(candidate);
+            }
+            pick -= weight;
+        }
+        candidates.last().copied()
+    }
+}
+
+// Material the engine gives up from the start
+#[derive(Copy, Clone)]
+pub enum Handicap {
+    // Black without the f pawn, so the player also has the first move
+    PawnAndMove,
+    // White without the queen's knight
+    Knight,
+    // White without the queen
+    Queen,
+}
+
+impl Handicap {
+    pub fn parse(text: &str) -> Option<Self> {
+        Some(match text {
+            "pawn" => Handicap::PawnAndMove,
+            "knight" => Handicap::Knight,
+            "queen" => Handicap::Queen,
+            _ => return None,
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Handicap::PawnAndMove => "pawn and move",
+            Handicap::Knight => "knight odds",
+            Handicap::Queen => "queen odds",
+        }
+    }
+
+    // Colour the engine plays, the side giving the odds
+    pub fn engine_black(&self) -> bool {
+        matches!(self, Handicap::PawnAndMove)
+    }
+
+    pub fn start(&self) -> GameState {
+        let fen = match self {
+            Handicap::PawnAndMove => "rnbqkbnr/ppppp1pp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            Handicap::Knight => "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/R1BQKBNR w KQkq - 0 1",
+            Handicap::Queen => "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNB1KBNR w KQkq - 0 1",
+        };
+        parse_fen(fen).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fen::to_fen, pgn::START_FEN, pieces::PieceClass, rng::GameRng};
+
+    // The first legal moves of the start position with the given scores
+    fn move_scores(scores: &[(i32, bool)]) -> Vec<MoveScore> {
+        let moves = parse_fen(START_FEN).unwrap().get_legal_moves(false);
+        scores
+            .iter()
+            .zip(moves)
+            .map(|(&(score, exact), mov)| MoveScore {
+                mov,
+                score,
+                exact,
+                pv: Vec::new(),
+            })
+            .collect()
+    }
+
+    fn chosen(skill: Skill, move_scores: &[MoveScore], seed: u64) -> usize {
+        let mut rng = GameRng::new(Some(seed));
+        let choice = skill.choose(move_scores, &mut rng).unwrap();
+        move_scores
+            .iter()
+            .position(|s| s.mov == choice.mov)
+            .unwrap()
+    }
+
+    #[test]
+    fn levels_and_ratings() {
+        assert!(Skill::new(21).is_none());
+        assert!(Skill::new(20).unwrap().is_full());
+        assert!(Skill::default().is_full());
+        assert_eq!(Skill::from_elo(500).level(), 0);
+        assert_eq!(Skill::from_elo(1600).level(), 10);
+        assert_eq!(Skill::from_elo(3000).level(), 20);
+        assert_eq!(Skill::new(10).unwrap().elo(), 1600);
+        for level in 0..=MAX_SKILL {
+            let skill = Skill::new(level).unwrap();
+            assert!(Skill::from_elo(skill.elo()) == skill);
+        }
+    }
+
+    #[test]
+    fn weaker_searches() {
+        let limits = SearchLimits::depth(6);
+        let full = Skill::default().limit(&limits);
+        assert_eq!((full.depth, full.nodes), (6, None));
+        let weakest = Skill::new(0).unwrap().limit(&limits);
+        assert_eq!((weakest.depth, weakest.nodes), (1, Some(1000)));
+        let middle = Skill::new(10).unwrap().limit(&limits);
+        assert_eq!((middle.depth, middle.nodes), (6, Some(32000)));
+        let mut limits = SearchLimits::depth(3);
+        limits.nodes = Some(500);
+        let middle = Skill::new(10).unwrap().limit(&limits);
+        assert_eq!((middle.depth, middle.nodes), (3, Some(500)));
+
+        let options = SearchOptions {
+            multi_pv: 1,
+            ..SearchOptions::default()
+        };
+        assert_eq!(Skill::default().options(&options).multi_pv, 1);
+        assert_eq!(
+            Skill::new(5).unwrap().options(&options).multi_pv,
+            CANDIDATES
+        );
+    }
+
+    #[test]
+    fn full_strength_plays_the_best_move() {
+        let scores = move_scores(&[(10, true), (10, true), (9, true)]);
+        for seed in 0..20 {
+            assert_eq!(chosen(Skill::default(), &scores, seed), 0);
+        }
+        // Nothing to choose between
+        let scores = move_scores(&[(10, true), (50, false)]);
+        assert_eq!(chosen(Skill::new(0).unwrap(), &scores, 0), 0);
+    }
+
+    #[test]
+    fn weaker_levels_choose_among_candidates() {
+        let weakest = Skill::new(0).unwrap();
+        let scores = move_scores(&[(0, true), (0, true), (0, true), (0, false), (0, true)]);
+        let picks: Vec<usize> = (0..50).map(|seed| chosen(weakest, &scores, seed)).collect();
+        // Only the exact scores are candidates
+        assert!(picks.iter().all(|&i| i != 3));
+        for i in [0, 1, 2, 4] {
+            assert!(picks.contains(&i));
+        }
+        // The same seed makes the same choice
+        assert_eq!(chosen(weakest, &scores, 7), chosen(weakest, &scores, 7));
+
+        // Blunders far beyond the noise are not played
+        let scores = move_scores(&[(0, true), (-3000, true), (-3000, true)]);
+        for seed in 0..50 {
+            assert_eq!(chosen(weakest, &scores, seed), 0);
+        }
+    }
+
+    #[test]
+    fn handicaps() {
+        assert!(Handicap::parse("rook").is_none());
+        let pawn = Handicap::parse("pawn").unwrap();
+        assert!(pawn.engine_black());
+        assert_eq!(
+            to_fen(&pawn.start()),
+            "rnbqkbnr/ppppp1pp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
+        );
+        let knight = Handicap::parse("knight").unwrap();
+        assert!(!knight.engine_black());
+        assert_eq!(knight.name(), "knight odds");
+        // White, the engine, has no queen
+        let queen = Handicap::parse("queen").unwrap().start();
+        assert!(queen
+            .board
+            .iter()
+            .flatten()
+            .flatten()
+            .all(|piece| { piece.class != PieceClass::Queen || piece.enemy }));
+    }
+}
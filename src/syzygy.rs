@@ -0,0 +1,1510 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use crate::{
+    gamestate::GameState,
+    moves::{Move, MoveType},
+    pieces::PieceClass,
+    see::is_capture,
+};
+
+// Syzygy tables hold win/draw/loss (WDL) and distance to zeroing move (DTZ) for
+// every position with few enough pieces. The decoding follows the format
+// written by Ronald de Man's generator
+
+// Win/draw/loss for the side to move. Cursed wins and blessed losses are
+// decided by the fifty move rule so are draws in practice
+pub const WDL_LOSS: i32 = -2;
+pub const WDL_BLESSED_LOSS: i32 = -1;
+pub const WDL_DRAW: i32 = 0;
+pub const WDL_CURSED_WIN: i32 = 1;
+pub const WDL_WIN: i32 = 2;
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xe8, 0x23, 0x5d];
+const DTZ_MAGIC: [u8; 4] = [0xd7, 0x66, 0x0c, 0xa5];
+const MAX_PIECES: usize = 7;
+
+// Flags stored per sub-table
+const FLAG_STM: u8 = 1;
+const FLAG_MAPPED: u8 = 2;
+const FLAG_WIN_PLIES: u8 = 4;
+const FLAG_LOSS_PLIES: u8 = 8;
+const FLAG_WIDE: u8 = 16;
+const FLAG_SINGLE_VALUE: u8 = 128;
+
+// Piece codes used in the files: 1 to 6 for white pawn to king, +8 for black
+const BLACK: u8 = 8;
+
+fn file_of(square: usize) -> usize {
+    square & 7
+}
+
+fn rank_of(square: usize) -> usize {
+    square >> 3
+}
+
+// Positive above the a1-h8 diagonal, negative below
+fn off_diagonal(square: usize) -> i32 {
+    rank_of(square) as i32 - file_of(square) as i32
+}
+
+// Lookup tables for turning piece placements into table indices
+struct Encoding {
+    map_pawns: [usize; 64],
+    map_b1h1h7: [usize; 64],
+    map_a1d1d4: [usize; 64],
+    map_kk: [[usize; 64]; 10],
+    binomial: [[u64; 64]; 7],
+    lead_pawn_idx: [[u64; 64]; 6],
+    lead_pawns_size: [[u64; 4]; 6],
+}
+
+fn encoding() -> &'static Encoding {
+    static ENCODING: OnceLock<Encoding> = OnceLock::new();
+    ENCODING.get_or_init(|| {
+        let mut e = Encoding {
+            map_pawns: [0; 64],
+            map_b1h1h7: [0; 64],
+            map_a1d1d4: [0; 64],
+            map_kk: [[0; 64]; 10],
+            binomial: [[0; 64]; 7],
+            lead_pawn_idx: [[0; 64]; 6],
+            lead_pawns_size: [[0; 4]; 6],
+        };
+
+        // Squares below the a1-h8 diagonal to 0..27
+        let mut code = 0;
+        for square in 0..64 {
+            if off_diagonal(square) < 0 {
+                e.map_b1h1h7[square] = code;
+                code += 1;
+            }
+        }
+
+        // The a1-d1-d4 triangle to 0..9, diagonal squares last
+        let mut diagonal = Vec::new();
+        code = 0;
+        for square in 0..=27 {
+            if off_diagonal(square) < 0 && file_of(square) <= 3 {
+                e.map_a1d1d4[square] = code;
+                code += 1;
+            } else if off_diagonal(square) == 0 && file_of(square) <= 3 {
+                diagonal.push(square);
+            }
+        }
+        for square in diagonal {
+            e.map_a1d1d4[square] = code;
+            code += 1;
+        }
+
+        // The 462 legal placements of two kings with the first in the
+        // triangle, both on the diagonal last
+        let mut both_on_diagonal = Vec::new();
+        code = 0;
+        for idx in 0..10 {
+            for s1 in 0..=27 {
+                if e.map_a1d1d4[s1] != idx || (idx == 0 && s1 != 1) {
+                    continue;
+                }
+                for s2 in 0..64 {
+                    let adjacent = file_of(s1).abs_diff(file_of(s2)) <= 1
+                        && rank_of(s1).abs_diff(rank_of(s2)) <= 1;
+                    // The second king may not go above the diagonal when the
+                    // first is on it
+                    if adjacent || (off_diagonal(s1) == 0 && off_diagonal(s2) > 0) {
+                        continue;
+                    } else if off_diagonal(s1) == 0 && off_diagonal(s2) == 0 {
+                        both_on_diagonal.push((idx, s2));
+                    } else {
+                        e.map_kk[idx][s2] = code;
+                        code += 1;
+                    }
+                }
+            }
+        }
+        for (idx, s2) in both_on_diagonal {
+            e.map_kk[idx][s2] = code;
+            code += 1;
+        }
+
+        // Ways to choose k of n squares
+        e.binomial[0][0] = 1;
+        for n in 1..64 {
+            for k in 0..7.min(n + 1) {
+                e.binomial[k][n] = if k > 0 { e.binomial[k - 1][n - 1] } else { 0 }
+                    + if k < n { e.binomial[k][n - 1] } else { 0 };
+            }
+        }
+
+        // Pawn squares a2-h7 to 0..47, the leading pawn is the one with the
+        // highest value: nearest the edge, then lowest rank
+        let mut available = 47;
+        for lead_pawns in 1..=5 {
+            for file in 0..4 {
+                let mut idx = 0;
+                for rank in 1..7 {
+                    let square = rank * 8 + file;
+                    if lead_pawns == 1 {
+                        e.map_pawns[square] = available;
+                        e.map_pawns[square ^ 7] = available - 1;
+                        available = available.saturating_sub(2);
+                    }
+                    e.lead_pawn_idx[lead_pawns][square] = idx;
+                    idx += e.binomial[lead_pawns - 1][e.map_pawns[square]];
+                }
+                e.lead_pawns_size[lead_pawns][file] = idx;
+            }
+        }
+        e
+    })
+}
+
+fn read_u16(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+// Compressed values for one side to move and leading pawn file. Positions
+// are offsets into the table's bytes
+#[derive(Clone, Default)]
+struct PairsData {
+    flags: u8,
+    min_sym_len: u8,
+    block_size: usize,
+    span: usize,
+    num_blocks: usize,
+    lowest_sym: usize,
+    btree: usize,
+    block_length: usize,
+    block_length_size: usize,
+    sparse_index: usize,
+    sparse_index_size: usize,
+    data: usize,
+    base64: Vec<u64>,
+    // Number of values, minus one, each symbol expands to
+    symlen: Vec<u8>,
+    pieces: [u8; MAX_PIECES],
+    group_idx: [u64; MAX_PIECES + 1],
+    group_len: [usize; MAX_PIECES + 1],
+    // DTZ value maps for win, loss, cursed win and blessed loss
+    map_idx: [usize; 4],
+}
+
+impl PairsData {
+    // Left and right halves of a symbol pair, 12 bits each
+    fn pair(&self, bytes: &[u8], sym: usize) -> (usize, usize) {
+        let at = self.btree + 3 * sym;
+        let (b0, b1, b2) = (
+            bytes[at] as usize,
+            bytes[at + 1] as usize,
+            bytes[at + 2] as usize,
+        );
+        ((b1 & 0xf) << 8 | b0, b2 << 4 | b1 >> 4)
+    }
+
+    fn set_symlen(&mut self, bytes: &[u8], sym: usize, visited: &mut [bool]) -> u8 {
+        visited[sym] = true;
+        let (left, right) = self.pair(bytes, sym);
+        if right == 0xfff {
+            return 0;
+        }
+        if !visited[left] {
+            self.symlen[left] = self.set_symlen(bytes, left, visited);
+        }
+        if !visited[right] {
+            self.symlen[right] = self.set_symlen(bytes, right, visited);
+        }
+        self.symlen[left]
+            .wrapping_add(self.symlen[right])
+            .wrapping_add(1)
+    }
+
+    // Reads the block sizes and Huffman code, returning where they end
+    fn set_sizes(&mut self, bytes: &[u8], mut at: usize) -> usize {
+        self.flags = bytes[at];
+        at += 1;
+        if self.flags & FLAG_SINGLE_VALUE != 0 {
+            // Every position has the value stored here
+            self.min_sym_len = bytes[at];
+            return at + 1;
+        }
+
+        let end = self.group_len.iter().position(|&len| len == 0).unwrap();
+        let table_size = self.group_idx[end];
+        self.block_size = 1 << bytes[at];
+        self.span = 1 << bytes[at + 1];
+        self.sparse_index_size = table_size.div_ceil(self.span as u64) as usize;
+        let padding = bytes[at + 2] as usize;
+        self.num_blocks = read_u32(bytes, at + 3) as usize;
+        self.block_length_size = self.num_blocks + padding;
+        let max_sym_len = bytes[at + 7];
+        self.min_sym_len = bytes[at + 8];
+        at += 9;
+        self.lowest_sym = at;
+
+        // Canonical Huffman code: base64[i] is the lowest code of length
+        // min_sym_len + i, left aligned in 64 bits
+        let lengths = (max_sym_len - self.min_sym_len + 1) as usize;
+        self.base64 = vec![0; lengths];
+        for i in (0..lengths - 1).rev() {
+            let lowest = read_u16(bytes, self.lowest_sym + 2 * i) as u64;
+            let next_lowest = read_u16(bytes, self.lowest_sym + 2 * (i + 1)) as u64;
+            self.base64[i] = (self.base64[i + 1] + lowest).wrapping_sub(next_lowest) / 2;
+        }
+        for (i, base) in self.base64.iter_mut().enumerate() {
+            let shift = 64 - i - self.min_sym_len as usize;
+            *base = if shift >= 64 { 0 } else { *base << shift };
+        }
+        at += 2 * lengths;
+
+        let symbols = read_u16(bytes, at) as usize;
+        at += 2;
+        self.btree = at;
+        self.symlen = vec![0; symbols];
+        let mut visited = vec![false; symbols];
+        for sym in 0..symbols {
+            if !visited[sym] {
+                self.symlen[sym] = self.set_symlen(bytes, sym, &mut visited);
+            }
+        }
+        at + 3 * symbols + (symbols & 1)
+    }
+
+    fn decompress(&self, bytes: &[u8], idx: u64) -> usize {
+        if self.flags & FLAG_SINGLE_VALUE != 0 {
+            return self.min_sym_len as usize;
+        }
+
+        // The sparse index gives a block and offset near the value, then
+        // whole blocks are stepped over to reach it
+        let k = (idx / self.span as u64) as usize;
+        let entry = self.sparse_index + 6 * k;
+        let mut block = read_u32(bytes, entry) as usize;
+        let mut offset = read_u16(bytes, entry + 4) as i64;
+        offset += (idx % self.span as u64) as i64 - (self.span / 2) as i64;
+        let block_length = |block: usize| read_u16(bytes, self.block_length + 2 * block) as i64;
+        while offset < 0 {
+            block -= 1;
+            offset += block_length(block) + 1;
+        }
+        while offset > block_length(block) {
+            offset -= block_length(block) + 1;
+            block += 1;
+        }
+
+        let mut at = self.data + block * self.block_size;
+        let mut buffer = u64::from_be_bytes(bytes[at..at + 8].try_into().unwrap());
+        at += 8;
+        let mut buffer_bits = 64;
+        let min_len = self.min_sym_len as usize;
+        let mut sym;
+        loop {
+            let mut len = 0;
+            while buffer < self.base64[len] {
+                len += 1;
+            }
+            sym = ((buffer - self.base64[len]) >> (64 - len - min_len)) as usize;
+            sym += read_u16(bytes, self.lowest_sym + 2 * len) as usize;
+            if offset < self.symlen[sym] as i64 + 1 {
+                break;
+            }
+            offset -= self.symlen[sym] as i64 + 1;
+            len += min_len;
+            buffer <<= len;
+            buffer_bits -= len;
+            if buffer_bits <= 32 {
+                buffer_bits += 32;
+                let next = u32::from_be_bytes(bytes[at..at + 4].try_into().unwrap());
+                buffer |= (next as u64) << (64 - buffer_bits);
+                at += 4;
+            }
+        }
+
+        // Expand pairs until reaching the single value at the offset
+        while self.symlen[sym] != 0 {
+            let (left, right) = self.pair(bytes, sym);
+            if offset < self.symlen[left] as i64 + 1 {
+                sym = left;
+            } else {
+                offset -= self.symlen[left] as i64 + 1;
+                sym = right;
+            }
+        }
+        self.pair(bytes, sym).0
+    }
+}
+
+struct Table {
+    bytes: Vec<u8>,
+    dtz: bool,
+    // Both sides have the same material
+    symmetric: bool,
+    has_pawns: bool,
+    has_unique_pieces: bool,
+    piece_count: usize,
+    // Pawns of the leading colour, then the other
+    pawn_count: [usize; 2],
+    // By side to move, then leading pawn file
+    items: Vec<Vec<PairsData>>,
+    dtz_map: usize,
+}
+
+impl Table {
+    fn get(&self, stm: usize, file: usize) -> &PairsData {
+        &self.items[stm % self.items.len()][if self.has_pawns { file } else { 0 }]
+    }
+
+    // White pieces first, e.g. KRPvKR
+    fn load(path: &Path, name: &str, dtz: bool) -> Option<Table> {
+        let bytes = fs::read(path).ok()?;
+        let magic = if dtz { DTZ_MAGIC } else { WDL_MAGIC };
+        if bytes.len() < 6 || bytes[0..4] != magic {
+            return None;
+        }
+
+        let (white, black) = name.split_once('v')?;
+        let count = |side: &str, class: char| side.chars().filter(|&c| c == class).count();
+        let (white_pawns, black_pawns) = (count(white, 'P'), count(black, 'P'));
+        let has_unique_pieces = [white, black]
+            .iter()
+            .any(|side| "QRBNP".chars().any(|class| count(side, class) == 1));
+        // The side with fewer pawns leads, for better compression
+        let white_leads = black_pawns == 0 || (white_pawns > 0 && black_pawns >= white_pawns);
+        let mut table = Table {
+            bytes: Vec::new(),
+            dtz,
+            symmetric: white == black,
+            has_pawns: white_pawns + black_pawns > 0,
+            has_unique_pieces,
+            piece_count: white.len() + black.len(),
+            pawn_count: if white_leads {
+                [white_pawns, black_pawns]
+            } else {
+                [black_pawns, white_pawns]
+            },
+            items: Vec::new(),
+            dtz_map: 0,
+        };
+        table.init(&bytes)?;
+        table.bytes = bytes;
+        Some(table)
+    }
+
+    fn init(&mut self, bytes: &[u8]) -> Option<()> {
+        let mut at = 4;
+        let flags = bytes[at];
+        at += 1;
+        if (flags & 2 != 0) != self.has_pawns || (flags & 1 != 0) == self.symmetric {
+            return None;
+        }
+
+        let sides = if !self.dtz && !self.symmetric { 2 } else { 1 };
+        let files = if self.has_pawns { 4 } else { 1 };
+        let both_pawns = self.has_pawns && self.pawn_count[1] > 0;
+        self.items = vec![vec![PairsData::default(); files]; sides];
+
+        for file in 0..files {
+            let order = [
+                [
+                    bytes[at] & 0xf,
+                    if both_pawns { bytes[at + 1] & 0xf } else { 0xf },
+                ],
+                [
+                    bytes[at] >> 4,
+                    if both_pawns { bytes[at + 1] >> 4 } else { 0xf },
+                ],
+            ];
+            at += 1 + both_pawns as usize;
+            for k in 0..self.piece_count {
+                for side in 0..sides {
+                    self.items[side][file].pieces[k] = if side == 1 {
+                        bytes[at] >> 4
+                    } else {
+                        bytes[at] & 0xf
+                    };
+                }
+                at += 1;
+            }
+            for (side, order) in order.iter().enumerate().take(sides) {
+                self.set_groups(side, file, *order);
+            }
+        }
+        at += at & 1;
+
+        for file in 0..files {
+            for side in 0..sides {
+                at = self.items[side][file].set_sizes(bytes, at);
+            }
+        }
+
+        if self.dtz {
+            self.dtz_map = at;
+            for file in 0..files {
+                let data = &mut self.items[0][file];
+                if data.flags & FLAG_MAPPED == 0 {
+                    continue;
+                }
+                if data.flags & FLAG_WIDE != 0 {
+                    at += at & 1;
+                    for i in 0..4 {
+                        data.map_idx[i] = (at - self.dtz_map) / 2 + 1;
+                        at += 2 * read_u16(bytes, at) as usize + 2;
+                    }
+                } else {
+                    for i in 0..4 {
+                        data.map_idx[i] = at - self.dtz_map + 1;
+                        at += bytes[at] as usize + 1;
+                    }
+                }
+            }
+            at += at & 1;
+        }
+
+        for file in 0..files {
+            for side in 0..sides {
+                let data = &mut self.items[side][file];
+                data.sparse_index = at;
+                at += 6 * data.sparse_index_size;
+            }
+        }
+        for file in 0..files {
+            for side in 0..sides {
+                let data = &mut self.items[side][file];
+                data.block_length = at;
+                at += 2 * data.block_length_size;
+            }
+        }
+        for file in 0..files {
+            for side in 0..sides {
+                let data = &mut self.items[side][file];
+                at = (at + 0x3f) & !0x3f;
+                data.data = at;
+                at += data.num_blocks * data.block_size;
+            }
+        }
+        if at > bytes.len() {
+            return None;
+        }
+        Some(())
+    }
+
+    // Splits the pieces into groups encoded together, and the index each
+    // group's encoding is multiplied by
+    fn set_groups(&mut self, side: usize, file: usize, order: [u8; 2]) {
+        let e = encoding();
+        let has_pawns = self.has_pawns;
+        let has_unique_pieces = self.has_unique_pieces;
+        let both_pawns = has_pawns && self.pawn_count[1] > 0;
+        let piece_count = self.piece_count;
+        let data = &mut self.items[side][file];
+
+        let mut n = 0;
+        let mut first_len: i32 = if has_pawns {
+            0
+        } else if has_unique_pieces {
+            3
+        } else {
+            2
+        };
+        data.group_len[0] = 1;
+        for i in 1..piece_count {
+            first_len -= 1;
+            if first_len > 0 || data.pieces[i] == data.pieces[i - 1] {
+                data.group_len[n] += 1;
+            } else {
+                n += 1;
+                data.group_len[n] = 1;
+            }
+        }
+        n += 1;
+        data.group_len[n] = 0;
+
+        let mut next = if both_pawns { 2 } else { 1 };
+        let mut free_squares =
+            64 - data.group_len[0] - if both_pawns { data.group_len[1] } else { 0 };
+        let mut idx: u64 = 1;
+        let mut k = 0;
+        while next < n || k == order[0] as usize || k == order[1] as usize {
+            if k == order[0] as usize {
+                data.group_idx[0] = idx;
+                idx *= if has_pawns {
+                    e.lead_pawns_size[data.group_len[0]][file]
+                } else if has_unique_pieces {
+                    31332
+                } else {
+                    462
+                };
+            } else if k == order[1] as usize {
+                data.group_idx[1] = idx;
+                idx *= e.binomial[data.group_len[1]][48 - data.group_len[0]];
+            } else {
+                data.group_idx[next] = idx;
+                idx *= e.binomial[data.group_len[next]][free_squares];
+                free_squares -= data.group_len[next];
+                next += 1;
+            }
+            k += 1;
+        }
+        data.group_idx[n] = idx;
+    }
+
+    // Converts a stored DTZ value to plies
+    fn map_dtz(&self, file: usize, value: usize, wdl: i32) -> i32 {
+        let data = self.get(0, file);
+        let flags = data.flags;
+        let mut value = value;
+        if flags & FLAG_MAPPED != 0 {
+            let map = data.map_idx[match wdl {
+                WDL_WIN => 0,
+                WDL_LOSS => 1,
+                WDL_CURSED_WIN => 2,
+                _ => 3,
+            }];
+            value = if flags & FLAG_WIDE != 0 {
+                read_u16(&self.bytes, self.dtz_map + 2 * (map + value)) as usize
+            } else {
+                self.bytes[self.dtz_map + map + value] as usize
+            };
+        }
+        let value = value as i32;
+        let in_moves = match wdl {
+            WDL_WIN => flags & FLAG_WIN_PLIES == 0,
+            WDL_LOSS => flags & FLAG_LOSS_PLIES == 0,
+            _ => true,
+        };
+        (if in_moves { value * 2 } else { value }) + 1
+    }
+
+    // Stored value for the position, or None if a DTZ table only has the other
+    // side to move
+    fn probe(&self, position: &Position, black_stronger: bool) -> Option<(usize, usize)> {
+        let (stm, file, idx) = self.index(position, black_stronger)?;
+        Some((self.get(stm, file).decompress(&self.bytes, idx), file))
+    }
+
+    // Side to move and leading pawn file of the sub-table holding the
+    // position, and its index there
+    fn index(&self, position: &Position, black_stronger: bool) -> Option<(usize, usize, u64)> {
+        let e = encoding();
+        let symmetric_black = self.symmetric && position.black_to_move;
+        let flip = symmetric_black || black_stronger;
+        let flip_color = if flip { BLACK } else { 0 };
+        let flip_squares = if flip { 56 } else { 0 };
+        let stm = (flip != position.black_to_move) as usize;
+
+        let mut squares = Vec::with_capacity(MAX_PIECES);
+        let mut pieces = Vec::with_capacity(MAX_PIECES);
+        let mut lead_pawns = 0;
+        let mut file = 0;
+        let pawns_leading = if self.has_pawns {
+            // Pawns of the leading colour come first in every sub-table
+            let lead = self.get(0, 0).pieces[0] ^ flip_color;
+            for &(square, piece) in position.pieces.iter() {
+                if piece == lead {
+                    squares.push(square ^ flip_squares);
+                    pieces.push(piece ^ flip_color);
+                }
+            }
+            lead_pawns = squares.len();
+            let best = (0..lead_pawns)
+                .rev()
+                .max_by_key(|&i| e.map_pawns[squares[i]])
+                .unwrap();
+            squares.swap(0, best);
+            file = file_of(squares[0]);
+            if file > 3 {
+                file = file_of(squares[0] ^ 7);
+            }
+            Some(lead)
+        } else {
+            None
+        };
+
+        if self.dtz {
+            let flags = self.get(stm, file).flags;
+            let both_sides = self.symmetric && !self.has_pawns;
+            if (flags & FLAG_STM) as usize != stm && !both_sides {
+                return None;
+            }
+        }
+
+        for &(square, piece) in position.pieces.iter() {
+            if Some(piece) != pawns_leading {
+                squares.push(square ^ flip_squares);
+                pieces.push(piece ^ flip_color);
+            }
+        }
+        let size = squares.len();
+        let data = self.get(stm, file);
+
+        // Same order as the table's pieces
+        for i in lead_pawns..size.saturating_sub(1) {
+            for j in i + 1..size {
+                if data.pieces[i] == pieces[j] {
+                    pieces.swap(i, j);
+                    squares.swap(i, j);
+                    break;
+                }
+            }
+        }
+
+        // Leading piece into the a-d files
+        if file_of(squares[0]) > 3 {
+            for square in squares.iter_mut() {
+                *square ^= 7;
+            }
+        }
+
+        let mut idx: u64;
+        if self.has_pawns {
+            idx = e.lead_pawn_idx[lead_pawns][squares[0]];
+            squares[1..lead_pawns].sort_by_key(|&square| e.map_pawns[square]);
+            for (i, &square) in squares.iter().enumerate().take(lead_pawns).skip(1) {
+                idx += e.binomial[i][e.map_pawns[square]];
+            }
+        } else {
+            // Leading piece below rank 5, then below the a1-h8 diagonal
+            if rank_of(squares[0]) > 3 {
+                for square in squares.iter_mut() {
+                    *square ^= 56;
+                }
+            }
+            for i in 0..data.group_len[0] {
+                let off = off_diagonal(squares[i]);
+                if off == 0 {
+                    continue;
+                }
+                if off > 0 {
+                    for square in squares[i..].iter_mut() {
+                        *square = ((*square >> 3) | (*square << 3)) & 63;
+                    }
+                }
+                break;
+            }
+
+            if self.has_unique_pieces {
+                let adjust1 = (squares[1] > squares[0]) as u64;
+                let adjust2 = (squares[2] > squares[0]) as u64 + (squares[2] > squares[1]) as u64;
+                let (s0, s1, s2) = (squares[0], squares[1], squares[2]);
+                idx = if off_diagonal(s0) != 0 {
+                    (e.map_a1d1d4[s0] as u64 * 63 + s1 as u64 - adjust1) * 62 + s2 as u64 - adjust2
+                } else if off_diagonal(s1) != 0 {
+                    (6 * 63 + rank_of(s0) as u64 * 28 + e.map_b1h1h7[s1] as u64) * 62 + s2 as u64
+                        - adjust2
+                } else if off_diagonal(s2) != 0 {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + rank_of(s0) as u64 * 7 * 28
+                        + (rank_of(s1) as u64 - adjust1) * 28
+                        + e.map_b1h1h7[s2] as u64
+                } else {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + 4 * 7 * 28
+                        + rank_of(s0) as u64 * 7 * 6
+                        + (rank_of(s1) as u64 - adjust1) * 6
+                        + (rank_of(s2) as u64 - adjust2)
+                };
+            } else {
+                idx = e.map_kk[e.map_a1d1d4[squares[0]]][squares[1]] as u64;
+            }
+        }
+
+        // Remaining groups, each square counted past the earlier groups' squares
+        idx *= data.group_idx[0];
+        let mut start = data.group_len[0];
+        let mut remaining_pawns = self.has_pawns && self.pawn_count[1] > 0;
+        let mut next = 1;
+        while data.group_len[next] != 0 {
+            let len = data.group_len[next];
+            squares[start..start + len].sort();
+            let mut n = 0;
+            for i in 0..len {
+                let square = squares[start + i];
+                let adjust = squares[..start].iter().filter(|&&s| square > s).count();
+                let pawn_offset = if remaining_pawns { 8 } else { 0 };
+                n += e.binomial[i + 1][square - adjust - pawn_offset];
+            }
+            remaining_pawns = false;
+            idx += n * data.group_idx[next];
+            start += len;
+            next += 1;
+        }
+        Some((stm, file, idx))
+    }
+}
+
+// Pieces as (square, code) with a1 = 0, in square order
+struct Position {
+    pieces: Vec<(usize, u8)>,
+    black_to_move: bool,
+}
+
+// The engine (enemy = false) is the side to move
+fn position(game_state: &GameState) -> Position {
+    let engine_black = game_state.engine_black;
+    let mut pieces = Vec::new();
+    for (y, row) in game_state.board.iter().enumerate() {
+        let rank = if engine_black { 7 - y } else { y };
+        for (x, square) in row.iter().enumerate() {
+            if let Some(piece) = square {
+                let class = match piece.class {
+                    PieceClass::Pawn => 1,
+                    PieceClass::Knight => 2,
+                    PieceClass::Bishop => 3,
+                    PieceClass::Rook => 4,
+                    PieceClass::Queen => 5,
+                    PieceClass::King => 6,
+                };
+                let white = piece.enemy == engine_black;
+                pieces.push((rank * 8 + x, if white { class } else { class | BLACK }));
+            }
+        }
+    }
+    pieces.sort();
+    Position {
+        pieces,
+        black_to_move: engine_black,
+    }
+}
+
+// Table name for the material, white first, e.g. KRPvKR
+fn material_name(position: &Position, swap: bool) -> String {
+    let side = |black: bool| -> String {
+        let mut codes: Vec<u8> = position
+            .pieces
+            .iter()
+            .filter(|(_, piece)| (piece & BLACK != 0) == black)
+            .map(|(_, piece)| piece & 7)
+            .collect();
+        codes.sort_by(|a, b| b.cmp(a));
+        codes
+            .iter()
+            .map(|code| "PNBRQK".as_bytes()[*code as usize - 1] as char)
+            .collect()
+    };
+    let (white, black) = (side(swap), side(!swap));
+    format!("{}v{}", white, black)
+}
+
+fn is_zeroing(game_state: &GameState, mov: &Move) -> bool {
+    if is_capture(game_state, mov) {
+        return true;
+    }
+    match mov.move_type {
+        MoveType::DoubleAdvance(..) | MoveType::EnPassant(..) | MoveType::Promotion(..) => true,
+        MoveType::Standard(from, _) => matches!(
+            game_state.board[from.y as usize][from.x as usize],
+            Some(piece) if piece.class == PieceClass::Pawn
+        ),
+        _ => false,
+    }
+}
+
+fn dtz_before_zeroing(wdl: i32) -> i32 {
+    match wdl {
+        WDL_WIN => 1,
+        WDL_CURSED_WIN => 101,
+        WDL_BLESSED_LOSS => -101,
+        WDL_LOSS => -1,
+        _ => 0,
+    }
+}
+
+pub struct Tablebases {
+    directory: PathBuf,
+    wdl: HashMap<String, OnceLock<Option<Table>>>,
+    dtz: HashMap<String, OnceLock<Option<Table>>>,
+    pub max_pieces: usize,
+}
+
+impl Tablebases {
+    // Finds the tables in the directory, each is read when first needed
+    pub fn open(directory: &str) -> Result<Tablebases, String> {
+        let entries =
+            fs::read_dir(directory).map_err(|e| format!("Couldn't read {}: {}", directory, e))?;
+        let mut tablebases = Tablebases {
+            directory: PathBuf::from(directory),
+            wdl: HashMap::new(),
+            dtz: HashMap::new(),
+            max_pieces: 0,
+        };
+        for entry in entries.flatten() {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            let (name, tables) = match file_name.rsplit_once('.') {
+                Some((name, "rtbw")) => (name, &mut tablebases.wdl),
+                Some((name, "rtbz")) => (name, &mut tablebases.dtz),
+                _ => continue,
+            };
+            let valid = name.split_once('v').is_some_and(|(white, black)| {
+                white.starts_with('K')
+                    && black.starts_with('K')
+                    && name.len() - 1 <= MAX_PIECES
+                    && (white.to_owned() + black)
+                        .chars()
+                        .all(|c| "KQRBNP".contains(c))
+            });
+            if valid {
+                tablebases.max_pieces = tablebases.max_pieces.max(name.len() - 1);
+                tables.insert(name.to_string(), OnceLock::new());
+            }
+        }
+        Ok(tablebases)
+    }
+
+    pub fn table_count(&self) -> usize {
+        self.wdl.len() + self.dtz.len()
+    }
+
+    // Table for the position's material and whether colours must be swapped
+    fn table(&self, position: &Position, dtz: bool) -> Option<(&Table, bool)> {
+        let (tables, extension) = if dtz {
+            (&self.dtz, "rtbz")
+        } else {
+            (&self.wdl, "rtbw")
+        };
+        for swap in [false, true] {
+            let name = material_name(position, swap);
+            if let Some(cell) = tables.get(&name) {
+                let path = self.directory.join(format!("{}.{}", name, extension));
+                return cell
+                    .get_or_init(|| Table::load(&path, &name, dtz))
+                    .as_ref()
+                    .map(|table| (table, swap));
+            }
+        }
+        None
+    }
+
+    // Whether the position could be in the tables at all
+    pub fn covers(&self, game_state: &GameState) -> bool {
+        let castling = [game_state.engine_castling, game_state.opponent_castling]
+            .iter()
+            .any(|c| c.kingside || c.queenside);
+        let pieces = game_state.board.iter().flatten().flatten().count();
+        !castling && pieces <= self.max_pieces
+    }
+
+    fn probe_wdl_table(&self, game_state: &GameState) -> Option<i32> {
+        let position = position(game_state);
+        if position.pieces.len() == 2 {
+            return Some(WDL_DRAW);
+        }
+        let (table, swap) = self.table(&position, false)?;
+        let (value, _) = table.probe(&position, swap)?;
+        Some(value as i32 - 2)
+    }
+
+    // DTZ in plies as stored, None if the table is missing and Some(None) if
+    // it only has the other side to move
+    fn probe_dtz_table(&self, game_state: &GameState, wdl: i32) -> Option<Option<i32>> {
+        let position = position(game_state);
+        let (table, swap) = self.table(&position, true)?;
+        Some(
+            table
+                .probe(&position, swap)
+                .map(|(value, file)| table.map_dtz(file, value, wdl)),
+        )
+    }
+
+    // Captures (and pawn moves when looking for zeroing moves) are searched
+    // first, as the tables don't account for en passant and may store
+    // anything when the best move is a capture. Returns the WDL and whether
+    // the best move zeroes the fifty move counter
+    fn search(&self, game_state: &GameState, zeroing_moves: bool) -> Option<(i32, bool)> {
+        let moves = game_state.get_legal_moves(false);
+        let mut best = WDL_LOSS;
+        let mut searched = 0;
+        for mov in moves.iter() {
+            let zeroing = if zeroing_moves {
+                is_zeroing(game_state, mov)
+            } else {
+                is_capture(game_state, mov)
+            };
+            if !zeroing {
+                continue;
+            }
+            searched += 1;
+            let next_state = game_state.perform_move(mov).flipped();
+            let (value, _) = self.search(&next_state, false)?;
+            let value = -value;
+            if value > best {
+                best = value;
+                if value >= WDL_WIN {
+                    return Some((value, true));
+                }
+            }
+        }
+
+        let no_more_moves = searched > 0 && searched == moves.len();
+        let value = if no_more_moves {
+            best
+        } else {
+            self.probe_wdl_table(game_state)?
+        };
+        if best >= value {
+            Some((best, best > WDL_DRAW || no_more_moves))
+        } else {
+            Some((value, false))
+        }
+    }
+
+    // Win/draw/loss for the side to move
+    pub fn probe_wdl(&self, game_state: &GameState, enemy: bool) -> Option<i32> {
+        if enemy {
+            return self.probe_wdl(&game_state.flipped(), false);
+        }
+        if !self.covers(game_state) {
+            return None;
+        }
+        self.search(game_state, false).map(|(wdl, _)| wdl)
+    }
+
+    // Plies to the next capture or pawn move under optimal play, positive
+    // when winning, 0 for draws. Off by one from the real distance when the
+    // stored value is in moves
+    pub fn probe_dtz(&self, game_state: &GameState) -> Option<i32> {
+        if !self.covers(game_state) {
+            return None;
+        }
+        let (wdl, zeroing_best) = self.search(game_state, true)?;
+        if wdl == WDL_DRAW {
+            return Some(0);
+        }
+        if zeroing_best {
+            return Some(dtz_before_zeroing(wdl));
+        }
+        if let Some(dtz) = self.probe_dtz_table(game_state, wdl)? {
+            let cursed = wdl == WDL_CURSED_WIN || wdl == WDL_BLESSED_LOSS;
+            return Some((dtz + if cursed { 100 } else { 0 }) * wdl.signum());
+        }
+
+        // The table only has the other side to move, so search one ply for
+        // the move that keeps the result with the lowest DTZ
+        let mut min_dtz = i32::MAX;
+        for mov in game_state.get_legal_moves(false) {
+            let zeroing = is_zeroing(game_state, &mov);
+            let next_state = game_state.perform_move(&mov).flipped();
+            let mut dtz = if zeroing {
+                -dtz_before_zeroing(self.search(&next_state, false)?.0)
+            } else {
+                -self.probe_dtz(&next_state)?
+            };
+            if dtz == 1
+                && next_state.in_check(false)
+                && next_state.get_legal_moves(false).is_empty()
+            {
+                min_dtz = 1;
+            }
+            if !zeroing {
+                dtz += dtz.signum();
+            }
+            if dtz < min_dtz && dtz.signum() == wdl.signum() {
+                min_dtz = dtz;
+            }
+        }
+        Some(if min_dtz == i32::MAX { -1 } else { min_dtz })
+    }
+
+    // Root moves that keep the best result, making the quickest progress when
+    // winning and holding out longest when losing. Uses DTZ tables when they
+    // are present, otherwise keeps every move with the best WDL
+    pub fn root_moves(&self, game_state: &GameState, moves: &[Move]) -> Option<Vec<Move>> {
+        if !self.covers(game_state) || moves.is_empty() {
+            return None;
+        }
+        let mut ranked = Vec::new();
+        for mov in moves {
+            let next_state = game_state.perform_move(mov).flipped();
+            let wdl = -self.probe_wdl(&next_state, false)?;
+            let rank = match self.probe_dtz_for_move(game_state, mov, &next_state, wdl) {
+                Some(dtz) => rank_dtz(dtz),
+                None => rank_wdl(wdl),
+            };
+            ranked.push((*mov, rank));
+        }
+        let best = ranked.iter().map(|(_, rank)| *rank).max()?;
+        Some(
+            ranked
+                .into_iter()
+                .filter(|(_, rank)| *rank == best)
+                .map(|(mov, _)| mov)
+                .collect(),
+        )
+    }
+
+    // DTZ from the position before the move
+    fn probe_dtz_for_move(
+        &self,
+        game_state: &GameState,
+        mov: &Move,
+        next_state: &GameState,
+        wdl: i32,
+    ) -> Option<i32> {
+        if self.dtz.is_empty() {
+            return None;
+        }
+        let mut dtz = if is_zeroing(game_state, mov) {
+            dtz_before_zeroing(wdl)
+        } else {
+            let dtz = -self.probe_dtz(next_state)?;
+            dtz + dtz.signum()
+        };
+        // A mating move counts as one ply
+        if dtz == 2 && next_state.in_check(false) && next_state.get_legal_moves(false).is_empty() {
+            dtz = 1;
+        }
+        Some(dtz)
+    }
+}
+
+// Higher is better: quick wins, then draws, then slow losses
+fn rank_dtz(dtz: i32) -> i32 {
+    match dtz {
+        // Cursed wins are draws under the fifty move rule
+        1..=100 => 1000 - dtz,
+        d if d > 100 => 0,
+        -100..=-1 => -1000 - dtz,
+        d if d < -100 => 0,
+        _ => 0,
+    }
+}
+
+fn rank_wdl(wdl: i32) -> i32 {
+    match wdl {
+        WDL_WIN => 1000,
+        WDL_LOSS => -1000,
+        _ => 0,
+    }
+}
+
+static TABLEBASES: OnceLock<Tablebases> = OnceLock::new();
+
+// Only has an effect before the first probe
+pub fn set_tablebases(tablebases: Tablebases) {
+    let _ = TABLEBASES.set(tablebases);
+}
+
+pub fn tablebases() -> Option<&'static Tablebases> {
+    TABLEBASES.get()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+    use crate::fen::parse_fen;
+
+    const WHITE_KING: u8 = 6;
+    const BLACK_KING: u8 = 6 | BLACK;
+
+    #[test]
+    fn binomial() {
+        let e = encoding();
+        assert_eq!(e.binomial[0][0], 1);
+        assert_eq!(e.binomial[1][47], 47);
+        assert_eq!(e.binomial[2][5], 10);
+        assert_eq!(e.binomial[3][62], 37820);
+        assert_eq!(e.binomial[6][63], 67945521);
+        assert_eq!(e.binomial[4][3], 0);
+    }
+
+    #[test]
+    fn square_maps() {
+        let e = encoding();
+        // b1, c1, d1, c2, d2, d3, then the diagonal a1, b2, c3, d4
+        for (code, square) in [1, 2, 3, 10, 11, 19, 0, 9, 18, 27].into_iter().enumerate() {
+            assert_eq!(e.map_a1d1d4[square], code);
+        }
+        assert_eq!(e.map_b1h1h7[1], 0);
+        assert_eq!(e.map_b1h1h7[7], 6);
+        assert_eq!(e.map_b1h1h7[10], 7);
+        assert_eq!(e.map_b1h1h7[55], 27);
+        // a2, h2, a3, h3 and so on down to d7, e7
+        assert_eq!(e.map_pawns[8], 47);
+        assert_eq!(e.map_pawns[15], 46);
+        assert_eq!(e.map_pawns[16], 45);
+        assert_eq!(e.map_pawns[51], 1);
+        assert_eq!(e.map_pawns[52], 0);
+    }
+
+    #[test]
+    fn king_pairs() {
+        let e = encoding();
+        let mut codes: Vec<usize> = e.map_kk.iter().flatten().copied().collect();
+        codes.sort();
+        codes.dedup();
+        assert_eq!(codes.len(), 462);
+        assert_eq!(codes.last(), Some(&461));
+        // Kings on b1 and d1 come first, the 21 placements with both on the
+        // diagonal last, starting with a1 and c3
+        assert_eq!(e.map_kk[0][3], 0);
+        assert_eq!(e.map_kk[6][18], 441);
+    }
+
+    #[test]
+    fn leading_pawns() {
+        let e = encoding();
+        for file in 0..4 {
+            assert_eq!(e.lead_pawns_size[1][file], 6);
+        }
+        assert_eq!(e.lead_pawn_idx[1][8], 0);
+        assert_eq!(e.lead_pawn_idx[1][48], 5);
+        // The a-pawn's map values 47 + 45 + 43 + 41 + 39 + 37
+        assert_eq!(e.lead_pawns_size[2][0], 252);
+        assert_eq!(e.lead_pawn_idx[2][16], 47);
+    }
+
+    #[test]
+    fn material_names() {
+        let game_state = parse_fen("4k3/4r3/8/8/8/8/3PR3/4K3 b - - 0 1").unwrap();
+        let position = position(&game_state);
+        assert!(position.black_to_move);
+        assert_eq!(position.pieces[0], (4, WHITE_KING));
+        assert_eq!(position.pieces.last(), Some(&(60, BLACK_KING)));
+        assert_eq!(material_name(&position, false), "KRPvKR");
+        assert_eq!(material_name(&position, true), "KRvKRP");
+    }
+
+    #[test]
+    fn dtz_ranks() {
+        assert!(rank_dtz(1) > rank_dtz(20));
+        assert!(rank_dtz(20) > rank_dtz(0));
+        assert_eq!(rank_dtz(101), rank_dtz(0));
+        assert!(rank_dtz(0) > rank_dtz(-20));
+        assert!(rank_dtz(-20) > rank_dtz(-1));
+        assert!(rank_wdl(WDL_WIN) > rank_wdl(WDL_CURSED_WIN));
+        assert_eq!(rank_wdl(WDL_BLESSED_LOSS), rank_wdl(WDL_DRAW));
+    }
+
+    fn adjacent(a: usize, b: usize) -> bool {
+        file_of(a).abs_diff(file_of(b)) <= 1 && rank_of(a).abs_diff(rank_of(b)) <= 1
+    }
+
+    // Whether a queen or rook attacks the target, with only the white king in
+    // the way. The lone king is left out so it can't hide behind itself
+    fn slider_attacks(from: usize, target: usize, king: usize, diagonals: bool) -> bool {
+        let mut directions = vec![(0, 1), (1, 0), (0, -1), (-1, 0)];
+        if diagonals {
+            directions.extend([(1, 1), (1, -1), (-1, 1), (-1, -1)]);
+        }
+        for (dx, dy) in directions {
+            let (mut x, mut y) = (file_of(from) as i32 + dx, rank_of(from) as i32 + dy);
+            while (0..8).contains(&x) && (0..8).contains(&y) {
+                let square = (y * 8 + x) as usize;
+                if square == target {
+                    return true;
+                }
+                if square == king {
+                    break;
+                }
+                x += dx;
+                y += dy;
+            }
+        }
+        false
+    }
+
+    // King and queen or rook against a lone king, worked out without the
+    // engine. The stronger side always wins on the move, the lone king only
+    // draws by taking the piece or by stalemate
+    fn expected_wdl(squares: [usize; 3], queen: bool, black: bool) -> i32 {
+        let [white_king, piece, black_king] = squares;
+        if !black {
+            return WDL_WIN;
+        }
+        let attacked = |square: usize| {
+            adjacent(square, white_king)
+                || (square != piece && slider_attacks(piece, square, white_king, queen))
+        };
+        let mut has_move = false;
+        for square in (0..64).filter(|&s| s != black_king && adjacent(s, black_king)) {
+            if attacked(square) {
+                continue;
+            }
+            if square == piece {
+                return WDL_DRAW;
+            }
+            has_move = true;
+        }
+        if has_move || attacked(black_king) {
+            WDL_LOSS
+        } else {
+            WDL_DRAW
+        }
+    }
+
+    // Every placement of the three pieces
+    fn placements() -> impl Iterator<Item = [usize; 3]> {
+        (0..64)
+            .flat_map(|a| (0..64).flat_map(move |b| (0..64).map(move |c| [a, b, c])))
+            .filter(|[a, b, c]| a != b && b != c && a != c)
+    }
+
+    // Positions per side to move for three pieces with one unique, as for
+    // the 31332 placements of the leading group
+    const TABLE_SIZE: usize = 31332;
+    const VALUES_PER_BLOCK: usize = 64;
+
+    // A WDL table for king and piece against king in the Syzygy format, with
+    // every symbol a single value coded in three bits
+    fn table_bytes(piece: u8, values: &[Vec<u8>]) -> Vec<u8> {
+        let blocks = TABLE_SIZE.div_ceil(VALUES_PER_BLOCK);
+        let mut bytes = WDL_MAGIC.to_vec();
+        // Not symmetric and without pawns, then the order of the leading
+        // group and the pieces for each side to move, one per nibble
+        bytes.extend([1, 0]);
+        for code in [WHITE_KING, piece, BLACK_KING] {
+            bytes.push(code | code << 4);
+        }
+        bytes.push(0);
+        for _ in 0..2 {
+            // Blocks of 32 bytes, a sparse index entry for each block's 64
+            // values, then the five symbols, none of them pairs
+            bytes.extend([0, 5, 6, 0]);
+            bytes.extend((blocks as u32).to_le_bytes());
+            bytes.extend([3, 3, 0, 0, 5, 0]);
+            for value in 0..5 {
+                bytes.extend([value, 0xf0, 0xff]);
+            }
+            bytes.push(0);
+        }
+        for _ in 0..2 {
+            for block in 0..blocks as u32 {
+                bytes.extend(block.to_le_bytes());
+                bytes.extend((VALUES_PER_BLOCK as u16 / 2).to_le_bytes());
+            }
+        }
+        for _ in 0..2 {
+            for block in 0..blocks {
+                let count = VALUES_PER_BLOCK.min(TABLE_SIZE - block * VALUES_PER_BLOCK);
+                bytes.extend((count as u16 - 1).to_le_bytes());
+            }
+        }
+        for side in 0..2 {
+            bytes.resize(bytes.len().next_multiple_of(64), 0);
+            for block in 0..blocks {
+                let mut data = [0; 32];
+                let start = block * VALUES_PER_BLOCK;
+                let end = TABLE_SIZE.min(start + VALUES_PER_BLOCK);
+                for (i, value) in values
+                    .get(side)
+                    .map_or(&[][..], |v| &v[start..end])
+                    .iter()
+                    .enumerate()
+                {
+                    for bit in 0..3 {
+                        if value >> (2 - bit) & 1 != 0 {
+                            let n = i * 3 + bit;
+                            data[n / 8] |= 0x80 >> (n % 8);
+                        }
+                    }
+                }
+                bytes.extend(data);
+            }
+        }
+        // Decoding reads a little past the last block
+        bytes.extend([0; 16]);
+        bytes
+    }
+
+    // Writes the table into a directory of its own. The values come from
+    // expected_wdl, and every placement the index maps to the same entry, as
+    // the mirror images of a position do, must agree
+    fn write_table(name: &str, piece: u8) -> PathBuf {
+        let directory = env::temp_dir().join(format!("syzygy-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join(format!("{}.rtbw", name));
+        fs::write(&path, table_bytes(piece, &[])).unwrap();
+        let table = Table::load(&path, name, false).unwrap();
+        assert_eq!(table.get(0, 0).group_idx[1], TABLE_SIZE as u64);
+
+        let mut values = vec![vec![None; TABLE_SIZE]; 2];
+        for squares in placements() {
+            for black in [false, true] {
+                let mut pieces = vec![
+                    (squares[0], WHITE_KING),
+                    (squares[1], piece),
+                    (squares[2], BLACK_KING),
+                ];
+                pieces.sort();
+                let position = Position {
+                    pieces,
+                    black_to_move: black,
+                };
+                let (stm, file, idx) = table.index(&position, false).unwrap();
+                assert_eq!((stm, file), (black as usize, 0));
+                let value = (expected_wdl(squares, piece == 5, black) + 2) as u8;
+                let entry = &mut values[stm][idx as usize];
+                assert_eq!(*entry.get_or_insert(value), value, "{:?}", squares);
+            }
+        }
+        let values: Vec<Vec<u8>> = values
+            .into_iter()
+            .map(|side| side.into_iter().map(|v| v.unwrap_or(2)).collect())
+            .collect();
+        fs::write(&path, table_bytes(piece, &values)).unwrap();
+        directory
+    }
+
+    fn fen(pieces: [(usize, char); 3], black: bool) -> String {
+        let mut board = [None; 64];
+        for (square, char) in pieces {
+            board[square] = Some(char);
+        }
+        let ranks: Vec<String> = (0..8)
+            .rev()
+            .map(|rank| {
+                let mut out = String::new();
+                let mut empty = 0;
+                for square in &board[rank * 8..rank * 8 + 8] {
+                    match square {
+                        Some(char) => {
+                            if empty > 0 {
+                                out += &empty.to_string();
+                                empty = 0;
+                            }
+                            out.push(*char);
+                        }
+                        None => empty += 1,
+                    }
+                }
+                if empty > 0 {
+                    out += &empty.to_string();
+                }
+                out
+            })
+            .collect();
+        format!(
+            "{} {} - - 0 1",
+            ranks.join("/"),
+            if black { "b" } else { "w" }
+        )
+    }
+
+    fn check_table(name: &str, piece: u8, known: &[(&str, i32)]) {
+        let directory = write_table(name, piece);
+        let tablebases = Tablebases::open(directory.to_str().unwrap()).unwrap();
+        assert_eq!(tablebases.max_pieces, 3);
+        check_probes(&tablebases, piece, known);
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    // Probes a sample of legal positions and the same with colours swapped
+    fn check_probes(tablebases: &Tablebases, piece: u8, known: &[(&str, i32)]) {
+        let probe = |fen: &str| tablebases.probe_wdl(&parse_fen(fen).unwrap(), false);
+        for (fen, wdl) in known {
+            assert_eq!(probe(fen), Some(*wdl), "{}", fen);
+        }
+
+        let class = if piece == 5 { 'Q' } else { 'R' };
+        let legal =
+            placements().filter(|&[white_king, _, black_king]| !adjacent(white_king, black_king));
+        for (i, squares) in legal.enumerate().filter(|(i, _)| i % 97 == 0) {
+            let [white_king, piece, black_king] = squares;
+            for black in [false, true] {
+                let in_check = slider_attacks(piece, black_king, white_king, class == 'Q');
+                if in_check && !black {
+                    continue;
+                }
+                let wdl = expected_wdl(squares, class == 'Q', black);
+                let white = fen(
+                    [(white_king, 'K'), (piece, class), (black_king, 'k')],
+                    black,
+                );
+                assert_eq!(probe(&white), Some(wdl), "{}", white);
+                let swapped = fen(
+                    [
+                        (white_king ^ 56, 'k'),
+                        (piece ^ 56, class.to_ascii_lowercase()),
+                        (black_king ^ 56, 'K'),
+                    ],
+                    !black,
+                );
+                assert_eq!(probe(&swapped), Some(wdl), "{}", swapped);
+            }
+        }
+    }
+
+    #[test]
+    fn probe_kqvk() {
+        check_table(
+            "KQvK",
+            5,
+            &[
+                ("4k3/8/8/8/8/8/8/3QK3 w - - 0 1", WDL_WIN),
+                ("4k3/8/8/8/8/8/8/3QK3 b - - 0 1", WDL_LOSS),
+                ("3qk3/8/8/8/8/8/8/4K3 b - - 0 1", WDL_WIN),
+                // Mate, stalemate and the queen taken
+                ("k7/1Q6/1K6/8/8/8/8/8 b - - 0 1", WDL_LOSS),
+                ("k7/8/1Q6/8/8/8/8/7K b - - 0 1", WDL_DRAW),
+                ("8/8/8/8/8/8/6Q1/K6k b - - 0 1", WDL_DRAW),
+            ],
+        );
+    }
+
+    #[test]
+    fn probe_krvk() {
+        check_table(
+            "KRvK",
+            4,
+            &[
+                ("4k3/8/8/8/8/8/8/3RK3 w - - 0 1", WDL_WIN),
+                ("4k3/8/8/8/8/8/8/3RK3 b - - 0 1", WDL_LOSS),
+                ("3rk3/8/8/8/8/8/8/4K3 b - - 0 1", WDL_WIN),
+                ("R6k/8/7K/8/8/8/8/8 b - - 0 1", WDL_LOSS),
+                ("k7/1R6/2K5/8/8/8/8/8 b - - 0 1", WDL_DRAW),
+                ("8/8/8/8/8/8/7R/K6k b - - 0 1", WDL_DRAW),
+            ],
+        );
+    }
+
+    // The real KQvK and KRvK tables aren't kept in the repository. Copy them
+    // from the Syzygy download into syzygy/ and run cargo test -- --ignored
+    #[test]
+    #[ignore = "needs the downloaded KQvK and KRvK tables in syzygy/"]
+    fn probe_downloaded_tables() {
+        let directory = concat!(env!("CARGO_MANIFEST_DIR"), "/syzygy");
+        let tablebases = Tablebases::open(directory).unwrap();
+        for name in ["KQvK", "KRvK"] {
+            for extension in ["rtbw", "rtbz"] {
+                let path = Path::new(directory).join(format!("{}.{}", name, extension));
+                assert!(path.exists(), "{} is missing", path.display());
+            }
+        }
+        check_probes(
+            &tablebases,
+            5,
+            &[
+                ("4k3/8/8/8/8/8/8/3QK3 w - - 0 1", WDL_WIN),
+                ("k7/1Q6/1K6/8/8/8/8/8 b - - 0 1", WDL_LOSS),
+                ("k7/8/1Q6/8/8/8/8/7K b - - 0 1", WDL_DRAW),
+            ],
+        );
+        check_probes(
+            &tablebases,
+            4,
+            &[
+                ("4k3/8/8/8/8/8/8/3RK3 w - - 0 1", WDL_WIN),
+                ("R6k/8/7K/8/8/8/8/8 b - - 0 1", WDL_LOSS),
+                ("k7/1R6/2K5/8/8/8/8/8 b - - 0 1", WDL_DRAW),
+            ],
+        );
+
+        let dtz = |fen: &str| tablebases.probe_dtz(&parse_fen(fen).unwrap());
+        // Mate on the move, and a capture of the piece
+        assert_eq!(dtz("k7/8/1K6/8/8/8/8/7Q w - - 0 1"), Some(1));
+        assert_eq!(dtz("k7/8/1K6/8/8/8/8/7R w - - 0 1"), Some(1));
+        assert_eq!(dtz("8/8/8/8/8/8/6Q1/K6k b - - 0 1"), Some(0));
+        // Every king move runs into a mate in one
+        assert_eq!(dtz("k7/8/2K5/1Q6/8/8/8/8 b - - 0 1"), Some(-2));
+    }
+}
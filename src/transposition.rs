@@ -0,0 +1,191 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    OnceLock,
+};
+
+use crate::settings::HASH_SIZE_MB;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Bound {
+    Exact,
+    // Score is at least this, from a beta cutoff
+    Lower,
+    // Score is at most this, no move raised alpha
+    Upper,
+}
+
+#[derive(Copy, Clone)]
+pub struct Entry {
+    pub depth: i32,
+    pub score: i32,
+    pub bound: Bound,
+    // Index into get_possible_moves, which is deterministic for a position
+    pub move_index: Option<usize>,
+}
+
+const USED: u64 = 1 << 40;
+const NO_MOVE: u64 = 0xff;
+
+impl Entry {
+    fn pack(&self) -> u64 {
+        let bound = match self.bound {
+            Bound::Exact => 0,
+            Bound::Lower => 1,
+            Bound::Upper => 2,
+        };
+        let move_index = match self.move_index {
+            Some(i) if (i as u64) < NO_MOVE => i as u64,
+            _ => NO_MOVE,
+        };
+        (self.score as i16 as u16 as u64)
+            | (self.depth.clamp(0, 255) as u64) << 16
+            | bound << 24
+            | move_index << 32
+            | USED
+    }
+
+    fn unpack(data: u64) -> Entry {
+        let bound = match (data >> 24) & 0b11 {
+            0 => Bound::Exact,
+            1 => Bound::Lower,
+            _ => Bound::Upper,
+        };
+        let move_index = (data >> 32) & 0xff;
+        Entry {
+            score: data as u16 as i16 as i32,
+            depth: ((data >> 16) & 0xff) as i32,
+            bound,
+            move_index: if move_index == NO_MOVE {
+                None
+            } else {
+                Some(move_index as usize)
+            },
+        }
+    }
+}
+
+// Shared between search threads without locking. Each slot stores the key
+// XORed with the data, so a slot torn by two threads writing at once fails
+// the key check instead of returning another position's data
+pub struct TranspositionTable {
+    slots: Vec<[AtomicU64; 2]>,
+}
+
+impl TranspositionTable {
+    pub fn new(size_mb: usize) -> Self {
+        let count = usize::max(size_mb * 1024 * 1024 / 16, 1);
+        Self {
+            slots: (0..count)
+                .map(|_| [AtomicU64::new(0), AtomicU64::new(0)])
+                .collect(),
+        }
+    }
+
+    fn slot(&self, key: u64) -> &[AtomicU64; 2] {
+        &self.slots[(key % self.slots.len() as u64) as usize]
+    }
+
+    pub fn probe(&self, key: u64) -> Option<Entry> {
+        let [check, data] = self.slot(key);
+        let data = data.load(Ordering::Relaxed);
+        if data & USED == 0 || check.load(Ordering::Relaxed) ^ data != key {
+            return None;
+        }
+        Some(Entry::unpack(data))
+    }
+
+    pub fn store(&self, key: u64, entry: Entry) {
+        // Keep deeper results for the same position
+        if let Some(existing) = self.probe(key) {
+            if existing.depth > entry.depth && entry.bound != Bound::Exact {
+                return;
+            }
+        }
+        let [check, data] = self.slot(key);
+        let packed = entry.pack();
+        check.store(key ^ packed, Ordering::Relaxed);
+        data.store(packed, Ordering::Relaxed);
+    }
+
+    pub fn clear(&self) {
+        for [check, data] in self.slots.iter() {
+            check.store(0, Ordering::Relaxed);
+            data.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+static TABLE: OnceLock<TranspositionTable> = OnceLock::new();
+
+pub fn table() -> &'static TranspositionTable {
+    TABLE.get_or_init(|| TranspositionTable::new(HASH_SIZE_MB))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(depth: i32, score: i32, bound: Bound, move_index: Option<usize>) -> Entry {
+        Entry {
+            depth,
+            score,
+            bound,
+            move_index,
+        }
+    }
+
+    fn same(a: Entry, b: Entry) -> bool {
+        (a.depth, a.score, a.move_index) == (b.depth, b.score, b.move_index) && a.bound == b.bound
+    }
+
+    #[test]
+    fn pack_round_trip() {
+        let entries = [
+            entry(0, 0, Bound::Exact, None),
+            entry(7, -31000, Bound::Lower, Some(0)),
+            entry(255, 31000, Bound::Upper, Some(254)),
+            entry(12, -1, Bound::Exact, Some(37)),
+        ];
+        for original in entries {
+            let packed = original.pack();
+            assert_ne!(packed & USED, 0);
+            assert!(same(Entry::unpack(packed), original));
+        }
+    }
+
+    #[test]
+    fn pack_clamps() {
+        let unpacked = Entry::unpack(entry(300, 5, Bound::Exact, Some(400)).pack());
+        assert_eq!(unpacked.depth, 255);
+        assert_eq!(unpacked.move_index, None);
+        assert_eq!(
+            Entry::unpack(entry(-3, 5, Bound::Exact, None).pack()).depth,
+            0
+        );
+    }
+
+    #[test]
+    fn store_and_probe() {
+        let table = TranspositionTable::new(1);
+        let key = 0x1234_5678_9abc_def0;
+        assert!(table.probe(key).is_none());
+
+        table.store(key, entry(6, 50, Bound::Exact, Some(3)));
+        assert!(same(
+            table.probe(key).unwrap(),
+            entry(6, 50, Bound::Exact, Some(3))
+        ));
+        // Same slot, different position
+        let other = key + table.slots.len() as u64;
+        assert!(table.probe(other).is_none());
+
+        // Shallower bounds don't replace deeper results, exact scores do
+        table.store(key, entry(2, 10, Bound::Lower, None));
+        assert_eq!(table.probe(key).unwrap().depth, 6);
+        table.store(key, entry(2, 10, Bound::Exact, None));
+        assert_eq!(table.probe(key).unwrap().depth, 2);
+
+        table.clear();
+        assert!(table.probe(key).is_none());
+    }
+}
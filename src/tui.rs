@@ -0,0 +1,752 @@
+use std::{
+    fs, io,
+    path::Path,
+    sync::mpsc::{self, Receiver},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout, Position},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Paragraph},
+    DefaultTerminal, Frame,
+};
+
+use crate::{
+    book::book_move,
+    clock::{clock_repr, time_result, Clock, TimeControl},
+    fen::{parse_fen, to_fen},
+    gamestate::{parse_layout, GameState},
+    history::History,
+    moves::Move,
+    render::{
+        checked_king, glyph, piece_at, BLACK_PIECE, CHECK, DARK_MOVED, DARK_SQUARE, LIGHT_MOVED,
+        LIGHT_SQUARE, WHITE_PIECE,
+    },
+    rng::GameRng,
+    san::{parse_move, san_line, to_san},
+    settings::{BOARD_WIDTH, SEARCH_DEPTH},
+    simulation::{
+        analyse_with_limits, score_repr, AnalysisResult, Progress, SearchInfo, SearchLimits,
+        SearchOptions, MAX_DEPTH,
+    },
+    skill::{Handicap, Skill, MAX_SKILL},
+    transposition::table,
+    utils::{parse_duration, parse_pos_notation, pos_notation, Vect, LETTERS},
+};
+
+// Between redraws while waiting for a key, so the clock and analysis update
+const TICK: Duration = Duration::from_millis(50);
+
+const CURSOR: u8 = 74;
+const SELECTED: u8 = 110;
+const TARGET: u8 = 151;
+
+const HELP: &str = "Arrows and space or enter pick squares. Commands: go, stop, analyse, \
+depth <n>, time <t>, clock <control>, skill <0-20>, elo <n>, flip, undo, redo, goto <ply>, \
+new [pawn|knight|queen], load <fen|pgn>, save <pgn>, fen, quit";
+
+// Search running on its own thread, reporting each iteration
+struct Search {
+    limits: SearchLimits,
+    handle: JoinHandle<AnalysisResult>,
+    infos: Receiver<SearchInfo>,
+    // Play the move found, rather than only analysing
+    play: bool,
+}
+
+struct Tui {
+    history: History,
+    // Colour the engine plays when not analysing
+    engine_black: bool,
+    // Search every position reached without ever playing a move
+    analysing: bool,
+    view_black: bool,
+    limits: SearchLimits,
+    options: SearchOptions,
+    search: Option<Search>,
+    // Iterations of the latest search, newest last, and the position searched
+    // for writing their lines in SAN
+    infos: Vec<SearchInfo>,
+    infos_root: GameState,
+    // Squares counted from white's a1
+    cursor: Vect,
+    selected: Option<Vect>,
+    command: String,
+    message: String,
+    // Time each side has used, white first, and when the side to move started
+    used: [Duration; 2],
+    turn_start: Instant,
+    clock: Option<Clock>,
+    rng: GameRng,
+    quit: bool,
+}
+
+impl Tui {
+    fn new(options: &SearchOptions) -> Self {
+        let mut options = *options;
+        options.progress = Progress::Quiet;
+        let start = parse_layout();
+        Self {
+            infos_root: start.clone(),
+            history: History::new(start),
+            engine_black: true,
+            analysing: false,
+            view_black: false,
+            limits: SearchLimits::depth(SEARCH_DEPTH),
+            options,
+            search: None,
+            infos: Vec::new(),
+            cursor: Vect { x: 4, y: 1 },
+            selected: None,
+            command: String::new(),
+            message: String::from(HELP),
+            used: [Duration::ZERO; 2],
+            turn_start: Instant::now(),
+            clock: None,
+            rng: GameRng::new(options.seed),
+            quit: false,
+        }
+    }
+
+    // Over on the board or on time
+    fn result(&self) -> Option<&'static str> {
+        match self.clock.as_ref().and_then(|clock| clock.flagged()) {
+            Some(black) => Some(time_result(black)),
+            None => self.history.current().game_result(),
+        }
+    }
+
+    // Runs the clock of the side to move, or stops it once the game is over
+    fn restart_clock(&mut self) {
+        let over = self.result().is_some();
+        let black = self.history.current().engine_black;
+        if let Some(clock) = &mut self.clock {
+            if over {
+                clock.stop();
+            } else {
+                clock.start(black);
+            }
+        }
+    }
+
+    fn start_search(&mut self, play: bool) {
+        let game_state = self.history.current();
+        if self.result().is_some() {
+            return;
+        }
+        if play {
+            if let Some(mov) = book_move(&game_state, &mut self.rng) {
+                self.message = format!("Book move {}", mov.coordinate(game_state.engine_black));
+                self.play_move(mov, None);
+                return;
+            }
+        }
+        let (sender, infos) = mpsc::channel();
+        let skill = self.options.skill;
+        let (mut limits, options) = if play {
+            (skill.limit(&self.limits), skill.options(&self.options))
+        } else {
+            (SearchLimits::depth(MAX_DEPTH), self.options)
+        };
+        if let (true, Some(clock)) = (play, &self.clock) {
+            let (soft, hard) = clock.search_times(game_state.engine_black);
+            limits.soft_time = Some(soft);
+            limits.time = Some(limits.time.map_or(hard, |time| time.min(hard)));
+        }
+        limits.stop = Default::default();
+        limits.listener = Some(sender);
+
+        self.infos.clear();
+        self.infos_root = game_state.clone();
+        let thread_limits = limits.clone();
+        let handle =
+            thread::spawn(move || analyse_with_limits(&game_state, &thread_limits, &options));
+        self.search = Some(Search {
+            limits,
+            handle,
+            infos,
+            play,
+        });
+    }
+
+    // Throws away any search in progress
+    fn stop_search(&mut self) {
+        if let Some(search) = self.search.take() {
+            search.limits.stop();
+            let _ = search.handle.join();
+        }
+    }
+
+    // Picks up the lines streamed from the search, and its move once done
+    fn update(&mut self) {
+        let flagged = self.clock.as_ref().and_then(|clock| clock.flagged());
+        if let (Some(black), true) = (flagged, self.clock.as_ref().is_some_and(Clock::running)) {
+            self.stop_search();
+            self.restart_clock();
+            self.message = String::from(time_result(black));
+            return;
+        }
+        let Some(search) = &self.search else {
+            return;
+        };
+        self.infos.extend(search.infos.try_iter());
+        if !search.handle.is_finished() {
+            return;
+        }
+        let search = self.search.take().unwrap();
+        self.infos.extend(search.infos.try_iter());
+        let analysis = search.handle.join().unwrap();
+        if !search.play {
+            return;
+        }
+        let skill = self.options.skill;
+        let choice = skill
+            .choose(&analysis.move_scores, &mut self.rng)
+            .map(|choice| (choice.mov, analysis.score - choice.score));
+        match choice {
+            Some((mov, loss)) => {
+                if loss > 0 {
+                    self.message = format!(
+                        "Skill level {} chose a move {} below the best",
+                        skill.level(),
+                        score_repr(loss)
+                    );
+                }
+                self.play_move(mov, Some(analysis))
+            }
+            None => self.message = String::from("No moves found"),
+        }
+    }
+
+    fn engine_to_move(&self) -> bool {
+        !self.analysing && self.history.current().engine_black == self.engine_black
+    }
+
+    // Searches the new position if the engine is to move or analysing
+    fn position_changed(&mut self, engine_may_move: bool) {
+        self.stop_search();
+        self.selected = None;
+        if self.analysing {
+            self.start_search(false);
+        } else if engine_may_move && self.engine_to_move() {
+            self.start_search(true);
+        }
+    }
+
+    fn play_move(&mut self, mov: Move, analysis: Option<AnalysisResult>) {
+        let mover = self.history.current().engine_black;
+        self.used[mover as usize] += self.turn_start.elapsed();
+        self.turn_start = Instant::now();
+        self.history.push(mov, analysis);
+        if let Some(clock) = &mut self.clock {
+            clock.press();
+        }
+        if let Some(result) = self.result() {
+            self.restart_clock();
+            self.message = String::from(result);
+        }
+        self.position_changed(true);
+    }
+
+    fn user_move(&mut self, mov: Move) {
+        if let Some(result) = self.result() {
+            self.message = String::from(result);
+            return;
+        }
+        if self.search.as_ref().is_some_and(|search| search.play) {
+            self.message = String::from("The engine is thinking, stop it first");
+            return;
+        }
+        let san = to_san(&self.history.current(), &mov);
+        self.message = format!("Played {}", san);
+        self.play_move(mov, None);
+    }
+
+    fn select_square(&mut self) {
+        let game_state = self.history.current();
+        let own = piece_at(&game_state, self.cursor)
+            .is_some_and(|(_, white)| white != game_state.engine_black);
+        let Some(from) = self.selected else {
+            if own {
+                self.selected = Some(self.cursor);
+            }
+            return;
+        };
+        let text = pos_notation(&from, false) + &pos_notation(&self.cursor, false);
+        // Pawns reaching the last rank become queens, type the move for others
+        match parse_move(&text, &game_state).or_else(|| parse_move(&(text + "q"), &game_state)) {
+            Some(mov) => self.user_move(mov),
+            None if own && from != self.cursor => self.selected = Some(self.cursor),
+            None => self.selected = None,
+        }
+    }
+
+    // Where the selected piece can go
+    fn targets(&self) -> Vec<Vect> {
+        let Some(from) = self.selected else {
+            return Vec::new();
+        };
+        let game_state = self.history.current();
+        let from = pos_notation(&from, false);
+        game_state
+            .get_legal_moves(false)
+            .iter()
+            .map(|mov| mov.coordinate(game_state.engine_black))
+            .filter(|text| text[0..2] == from)
+            .filter_map(|text| parse_pos_notation(&text[2..4], false))
+            .collect()
+    }
+
+    fn load(&mut self, text: &str) {
+        let history = if Path::new(text).is_file() {
+            let loaded = fs::read_to_string(text)
+                .map_err(|e| e.to_string())
+                .and_then(|pgn| History::from_pgn(&pgn));
+            match loaded {
+                Ok((history, stopped)) => {
+                    self.message = match stopped {
+                        Some(san) => format!("Stopped at illegal move {}", san),
+                        None => format!("Loaded {}", text),
+                    };
+                    history
+                }
+                Err(e) => {
+                    self.message = format!("Couldn't load {}: {}", text, e);
+                    return;
+                }
+            }
+        } else {
+            match parse_fen(text) {
+                Ok(game_state) => History::new(game_state),
+                Err(e) => {
+                    self.message = format!("Invalid FEN: {}", e);
+                    return;
+                }
+            }
+        };
+        self.new_game(history);
+    }
+
+    fn new_game(&mut self, history: History) {
+        self.stop_search();
+        self.history = history;
+        // Searches replay from the seed only from an empty table
+        table().clear();
+        self.rng = GameRng::new(self.options.seed);
+        self.used = [Duration::ZERO; 2];
+        self.turn_start = Instant::now();
+        self.clock = self.clock.as_ref().map(|clock| Clock::new(clock.control));
+        self.restart_clock();
+        self.position_changed(false);
+    }
+
+    fn navigate(&mut self, moved: bool) {
+        if moved {
+            self.restart_clock();
+            self.position_changed(false);
+        } else {
+            self.message = format!("Can't go there from ply {}", self.history.ply());
+        }
+    }
+
+    fn command(&mut self, line: &str) {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((&command, args)) = words.split_first() else {
+            return;
+        };
+        self.message.clear();
+        match (command, args) {
+            ("go", []) => {
+                self.analysing = false;
+                self.engine_black = self.history.current().engine_black;
+                self.position_changed(true);
+            }
+            ("stop", []) => {
+                // The move found so far is played, analysis just ends
+                match &self.search {
+                    Some(search) => search.limits.stop(),
+                    None => self.message = String::from("Not searching"),
+                }
+                self.analysing = false;
+            }
+            ("analyse" | "analyze", []) => {
+                self.analysing = true;
+                self.position_changed(false);
+            }
+            ("depth" | "nodes" | "mate", [_]) => match SearchLimits::parse(line) {
+                Some(limits) => self.limits = limits,
+                None => self.message = format!("{} must be non-negative int", command),
+            },
+            ("time", [time]) => match parse_duration(time) {
+                Some(time) => {
+                    self.limits = SearchLimits::depth(MAX_DEPTH);
+                    self.limits.time = Some(time);
+                }
+                None => self.message = String::from("Time must be a number with s, ms or m"),
+            },
+            ("clock", ["off"]) => self.clock = None,
+            ("clock", [_, ..]) => match TimeControl::parse(&args.join(" ")) {
+                Some(control) => {
+                    self.limits = SearchLimits::depth(MAX_DEPTH);
+                    self.clock = Some(Clock::new(control));
+                    self.restart_clock();
+                    self.message = format!("Clock set to {} each", control.repr());
+                }
+                None => {
+                    self.message =
+                        String::from("Time control must be like 5m+3s, 40/90m or 15m delay 5s")
+                }
+            },
+            ("flip", []) => self.view_black = !self.view_black,
+            ("undo", []) => {
+                let moved = self.history.undo();
+                self.navigate(moved);
+            }
+            ("redo", []) => {
+                let moved = self.history.redo();
+                self.navigate(moved);
+            }
+            ("goto", [ply]) => match ply.parse() {
+                Ok(ply) => {
+                    let moved = self.history.goto(ply);
+                    self.navigate(moved);
+                }
+                Err(_) => self.message = String::from("Ply must be non-negative int"),
+            },
+            ("new", []) => {
+                self.engine_black = true;
+                self.analysing = false;
+                self.new_game(History::new(parse_layout()));
+            }
+            ("new", [odds]) => match Handicap::parse(odds) {
+                Some(handicap) => {
+                    self.engine_black = handicap.engine_black();
+                    self.analysing = false;
+                    self.new_game(History::new(handicap.start()));
+                    self.message = format!("Engine gives {}", handicap.name());
+                    self.position_changed(true);
+                }
+                None => self.message = String::from("Odds must be pawn, knight or queen"),
+            },
+            ("skill", [level]) => match level.parse().ok().and_then(Skill::new) {
+                Some(skill) => self.options.skill = skill,
+                None => self.message = format!("Skill must be 0 to {}", MAX_SKILL),
+            },
+            ("elo", [elo]) => match elo.parse() {
+                Ok(elo) => {
+                    self.options.skill = Skill::from_elo(elo);
+                    self.message = format!("Playing at skill level {}", self.options.skill.level());
+                }
+                Err(_) => self.message = String::from("Elo must be non-negative int"),
+            },
+            ("load", [_, ..]) => self.load(&args.join(" ")),
+            ("save", [path]) => {
+                let flagged = self.clock.as_ref().and_then(|clock| clock.flagged());
+                let mut tags = vec![("Seed", self.rng.seed.to_string())];
+                if let Some(control) = self
+                    .clock
+                    .as_ref()
+                    .and_then(|clock| clock.control.pgn_tag())
+                {
+                    tags.push(("TimeControl", control));
+                }
+                let pgn = self
+                    .history
+                    .to_pgn(self.engine_black, flagged.map(time_result), tags);
+                self.message = match fs::write(path, pgn) {
+                    Ok(()) => format!("Saved {} plies to {}", self.history.ply(), path),
+                    Err(e) => format!("Couldn't write {}: {}", path, e),
+                }
+            }
+            ("fen", []) => self.message = to_fen(&self.history.current()),
+            ("help", []) => self.message = String::from(HELP),
+            ("quit" | "exit", []) => self.quit = true,
+            _ => match parse_move(line, &self.history.current()) {
+                Some(mov) => self.user_move(mov),
+                None => self.message = format!("Illegal move or unknown command: {}", line),
+            },
+        }
+    }
+
+    fn key(&mut self, key: KeyEvent) {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            self.quit = true;
+            return;
+        }
+        // Up is towards the top of the screen whichever way the board faces
+        let (forward, right) = if self.view_black { (-1, -1) } else { (1, 1) };
+        let step = match key.code {
+            KeyCode::Up => Some((0, forward)),
+            KeyCode::Down => Some((0, -forward)),
+            KeyCode::Right => Some((right, 0)),
+            KeyCode::Left => Some((-right, 0)),
+            _ => None,
+        };
+        if let Some((x, y)) = step {
+            self.cursor.x = (self.cursor.x + x).clamp(0, BOARD_WIDTH - 1);
+            self.cursor.y = (self.cursor.y + y).clamp(0, BOARD_WIDTH - 1);
+            return;
+        }
+        match key.code {
+            KeyCode::Enter if !self.command.is_empty() => {
+                let line = std::mem::take(&mut self.command);
+                self.command(line.trim());
+            }
+            KeyCode::Enter => self.select_square(),
+            KeyCode::Char(' ') if self.command.is_empty() => self.select_square(),
+            KeyCode::Char(char) => self.command.push(char),
+            KeyCode::Backspace => {
+                self.command.pop();
+            }
+            KeyCode::Esc => {
+                self.command.clear();
+                self.selected = None;
+            }
+            _ => (),
+        }
+    }
+
+    fn board_lines(&self) -> Vec<Line<'static>> {
+        let game_state = self.history.current();
+        let check = checked_king(&game_state);
+        let targets = self.targets();
+        let last_move = self.history.last_entry().and_then(|entry| {
+            let text = entry.mov.coordinate(!game_state.engine_black);
+            parse_pos_notation(text.get(0..2)?, false)
+                .zip(parse_pos_notation(text.get(2..4)?, false))
+        });
+
+        let mut lines = Vec::new();
+        for row in 0..BOARD_WIDTH {
+            let rank = if self.view_black {
+                row
+            } else {
+                BOARD_WIDTH - 1 - row
+            };
+            let mut spans = vec![Span::raw(format!("{} ", rank + 1))];
+            for column in 0..BOARD_WIDTH {
+                let file = if self.view_black {
+                    BOARD_WIDTH - 1 - column
+                } else {
+                    column
+                };
+                let square = Vect { x: file, y: rank };
+                let light = (file + rank) % 2 == 1;
+                let moved = last_move.is_some_and(|(from, to)| from == square || to == square);
+                let background = if square == self.cursor {
+                    CURSOR
+                } else if Some(square) == self.selected {
+                    SELECTED
+                } else if targets.contains(&square) {
+                    TARGET
+                } else if check == Some(square) {
+                    CHECK
+                } else {
+                    match (moved, light) {
+                        (true, true) => LIGHT_MOVED,
+                        (true, false) => DARK_MOVED,
+                        (false, true) => LIGHT_SQUARE,
+                        (false, false) => DARK_SQUARE,
+                    }
+                };
+                let (text, foreground) = match piece_at(&game_state, square) {
+                    Some((piece, white)) => (
+                        format!(" {} ", glyph(piece.class)),
+                        if white { WHITE_PIECE } else { BLACK_PIECE },
+                    ),
+                    None => (String::from("   "), WHITE_PIECE),
+                };
+                let style = Style::default()
+                    .bg(Color::Indexed(background))
+                    .fg(Color::Indexed(foreground));
+                spans.push(Span::styled(text, style));
+            }
+            lines.push(Line::from(spans));
+        }
+        let mut files = String::from("  ");
+        for column in 0..BOARD_WIDTH {
+            let file = if self.view_black {
+                BOARD_WIDTH - 1 - column
+            } else {
+                column
+            };
+            files += &format!(" {} ", LETTERS[file as usize]);
+        }
+        lines.push(Line::from(files));
+        lines
+    }
+
+    fn clock_lines(&self) -> Vec<Line<'static>> {
+        let to_move = self.history.current().engine_black;
+        // Time left on a clock, otherwise the time used
+        let mut lines: Vec<Line> = [false, true]
+            .iter()
+            .map(|&black| {
+                let time = match &self.clock {
+                    Some(clock) => clock.remaining(black),
+                    None if black == to_move => {
+                        self.used[black as usize] + self.turn_start.elapsed()
+                    }
+                    None => self.used[black as usize],
+                };
+                let name = if black { "Black" } else { "White" };
+                let marker = if black == to_move { ">" } else { " " };
+                Line::from(format!("{} {} {:>7}", marker, name, clock_repr(time)))
+            })
+            .collect();
+        if let Some(clock) = &self.clock {
+            lines.push(Line::from(format!("  {}", clock.control.repr())));
+        }
+        let limit = match (self.limits.time, self.limits.nodes, self.limits.mate) {
+            (Some(time), _, _) => format!("{:.1}s per move", time.as_secs_f64()),
+            (_, Some(nodes), _) => format!("{} nodes", nodes),
+            (_, _, Some(mate)) => format!("mate in {}", mate),
+            _ => format!("depth {}", self.limits.depth),
+        };
+        lines.push(Line::from(format!("  Limit {}", limit)));
+        if !self.options.skill.is_full() {
+            lines.push(Line::from(format!(
+                "  Skill level {}",
+                self.options.skill.level()
+            )));
+        }
+        lines.push(Line::from(format!("  Seed {}", self.rng.seed)));
+        lines
+    }
+
+    fn move_lines(&self) -> Vec<Line<'static>> {
+        let mut lines: Vec<Line> = Vec::new();
+        let mut spans: Vec<Span> = Vec::new();
+        for (i, entry) in self.history.entries().iter().enumerate() {
+            let number = self.history.move_number(i);
+            let white = !number.ends_with("...");
+            if (white || i == 0) && !spans.is_empty() {
+                lines.push(Line::from(std::mem::take(&mut spans)));
+            }
+            if white || i == 0 {
+                spans.push(Span::raw(format!("{:<6}", number)));
+            }
+            let style = if i + 1 == self.history.ply() {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else if i >= self.history.ply() {
+                Style::default().fg(Color::DarkGray)
+            } else {
+                Style::default()
+            };
+            spans.push(Span::styled(format!("{:<7}", entry.san), style));
+            spans.push(Span::raw(" "));
+        }
+        if !spans.is_empty() {
+            lines.push(Line::from(spans));
+        }
+        lines
+    }
+
+    fn analysis_lines(&self) -> Vec<Line<'static>> {
+        let Some(latest) = self.infos.last() else {
+            return vec![Line::from("No search yet, type go or analyse")];
+        };
+        let nps = latest.nodes as u128 * 1000 / u128::max(latest.ms, 1);
+        let mut lines = vec![Line::from(format!(
+            "Depth {}  Score {}  Nodes {}  {} nps  {:.1}s",
+            latest.depth,
+            score_repr(latest.score),
+            latest.nodes,
+            nps,
+            latest.ms as f64 / 1000.0
+        ))];
+        for info in self.infos.iter().rev() {
+            lines.push(Line::from(format!(
+                "{:>3} {:>7}  {}",
+                info.depth,
+                score_repr(info.score),
+                san_line(&self.infos_root, &info.pv)
+            )));
+        }
+        lines
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [top, bottom] =
+            Layout::vertical([Constraint::Min(15), Constraint::Length(4)]).areas(frame.area());
+        let [left, moves, analysis] = Layout::horizontal([
+            Constraint::Length(28),
+            Constraint::Length(24),
+            Constraint::Min(30),
+        ])
+        .areas(top);
+        let [board, clock] =
+            Layout::vertical([Constraint::Length(11), Constraint::Min(5)]).areas(left);
+
+        frame.render_widget(
+            Paragraph::new(self.board_lines()).block(Block::bordered().title("Board")),
+            board,
+        );
+        frame.render_widget(
+            Paragraph::new(self.clock_lines()).block(Block::bordered().title("Clock")),
+            clock,
+        );
+
+        // Keep the latest moves in view
+        let move_lines = self.move_lines();
+        let scroll = move_lines
+            .len()
+            .saturating_sub(moves.height.saturating_sub(2) as usize);
+        frame.render_widget(
+            Paragraph::new(move_lines)
+                .scroll((scroll as u16, 0))
+                .block(Block::bordered().title("Moves")),
+            moves,
+        );
+
+        let title = match &self.search {
+            Some(search) if search.play => "Engine thinking",
+            Some(_) => "Analysing",
+            None => "Analysis",
+        };
+        frame.render_widget(
+            Paragraph::new(self.analysis_lines()).block(Block::bordered().title(title)),
+            analysis,
+        );
+
+        let prompt = format!("> {}", self.command);
+        let cursor_x = bottom.x + 1 + prompt.chars().count() as u16;
+        frame.render_widget(
+            Paragraph::new(vec![Line::from(prompt), Line::from(self.message.clone())])
+                .block(Block::bordered().title("Command")),
+            bottom,
+        );
+        frame.set_cursor_position(Position::new(cursor_x, bottom.y + 1));
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
+        while !self.quit {
+            self.update();
+            terminal.draw(|frame| self.draw(frame))?;
+            if event::poll(TICK)? {
+                if let Event::Key(key) = event::read()? {
+                    if key.kind == KeyEventKind::Press {
+                        self.key(key);
+                    }
+                }
+            }
+        }
+        self.stop_search();
+        Ok(())
+    }
+}
+
+// Full screen board, move list, clock and live analysis, playing the same
+// game as the command line
+pub fn tui(options: &SearchOptions) {
+    let mut terminal = ratatui::init();
+    let result = Tui::new(options).run(&mut terminal);
+    ratatui::restore();
+    if let Err(e) = result {
+        println!("Terminal error: {}", e);
+    }
+}
@@ -0,0 +1,512 @@
+use std::{fs, time::Instant};
+
+use crate::{
+    development::{
+        max_development, DevelopmentTerms, DEVELOPMENT_PARAM_COUNT, DEVELOPMENT_PARAM_NAMES,
+        DEVELOPMENT_SCALE, POSITION, TRAP,
+    },
+    evaluation::{feature_difference, params, Features, PARAM_COUNT, PARAM_NAMES, PAWN_VALUE},
+    fen::parse_epd,
+    gamestate::GameState,
+    history::History,
+    moves::Move,
+    pgn::parse_pgn,
+    see::{is_capture, see},
+};
+
+// Development totals are multiplied by this before comparing moves, so a move
+// scoring 0.1 higher is e times likelier to be the one played
+const MOVE_SHARPNESS: f64 = 10.;
+
+struct Sample {
+    // White minus black, so independent of side to move
+    features: Features,
+    // 1 for a white win, 0.5 for a draw and 0 for a black win
+    result: f64,
+}
+
+// A move played in a game and every move it was chosen from
+struct MoveSample {
+    moves: Vec<DevelopmentTerms>,
+    played: usize,
+}
+
+fn parse_result(text: &str) -> Option<f64> {
+    match text {
+        "1-0" => Some(1.),
+        "0-1" => Some(0.),
+        "1/2-1/2" => Some(0.5),
+        _ => None,
+    }
+}
+
+fn sample(game_state: &GameState, result: f64) -> Sample {
+    let mut features = feature_difference(game_state);
+    if game_state.engine_black {
+        for feature in features.iter_mut() {
+            *feature = -*feature;
+        }
+    }
+    Sample { features, result }
+}
+
+// Not in check and with no capture winning material, so the static evaluation
+// can be compared with the result
+fn is_quiet(game_state: &GameState) -> bool {
+    !game_state.in_check(false)
+        && game_state
+            .get_legal_moves(false)
+            .iter()
+            .all(|mov| !is_capture(game_state, mov) || see(game_state, mov) <= 0)
+}
+
+fn move_sample(game_state: &GameState, played: &Move) -> Option<MoveSample> {
+    let moves = game_state.get_legal_moves(false);
+    if moves.len() < 2 {
+        return None;
+    }
+    Some(MoveSample {
+        played: moves.iter().position(|mov| mov == played)?,
+        moves: moves
+            .iter()
+            .map(|mov| DevelopmentTerms::new(game_state, mov))
+            .collect(),
+    })
+}
+
+fn epd_samples(text: &str) -> Vec<Sample> {
+    let mut samples = Vec::new();
+    let mut skipped = 0;
+
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let record = match parse_epd(line) {
+            Ok(record) => record,
+            Err(e) => {
+                println!("Line {}: {}", i + 1, e);
+                skipped += 1;
+                continue;
+            }
+        };
+        let result = match record.operation("c9").and_then(|ops| ops.first()) {
+            Some(text) => parse_result(text),
+            None => None,
+        };
+        let result = match result {
+            Some(r) => r,
+            None => {
+                println!("Line {}: missing or invalid c9 result", i + 1);
+                skipped += 1;
+                continue;
+            }
+        };
+        samples.push(sample(&record.game_state, result));
+    }
+
+    if skipped > 0 {
+        println!("Skipped {} lines", skipped);
+    }
+    samples
+}
+
+// Quiet positions from every game with a result, and the moves played in
+// them by the side that didn't lose
+fn pgn_samples(text: &str) -> (Vec<Sample>, Vec<MoveSample>) {
+    let mut samples = Vec::new();
+    let mut move_samples = Vec::new();
+    let mut skipped = 0;
+
+    for game in parse_pgn(text) {
+        let history = match (
+            game.result().and_then(parse_result),
+            History::from_game(&game),
+        ) {
+            (Some(result), Ok((history, None))) => (result, history),
+            _ => {
+                skipped += 1;
+                continue;
+            }
+        };
+        let (result, history) = history;
+        let mut game_state = history.start().clone();
+        for entry in history.entries() {
+            if is_quiet(&game_state) {
+                samples.push(sample(&game_state, result));
+            }
+            let mover_result = if game_state.engine_black {
+                1. - result
+            } else {
+                result
+            };
+            if mover_result >= 0.5 {
+                move_samples.extend(move_sample(&game_state, &entry.mov));
+            }
+            game_state = entry.game_state.clone();
+        }
+    }
+
+    if skipped > 0 {
+        println!(
+            "Skipped {} games without a result or with an illegal move",
+            skipped
+        );
+    }
+    (samples, move_samples)
+}
+
+// PGN games if the file ends in .pgn, else EPD lines with a c9 result
+fn load_samples(path: &str) -> Result<(Vec<Sample>, Vec<MoveSample>), String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    if path.to_lowercase().ends_with(".pgn") {
+        Ok(pgn_samples(&text))
+    } else {
+        Ok((epd_samples(&text), Vec::new()))
+    }
+}
+
+fn sigmoid(eval: f64, k: f64) -> f64 {
+    1. / (1. + f64::powf(10., -k * eval / 400.))
+}
+
+fn mean_error(samples: &[Sample], evals: &[i32], k: f64) -> f64 {
+    let total: f64 = samples
+        .iter()
+        .zip(evals)
+        .map(|(sample, &eval)| {
+            let diff = sample.result - sigmoid(eval as f64, k);
+            diff * diff
+        })
+        .sum();
+    total / samples.len() as f64
+}
+
+// Mean log loss of the moves played, each position's moves being chosen
+// between by their development totals
+fn move_error(samples: &[MoveSample], weights: &[i32; DEVELOPMENT_PARAM_COUNT]) -> f64 {
+    let total: f64 = samples
+        .iter()
+        .map(|sample| {
+            let scores: Vec<f64> = sample
+                .moves
+                .iter()
+                .map(|terms| terms.weigh(weights).total() as f64 * MOVE_SHARPNESS)
+                .collect();
+            let max = scores.iter().copied().fold(f64::MIN, f64::max);
+            let log_sum = max + scores.iter().map(|s| (s - max).exp()).sum::<f64>().ln();
+            log_sum - scores[sample.played]
+        })
+        .sum();
+    total / samples.len() as f64
+}
+
+fn find_scaling(samples: &[Sample], evals: &[i32]) -> f64 {
+    // Refine the scaling constant one decimal place at a time
+    let mut best_k = 1.;
+    let mut best_error = mean_error(samples, evals, best_k);
+    for step in [0.1, 0.01, 0.001] {
+        let (start, end) = (best_k - 10. * step, best_k + 10. * step);
+        let mut k = f64::max(start, step);
+        while k <= end {
+            let error = mean_error(samples, evals, k);
+            if error < best_error {
+                best_error = error;
+                best_k = k;
+            }
+            k += step;
+        }
+    }
+    best_k
+}
+
+// Evaluation weights by the logistic error against the game results
+fn tune_weights(samples: &[Sample], weights: &mut [i32; PARAM_COUNT]) {
+    let score = |features: &Features, weights: &[i32; PARAM_COUNT]| -> i32 {
+        weights.iter().zip(features).map(|(w, f)| w * f).sum()
+    };
+    let mut evals: Vec<i32> = samples
+        .iter()
+        .map(|s| score(&s.features, weights))
+        .collect();
+
+    let k = find_scaling(samples, &evals);
+    let mut best_error = mean_error(samples, &evals, k);
+    println!(
+        "Scaling constant {:.3}, starting error {:.6}",
+        k, best_error
+    );
+
+    // Local search, halving the step once no single change helps
+    let mut step = 16;
+    let mut pass = 0;
+    loop {
+        pass += 1;
+        let mut improved = false;
+
+        for (i, weight) in weights.iter_mut().enumerate() {
+            // Pawn value anchors the scale of every other weight
+            if i == PAWN_VALUE {
+                continue;
+            }
+            for delta in [step, -step] {
+                let trial: Vec<i32> = samples
+                    .iter()
+                    .zip(&evals)
+                    .map(|(sample, eval)| eval + delta * sample.features[i])
+                    .collect();
+                let error = mean_error(samples, &trial, k);
+                if error < best_error {
+                    best_error = error;
+                    *weight += delta;
+                    evals = trial;
+                    improved = true;
+                    break;
+                }
+            }
+        }
+
+        println!("Pass {}, step {}, error {:.6}", pass, step, best_error);
+
+        if !improved {
+            if step == 1 {
+                break;
+            }
+            step /= 2;
+        }
+    }
+}
+
+// Development weights by how well they pick out the moves played, keeping
+// every move's total within 0..1
+fn tune_development(samples: &[MoveSample], weights: &mut [i32; DEVELOPMENT_PARAM_COUNT]) {
+    let mut best_error = move_error(samples, weights);
+    println!("Starting development error {:.6}", best_error);
+
+    let mut step = 8;
+    let mut pass = 0;
+    loop {
+        pass += 1;
+        let mut improved = false;
+
+        for i in 0..DEVELOPMENT_PARAM_COUNT {
+            for delta in [step, -step] {
+                let mut trial = *weights;
+                trial[i] += delta;
+                if trial[TRAP..=POSITION].iter().any(|&w| w < 0)
+                    || max_development(&trial) > DEVELOPMENT_SCALE
+                {
+                    continue;
+                }
+                let error = move_error(samples, &trial);
+                if error < best_error {
+                    best_error = error;
+                    *weights = trial;
+                    improved = true;
+                    break;
+                }
+            }
+        }
+
+        println!(
+            "Development pass {}, step {}, error {:.6}",
+            pass, step, best_error
+        );
+
+        if !improved {
+            if step == 1 {
+                break;
+            }
+            step /= 2;
+        }
+    }
+}
+
+pub fn tune(data_path: &str, out_path: &str) {
+    let timer = Instant::now();
+    let (samples, move_samples) = match load_samples(data_path) {
+        Ok(samples) => samples,
+        Err(e) => {
+            println!("Couldn't load positions: {}", e);
+            return;
+        }
+    };
+    if samples.is_empty() && move_samples.is_empty() {
+        println!("No positions to tune on");
+        return;
+    }
+    println!(
+        "Loaded {} positions and {} moves in {} ms",
+        samples.len(),
+        move_samples.len(),
+        timer.elapsed().as_millis()
+    );
+
+    let mut best = params().clone();
+    if !samples.is_empty() {
+        tune_weights(&samples, &mut best.weights);
+    }
+    if !move_samples.is_empty() {
+        tune_development(&move_samples, &mut best.development);
+    }
+
+    for (name, weight) in PARAM_NAMES.iter().zip(best.weights) {
+        println!("{} {}", name, weight);
+    }
+    for (name, weight) in DEVELOPMENT_PARAM_NAMES.iter().zip(best.development) {
+        println!("{} {}", name, weight);
+    }
+
+    match best.save(out_path) {
+        Ok(_) => println!("Wrote {} in {} secs", out_path, timer.elapsed().as_secs()),
+        Err(e) => println!("Couldn't write {}: {}", out_path, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+    use crate::{
+        development::{DEFAULT_DEVELOPMENT_WEIGHTS, SAFETY},
+        evaluation::{EvalParams, QUEEN_VALUE},
+    };
+
+    fn sample(result: f64) -> Sample {
+        Sample {
+            features: Features::default(),
+            result,
+        }
+    }
+
+    #[test]
+    fn sigmoid_scale() {
+        assert_eq!(sigmoid(0., 1.), 0.5);
+        assert!((sigmoid(400., 1.) - 10. / 11.).abs() < 1e-12);
+        assert!((sigmoid(-400., 1.) - 1. / 11.).abs() < 1e-12);
+        assert!((sigmoid(200., 2.) - sigmoid(400., 1.)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn loss() {
+        let samples = [sample(1.), sample(0.), sample(0.5)];
+        // Even evaluations are a quarter off for the decisive games
+        assert!((mean_error(&samples, &[0, 0, 0], 1.) - 0.5 / 3.).abs() < 1e-12);
+        let confident = mean_error(&samples, &[2000, -2000, 0], 1.);
+        assert!(confident < 1e-9);
+        let wrong = mean_error(&samples, &[-2000, 2000, 0], 1.);
+        assert!((wrong - 2. / 3.).abs() < 1e-4);
+    }
+
+    #[test]
+    fn scaling_is_found() {
+        // Results exactly on the curve for k = 1.3 have no error there
+        let evals: Vec<i32> = (-10..=10).map(|i| i * 50).collect();
+        let samples: Vec<Sample> = evals
+            .iter()
+            .map(|&eval| sample(sigmoid(eval as f64, 1.3)))
+            .collect();
+        assert!((find_scaling(&samples, &evals) - 1.3).abs() < 1e-6);
+    }
+
+    #[test]
+    fn samples_are_from_white() {
+        let path = env::temp_dir().join(format!("tune-{}.epd", std::process::id()));
+        fs::write(
+            &path,
+            "# comment\n\
+             4k3/8/8/8/8/8/8/3QK3 w - - c9 \"1-0\";\n\
+             4k3/8/8/8/8/8/8/3QK3 b - - c9 \"1/2-1/2\";\n\
+             4k3/8/8/8/8/8/8/3QK3 b - - c9 \"2-0\";\n\
+             4k3/8/8/8/8/8/8/3QK3 b - -\n",
+        )
+        .unwrap();
+        let (samples, move_samples) = load_samples(path.to_str().unwrap()).unwrap();
+        fs::remove_file(path).unwrap();
+        assert!(move_samples.is_empty());
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[0].result, 1.);
+        assert_eq!(samples[1].result, 0.5);
+        for sample in samples.iter() {
+            assert_eq!(sample.features[QUEEN_VALUE], 1);
+        }
+    }
+
+    #[test]
+    fn samples_from_games() {
+        let path = env::temp_dir().join(format!("tune-{}.pgn", std::process::id()));
+        fs::write(
+            &path,
+            "[Result \"1-0\"]\n\n1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7# 1-0\n\n\
+             [Result \"*\"]\n\n1. d4 d5 *\n",
+        )
+        .unwrap();
+        let (samples, move_samples) = load_samples(path.to_str().unwrap()).unwrap();
+        fs::remove_file(path).unwrap();
+        assert!(!samples.is_empty() && samples.len() <= 7);
+        assert!(samples.iter().all(|sample| sample.result == 1.));
+        // Only the winner's moves, each among all of its legal moves
+        assert_eq!(move_samples.len(), 4);
+        assert_eq!(move_samples[0].moves.len(), 20);
+    }
+
+    fn move_choice(played: (f32, f32), other: (f32, f32)) -> MoveSample {
+        let terms = |(safety, position)| DevelopmentTerms {
+            material: 0,
+            trap: 0.,
+            pawn: 0.,
+            safety,
+            position,
+        };
+        MoveSample {
+            moves: vec![terms(other), terms(played)],
+            played: 1,
+        }
+    }
+
+    #[test]
+    fn development_error() {
+        let samples = [move_choice((1., 0.), (0., 0.))];
+        let error = move_error(&samples, &[0; DEVELOPMENT_PARAM_COUNT]);
+        assert!((error - f64::ln(2.)).abs() < 1e-9);
+        assert!(move_error(&samples, &DEFAULT_DEVELOPMENT_WEIGHTS) < error);
+    }
+
+    #[test]
+    fn development_is_tuned() {
+        // Safe moves are played over ones moving up the board
+        let samples = [
+            move_choice((1., 0.), (0., 2.)),
+            move_choice((1., 0.), (1., 3.)),
+        ];
+        let mut weights = DEFAULT_DEVELOPMENT_WEIGHTS;
+        tune_development(&samples, &mut weights);
+        assert!(weights[SAFETY] > DEFAULT_DEVELOPMENT_WEIGHTS[SAFETY]);
+        assert!(weights[POSITION] < DEFAULT_DEVELOPMENT_WEIGHTS[POSITION]);
+        assert!(max_development(&weights) <= DEVELOPMENT_SCALE);
+    }
+
+    #[test]
+    fn params_round_trip() {
+        let path = env::temp_dir().join(format!("params-{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut params = EvalParams::default();
+        params.development[SAFETY] = 60;
+        params.development[POSITION] = 15;
+        params.save(path).unwrap();
+        let loaded = EvalParams::load(path).unwrap();
+        assert_eq!(loaded.weights, params.weights);
+        assert_eq!(loaded.development, params.development);
+
+        // Safety and a full position score would be over one
+        fs::write(path, "dev_safety 60\ndev_position 20\n").unwrap();
+        let error = EvalParams::load(path).map(|_| ());
+        fs::remove_file(path).unwrap();
+        assert_eq!(
+            error,
+            Err("Development weights add up to more than 120".to_string())
+        );
+    }
+}
@@ -0,0 +1,579 @@
+use std::{
+    io::{self, BufRead},
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    book::book_move,
+    clock::{allocate, Increment},
+    fen::parse_fen,
+    gamestate::{parse_layout, GameState},
+    moves::parse_coordinate,
+    rng::GameRng,
+    simulation::{
+        analyse_with_limits, AnalysisResult, Progress, SearchLimits, SearchOptions, MAX_DEPTH,
+    },
+    skill::{Skill, MAX_ELO, MAX_SKILL, MIN_ELO},
+    transposition::table,
+};
+
+enum Event {
+    Line(String),
+    // Search result tagged with the search it belongs to
+    Done(u64, AnalysisResult),
+}
+
+// Limits for "go", split from the clock and the flags that hold the result back
+struct Go {
+    limits: SearchLimits,
+    // Time allowed once a ponder search becomes a real one
+    ponder_time: Option<Duration>,
+    // Ponder and infinite searches only report after ponderhit or stop
+    hold: bool,
+}
+
+struct Search {
+    limits: SearchLimits,
+    ponder_time: Option<Duration>,
+    hold: bool,
+    // Finished while held, reported on ponderhit or stop
+    finished: Option<AnalysisResult>,
+}
+
+// The position is kept with the side to move as the engine (enemy = false), as
+// in the xboard mode
+struct Uci {
+    game_state: GameState,
+    options: SearchOptions,
+    // Skill Level, or the rating with UCI_LimitStrength
+    skill: Skill,
+    limit_strength: bool,
+    elo: u32,
+    rng: GameRng,
+    searching: Option<Search>,
+    generation: u64,
+    events: Sender<Event>,
+}
+
+fn parse_number<T: std::str::FromStr>(text: Option<&&str>) -> Option<T> {
+    text?.parse().ok()
+}
+
+// Depth, nodes, mate and movetime are read as by the analyse command, the
+// clock gives the time for the side to move
+fn parse_go(args: &[&str], black: bool) -> Option<Go> {
+    let mut rest = Vec::new();
+    let (mut remaining, mut increment, mut moves_to_go) = (None, Duration::ZERO, None);
+    let (mut ponder, mut infinite) = (false, false);
+    let mut tokens = args.iter();
+    while let Some(&token) = tokens.next() {
+        match token {
+            "ponder" => ponder = true,
+            "infinite" => infinite = true,
+            "wtime" | "btime" => {
+                let ms = parse_number(tokens.next())?;
+                if (token == "btime") == black {
+                    remaining = Some(Duration::from_millis(ms));
+                }
+            }
+            "winc" | "binc" => {
+                let ms = parse_number(tokens.next())?;
+                if (token == "binc") == black {
+                    increment = Duration::from_millis(ms);
+                }
+            }
+            "movestogo" => moves_to_go = Some(parse_number(tokens.next())?),
+            _ => rest.push(token),
+        }
+    }
+
+    let mut limits = if rest.is_empty() {
+        SearchLimits::depth(MAX_DEPTH)
+    } else {
+        SearchLimits::parse(&rest.join(" "))?
+    };
+    if let (Some(remaining), None) = (remaining, limits.time) {
+        let increment = match increment {
+            Duration::ZERO => Increment::None,
+            increment => Increment::Fischer(increment),
+        };
+        let (soft, hard) = allocate(remaining, moves_to_go, increment);
+        (limits.soft_time, limits.time) = (Some(soft), Some(hard));
+    }
+    // A ponder search carries on past its time, which only starts on
+    // ponderhit, and an infinite one has none
+    let ponder_time = if ponder {
+        limits.soft_time.or(limits.time)
+    } else {
+        None
+    };
+    if ponder || infinite {
+        (limits.soft_time, limits.time) = (None, None);
+    }
+    Some(Go {
+        limits,
+        ponder_time,
+        hold: ponder || infinite,
+    })
+}
+
+impl Uci {
+    fn new(options: &SearchOptions, events: Sender<Event>) -> Self {
+        Self {
+            game_state: parse_layout(),
+            options: *options,
+            skill: options.skill,
+            limit_strength: false,
+            elo: MAX_ELO,
+            rng: GameRng::new(options.seed),
+            searching: None,
+            generation: 0,
+            events,
+        }
+    }
+
+    fn update_skill(&mut self) {
+        self.options.skill = if self.limit_strength {
+            Skill::from_elo(self.elo)
+        } else {
+            self.skill
+        };
+    }
+
+    // "startpos" or "fen <fen>", then optionally "moves" and moves in
+    // coordinate notation
+    fn position(&mut self, args: &[&str]) {
+        let (setup, moves) = match args.iter().position(|&arg| arg == "moves") {
+            Some(i) => (&args[..i], &args[i + 1..]),
+            None => (args, &[][..]),
+        };
+        let game_state = match setup {
+            ["startpos"] => parse_layout(),
+            ["fen", fen @ ..] => match parse_fen(&fen.join(" ")) {
+                Ok(game_state) => game_state,
+                Err(e) => {
+                    println!("info string Invalid FEN: {}", e);
+                    return;
+                }
+            },
+            _ => {
+                println!("info string Unknown position: {}", setup.join(" "));
+                return;
+            }
+        };
+        self.game_state = game_state;
+        for text in moves {
+            let mov = parse_coordinate(text, &self.game_state, false).filter(|mov| {
+                self.game_state
+                    .is_legal(mov, &self.game_state.perform_move(mov))
+            });
+            match mov {
+                Some(mov) => self.game_state = self.game_state.perform_move(&mov).flipped(),
+                None => {
+                    println!("info string Illegal move: {}", text);
+                    return;
+                }
+            }
+        }
+    }
+
+    fn go(&mut self, args: &[&str]) {
+        self.abort_search();
+        let Some(go) = parse_go(args, self.game_state.engine_black) else {
+            println!("info string Bad limits: go {}", args.join(" "));
+            return;
+        };
+        if !go.hold {
+            if let Some(mov) = book_move(&self.game_state, &mut self.rng) {
+                println!("bestmove {}", mov.coordinate(self.game_state.engine_black));
+                return;
+            }
+        }
+        let skill = self.options.skill;
+        let limits = skill.limit(&go.limits);
+        self.generation += 1;
+
+        let game_state = self.game_state.clone();
+        let thread_limits = limits.clone();
+        let options = skill.options(&self.options);
+        let generation = self.generation;
+        let events = self.events.clone();
+        thread::spawn(move || {
+            let analysis = analyse_with_limits(&game_state, &thread_limits, &options);
+            let _ = events.send(Event::Done(generation, analysis));
+        });
+        self.searching = Some(Search {
+            limits,
+            ponder_time: go.ponder_time,
+            hold: go.hold,
+            finished: None,
+        });
+    }
+
+    // Throws away any search in progress without a bestmove
+    fn abort_search(&mut self) {
+        if let Some(search) = self.searching.take() {
+            search.limits.stop();
+            self.generation += 1;
+        }
+    }
+
+    // The move played from a finished search, with the expected reply to
+    // ponder on
+    fn report(&mut self, analysis: AnalysisResult) {
+        self.searching = None;
+        let choice = self
+            .options
+            .skill
+            .choose(&analysis.move_scores, &mut self.rng);
+        let engine_black = self.game_state.engine_black;
+        match choice {
+            Some(choice) => match choice.pv.get(1) {
+                Some(reply) => println!(
+                    "bestmove {} ponder {}",
+                    choice.mov.coordinate(engine_black),
+                    reply.coordinate(engine_black)
+                ),
+                None => println!("bestmove {}", choice.mov.coordinate(engine_black)),
+            },
+            // Mated or stalemated
+            None => println!("bestmove 0000"),
+        }
+    }
+
+    fn search_done(&mut self, generation: u64, analysis: AnalysisResult) {
+        if generation != self.generation {
+            return;
+        }
+        match &mut self.searching {
+            Some(search) if search.hold => search.finished = Some(analysis),
+            Some(_) => self.report(analysis),
+            None => {}
+        }
+    }
+
+    // The opponent played the expected move, so the ponder search now runs on
+    // the time it would have had
+    fn ponder_hit(&mut self) {
+        let Some(search) = &mut self.searching else {
+            return;
+        };
+        search.hold = false;
+        if let Some(analysis) = search.finished.take() {
+            self.report(analysis);
+        } else if let Some(time) = search.ponder_time {
+            let limits = search.limits.clone();
+            thread::spawn(move || {
+                thread::sleep(time);
+                limits.stop();
+            });
+        }
+    }
+
+    fn stop(&mut self) {
+        let Some(search) = &mut self.searching else {
+            return;
+        };
+        search.hold = false;
+        search.limits.stop();
+        if let Some(analysis) = search.finished.take() {
+            self.report(analysis);
+        }
+    }
+
+    // "name <name> value <value>"
+    fn set_option(&mut self, args: &[&str]) {
+        let text = args.join(" ");
+        let Some((name, value)) = text
+            .strip_prefix("name ")
+            .and_then(|text| text.split_once(" value "))
+        else {
+            println!("info string Bad option: {}", text);
+            return;
+        };
+        let valid = match name {
+            "MultiPV" => match value.parse::<usize>() {
+                Ok(n) if n > 0 => {
+                    self.options.multi_pv = n;
+                    true
+                }
+                _ => false,
+            },
+            "Skill Level" => match value.parse().ok().and_then(Skill::new) {
+                Some(skill) => {
+                    self.skill = skill;
+                    true
+                }
+                None => false,
+            },
+            "UCI_LimitStrength" => match value.parse() {
+                Ok(limit) => {
+                    self.limit_strength = limit;
+                    true
+                }
+                Err(_) => false,
+            },
+            "UCI_Elo" => match value.parse() {
+                Ok(elo) => {
+                    self.elo = elo;
+                    true
+                }
+                Err(_) => false,
+            },
+            // Takes effect at once, and again with every ucinewgame
+            "Seed" => match value.parse() {
+                Ok(seed) => {
+                    self.options.seed = Some(seed);
+                    table().clear();
+                    self.rng = GameRng::new(self.options.seed);
+                    true
+                }
+                Err(_) if value == "random" => {
+                    self.options.seed = None;
+                    true
+                }
+                Err(_) => false,
+            },
+            _ => {
+                println!("info string Unknown option: {}", name);
+                return;
+            }
+        };
+        if valid {
+            self.update_skill();
+        } else {
+            println!("info string Bad value for {}: {}", name, value);
+        }
+    }
+
+    // Returns false on quit
+    fn command(&mut self, line: &str) -> bool {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let Some((&command, args)) = tokens.split_first() else {
+            return true;
+        };
+        match command {
+            "uci" => {
+                println!("id name rust-chess-engine");
+                println!("option name MultiPV type spin default 1 min 1 max 64");
+                println!(
+                    "option name Skill Level type spin default {} min 0 max {}",
+                    MAX_SKILL, MAX_SKILL
+                );
+                println!("option name UCI_LimitStrength type check default false");
+                println!(
+                    "option name UCI_Elo type spin default {} min {} max {}",
+                    MAX_ELO, MIN_ELO, MAX_ELO
+                );
+                println!("option name Seed type string default random");
+                println!("uciok");
+            }
+            "isready" => println!("readyok"),
+            "debug" | "register" => {}
+            "setoption" => self.set_option(args),
+            "ucinewgame" => {
+                self.abort_search();
+                self.game_state = parse_layout();
+                // Searches replay from the seed only from an empty table
+                table().clear();
+                self.rng = GameRng::new(self.options.seed);
+                println!("info string Game seed {}", self.rng.seed);
+            }
+            "position" => {
+                self.abort_search();
+                self.position(args);
+            }
+            "go" => self.go(args),
+            "ponderhit" => self.ponder_hit(),
+            "stop" => self.stop(),
+            "quit" => {
+                self.abort_search();
+                return false;
+            }
+            _ => println!("info string Unknown command: {}", command),
+        }
+        true
+    }
+}
+
+fn read_lines(events: Sender<Event>) {
+    for line in io::stdin().lock().lines() {
+        match line {
+            Ok(line) => {
+                if events.send(Event::Line(line)).is_err() {
+                    return;
+                }
+            }
+            Err(_) => break,
+        }
+    }
+    let _ = events.send(Event::Line(String::from("quit")));
+}
+
+// Plays over the Universal Chess Interface, for GUIs such as Arena or Cute Chess
+pub fn uci(options: &SearchOptions) {
+    let (sender, receiver): (Sender<Event>, Receiver<Event>) = mpsc::channel();
+    let input_sender = sender.clone();
+    thread::spawn(move || read_lines(input_sender));
+
+    let mut options = *options;
+    options.progress = Progress::Uci;
+    let mut state = Uci::new(&options, sender);
+    while let Ok(event) = receiver.recv() {
+        match event {
+            Event::Line(line) => {
+                if !state.command(line.trim()) {
+                    break;
+                }
+            }
+            Event::Done(generation, analysis) => state.search_done(generation, analysis),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fen::to_fen,
+        simulation::{analyse, pv_repr, uci_info},
+    };
+
+    fn uci() -> (Uci, Receiver<Event>) {
+        let (sender, receiver) = mpsc::channel();
+        let options = SearchOptions {
+            threads: 1,
+            progress: Progress::Quiet,
+            seed: Some(1),
+            ..SearchOptions::default()
+        };
+        (Uci::new(&options, sender), receiver)
+    }
+
+    fn wait_for_search(state: &mut Uci, receiver: &Receiver<Event>) {
+        while let Ok(event) = receiver.recv() {
+            if let Event::Done(generation, analysis) = event {
+                state.search_done(generation, analysis);
+                return;
+            }
+        }
+    }
+
+    #[test]
+    fn go_limits() {
+        let go = parse_go(&["depth", "5"], false).unwrap();
+        assert_eq!(go.limits.depth, 5);
+        assert!(go.limits.time.is_none() && !go.hold);
+
+        // Black's clock, with ten moves to the next control
+        let args = [
+            "wtime",
+            "60000",
+            "btime",
+            "30000",
+            "winc",
+            "1000",
+            "movestogo",
+            "10",
+        ];
+        let go = parse_go(&args, true).unwrap();
+        let (soft, hard) = allocate(Duration::from_secs(30), Some(10), Increment::None);
+        assert_eq!(
+            (go.limits.soft_time, go.limits.time),
+            (Some(soft), Some(hard))
+        );
+
+        let go = parse_go(&["ponder", "wtime", "60000", "btime", "60000"], false).unwrap();
+        assert!(go.hold && go.limits.time.is_none() && go.limits.soft_time.is_none());
+        assert!(go.ponder_time.is_some());
+        let go = parse_go(&["infinite"], false).unwrap();
+        assert!(go.hold && go.ponder_time.is_none());
+        assert_eq!(go.limits.depth, MAX_DEPTH);
+
+        assert!(parse_go(&["wtime"], false).is_none());
+        assert!(parse_go(&["depth", "x"], false).is_none());
+    }
+
+    #[test]
+    fn positions() {
+        let (mut state, _) = uci();
+        state.command("position startpos moves e2e4 e7e5 g1f3");
+        assert_eq!(
+            to_fen(&state.game_state),
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2"
+        );
+        state.command("position fen 4k3/8/8/8/8/8/8/4K2R w K - 0 1 moves e1g1");
+        assert_eq!(to_fen(&state.game_state), "4k3/8/8/8/8/8/8/5RK1 b - - 1 1");
+        // Moves stop at the first illegal one
+        state.command("position startpos moves e2e4 e2e4");
+        assert!(state.game_state.engine_black);
+    }
+
+    #[test]
+    fn options() {
+        let (mut state, _) = uci();
+        state.command("setoption name MultiPV value 3");
+        assert_eq!(state.options.multi_pv, 3);
+        state.command("setoption name MultiPV value 0");
+        assert_eq!(state.options.multi_pv, 3);
+
+        state.command("setoption name Skill Level value 5");
+        assert_eq!(state.options.skill.level(), 5);
+        state.command("setoption name UCI_Elo value 800");
+        assert_eq!(state.options.skill.level(), 5);
+        state.command("setoption name UCI_LimitStrength value true");
+        assert_eq!(state.options.skill.level(), 0);
+        state.command("setoption name UCI_LimitStrength value false");
+        assert_eq!(state.options.skill.level(), 5);
+
+        // A numeric seed would clear the table shared with other tests
+        state.command("setoption name Seed value random");
+        assert_eq!(state.options.seed, None);
+    }
+
+    #[test]
+    fn multipv_info() {
+        let options = SearchOptions {
+            threads: 1,
+            progress: Progress::Quiet,
+            multi_pv: 3,
+            ..SearchOptions::default()
+        };
+        let analysis = analyse(&parse_layout(), 2, &options);
+        let lines = uci_info(2, &analysis.move_scores, 3, 100, 5, false);
+        assert_eq!(lines.len(), 3);
+        for (i, line) in lines.iter().enumerate() {
+            let start = format!("info depth 2 multipv {} score cp ", i + 1);
+            assert!(line.starts_with(&start), "{}", line);
+            assert!(line.contains(" nodes 100 time 5 pv "), "{}", line);
+        }
+        let pv = pv_repr(&analysis.move_scores[0].pv, false);
+        assert!(lines[0].ends_with(&format!(" pv {}", pv)));
+    }
+
+    #[test]
+    fn ponder_waits_for_ponderhit() {
+        let (mut state, receiver) = uci();
+        state.command("go ponder depth 1");
+        wait_for_search(&mut state, &receiver);
+        // Held back until the GUI says the expected move was played
+        assert!(state.searching.as_ref().unwrap().finished.is_some());
+        state.command("ponderhit");
+        assert!(state.searching.is_none());
+
+        state.command("go depth 1");
+        wait_for_search(&mut state, &receiver);
+        assert!(state.searching.is_none());
+    }
+
+    #[test]
+    fn mated_has_no_move() {
+        let (mut state, receiver) = uci();
+        state.command("position fen 7k/6Q1/6K1/8/8/8/8/8 b - - 0 1");
+        state.command("go depth 2");
+        wait_for_search(&mut state, &receiver);
+        assert!(state.searching.is_none());
+    }
+}
@@ -1,11 +1,14 @@
-use std::io::{self, BufRead, BufReader, Write};
+use std::{
+    io::{self, Write},
+    time::Duration,
+};
 
-use crate::settings::{BOARD_WIDTH, ENGINE_BLACK};
+use crate::settings::BOARD_WIDTH;
 
 pub const LETTERS: [char; 8] = ['a', 'b', 'c', 'd', 'e', 'f', 'g', 'h'];
 
 // Also represents vectors
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Eq)]
 pub struct Vect {
     pub x: i8,
     pub y: i8,
@@ -35,9 +38,9 @@ pub struct CastlingPossibilities {
     pub kingside: bool,
 }
 
-pub fn pos_notation(pos: &Vect) -> String {
+pub fn pos_notation(pos: &Vect, engine_black: bool) -> String {
     let (x, mut y) = (pos.x, pos.y);
-    if ENGINE_BLACK {
+    if engine_black {
         // x = BOARD_WIDTH - x - 1;
         y = BOARD_WIDTH - y - 1;
     }
@@ -51,9 +54,51 @@ pub fn pos_notation(pos: &Vect) -> String {
     format!("{letter}{y}")
 }
 
-pub fn input(prompt: &str) -> String {
+pub fn parse_pos_notation(text: &str, engine_black: bool) -> Option<Vect> {
+    let chars: Vec<char> = text.chars().collect();
+    if chars.len() != 2 {
+        return None;
+    }
+    let x = LETTERS.iter().position(|&l| l == chars[0])? as i8;
+    let rank = chars[1].to_digit(10)? as i8;
+    if !(1..=BOARD_WIDTH).contains(&rank) {
+        return None;
+    }
+    let y = if engine_black {
+        BOARD_WIDTH - rank
+    } else {
+        rank - 1
+    };
+    Some(Vect { x, y })
+}
+
+// None once input has ended
+pub fn read_line(prompt: &str) -> Option<String> {
     print!("{}", prompt);
     io::stdout().flush().expect("Couldn't flush buffer");
-    let result = BufReader::new(io::stdin()).lines().next().unwrap();
-    result.unwrap()
+    let mut line = String::new();
+    match io::stdin().read_line(&mut line) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(line.trim_end_matches(['\n', '\r']).to_string()),
+    }
+}
+
+pub fn input(prompt: &str) -> String {
+    read_line(prompt).unwrap()
+}
+
+// "10s", "500ms", "2m", or plain seconds
+pub fn parse_duration(text: &str) -> Option<Duration> {
+    let (number, unit) = match text.find(|c: char| c.is_ascii_alphabetic()) {
+        Some(i) => text.split_at(i),
+        None => (text, "s"),
+    };
+    let number: f64 = number.parse().ok()?;
+    let seconds = match unit {
+        "ms" => number / 1000.0,
+        "s" => number,
+        "m" => number * 60.0,
+        _ => return None,
+    };
+    Duration::try_from_secs_f64(seconds).ok()
 }
@@ -0,0 +1,359 @@
+use std::{
+    io::{self, BufRead},
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    book::book_move,
+    clock::{allocate, Increment},
+    fen::parse_fen,
+    gamestate::{parse_layout, GameState},
+    moves::{parse_coordinate, Move},
+    rng::GameRng,
+    simulation::{
+        analyse_with_limits, AnalysisResult, Progress, SearchLimits, SearchOptions, MAX_DEPTH,
+    },
+    skill::Skill,
+    transposition::table,
+};
+
+enum Event {
+    Line(String),
+    // Search result tagged with the search it belongs to
+    Done(u64, AnalysisResult),
+}
+
+struct TimeControl {
+    // Moves per session, 0 for the whole game
+    moves_per_session: u32,
+    increment: Duration,
+    // Fixed time per move from "st"
+    per_move: Option<Duration>,
+    // Engine clock as last reported by "time"
+    remaining: Option<Duration>,
+}
+
+// The position is kept with the side to move as the engine (enemy = false),
+// flipping it after every move, so engine_black is true when black is to move
+struct Xboard {
+    game_state: GameState,
+    history: Vec<GameState>,
+    // Colour the engine plays, None in force mode
+    engine_black: Option<bool>,
+    options: SearchOptions,
+    max_depth: u8,
+    time_control: TimeControl,
+    rng: GameRng,
+    // Search in progress, its limits and generation
+    searching: Option<SearchLimits>,
+    generation: u64,
+    events: Sender<Event>,
+}
+
+impl Xboard {
+    fn new(options: &SearchOptions, events: Sender<Event>) -> Self {
+        Self {
+            game_state: parse_layout(),
+            history: Vec::new(),
+            engine_black: Some(true),
+            options: *options,
+            max_depth: MAX_DEPTH,
+            time_control: TimeControl {
+                moves_per_session: 0,
+                increment: Duration::ZERO,
+                per_move: None,
+                remaining: None,
+            },
+            rng: GameRng::new(options.seed),
+            searching: None,
+            generation: 0,
+            events,
+        }
+    }
+
+    fn make_move(&mut self, mov: &Move) {
+        let next_state = self.game_state.perform_move(mov).flipped();
+        self.history
+            .push(std::mem::replace(&mut self.game_state, next_state));
+        if let Some(result) = self.game_state.game_result() {
+            println!("{}", result);
+        }
+    }
+
+    // Soft and hard limits, a fixed time per move having no soft one
+    fn move_times(&self) -> (Option<Duration>, Option<Duration>) {
+        let control = &self.time_control;
+        if control.per_move.is_some() {
+            return (None, control.per_move);
+        }
+        let Some(remaining) = control.remaining else {
+            return (None, None);
+        };
+        let moves_to_go = match control.moves_per_session {
+            0 => None,
+            n => {
+                let played = (self.history.len() / 2) as u32;
+                Some(n - played % n)
+            }
+        };
+        let increment = match control.increment {
+            Duration::ZERO => Increment::None,
+            increment => Increment::Fischer(increment),
+        };
+        let (soft, hard) = allocate(remaining, moves_to_go, increment);
+        (Some(soft), Some(hard))
+    }
+
+    fn start_search(&mut self) {
+        if self.game_state.game_result().is_some() {
+            return;
+        }
+        if let Some(mov) = book_move(&self.game_state, &mut self.rng) {
+            println!("move {}", mov.coordinate(self.game_state.engine_black));
+            self.make_move(&mov);
+            return;
+        }
+        let skill = self.options.skill;
+        let mut limits = skill.limit(&SearchLimits::depth(self.max_depth));
+        (limits.soft_time, limits.time) = self.move_times();
+        self.generation += 1;
+
+        let game_state = self.game_state.clone();
+        let thread_limits = limits.clone();
+        let options = skill.options(&self.options);
+        let generation = self.generation;
+        let events = self.events.clone();
+        thread::spawn(move || {
+            let analysis = analyse_with_limits(&game_state, &thread_limits, &options);
+            let _ = events.send(Event::Done(generation, analysis));
+        });
+        self.searching = Some(limits);
+    }
+
+    // Throws away any search in progress
+    fn abort_search(&mut self) {
+        if let Some(limits) = self.searching.take() {
+            limits.stop();
+            self.generation += 1;
+        }
+    }
+
+    fn engine_to_move(&self) -> bool {
+        self.engine_black == Some(self.game_state.engine_black)
+    }
+
+    fn search_done(&mut self, generation: u64, analysis: AnalysisResult) {
+        if generation != self.generation || self.searching.take().is_none() {
+            return;
+        }
+        let choice = self
+            .options
+            .skill
+            .choose(&analysis.move_scores, &mut self.rng);
+        let Some(mov) = choice.map(|choice| &choice.mov) else {
+            return;
+        };
+        println!("move {}", mov.coordinate(self.game_state.engine_black));
+        self.make_move(mov);
+    }
+
+    fn user_move(&mut self, text: &str) {
+        self.abort_search();
+        let mov = parse_coordinate(text, &self.game_state, false).filter(|mov| {
+            self.game_state
+                .is_legal(mov, &self.game_state.perform_move(mov))
+        });
+        match mov {
+            Some(mov) => {
+                self.make_move(&mov);
+                if self.engine_to_move() {
+                    self.start_search();
+                }
+            }
+            None => println!("Illegal move: {}", text),
+        }
+    }
+
+    fn undo(&mut self, plies: usize) {
+        self.abort_search();
+        for _ in 0..plies {
+            if let Some(previous) = self.history.pop() {
+                self.game_state = previous;
+            }
+        }
+    }
+
+    fn level(&mut self, args: &[&str]) -> Option<()> {
+        let [moves, base, increment] = args[..] else {
+            return None;
+        };
+        // Base is minutes, or minutes:seconds
+        let base = match base.split_once(':') {
+            Some((minutes, seconds)) => {
+                minutes.parse::<u64>().ok()? * 60 + seconds.parse::<u64>().ok()?
+            }
+            None => base.parse::<u64>().ok()? * 60,
+        };
+        self.time_control = TimeControl {
+            moves_per_session: moves.parse().ok()?,
+            increment: Duration::from_secs_f64(increment.parse().ok()?),
+            per_move: None,
+            remaining: Some(Duration::from_secs(base)),
+        };
+        Some(())
+    }
+
+    // Returns false on quit
+    fn command(&mut self, line: &str) -> bool {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let Some((&command, args)) = tokens.split_first() else {
+            return true;
+        };
+        match command {
+            "xboard" | "accepted" | "rejected" | "random" | "computer" | "name" | "otim"
+            | "hard" | "easy" | "draw" | "ics" => {}
+            "protover" => println!(
+                "feature myname=\"rust-chess-engine\" usermove=1 setboard=1 ping=1 \
+                 sigint=0 sigterm=0 colors=0 analyze=0 \
+                 option=\"MultiPV -spin 1 1 64\" \
+                 option=\"Skill Level -spin 20 0 20\" \
+                 option=\"Seed -string random\" done=1"
+            ),
+            "ping" => println!("pong {}", args.join(" ")),
+            "new" => {
+                self.abort_search();
+                self.game_state = parse_layout();
+                self.history.clear();
+                self.engine_black = Some(true);
+                self.max_depth = MAX_DEPTH;
+                self.time_control.per_move = None;
+                // Searches replay from the seed only from an empty table
+                table().clear();
+                self.rng = GameRng::new(self.options.seed);
+                println!("# Game seed {}", self.rng.seed);
+            }
+            "force" | "result" => {
+                self.abort_search();
+                self.engine_black = None;
+            }
+            "go" => {
+                self.abort_search();
+                self.engine_black = Some(self.game_state.engine_black);
+                self.start_search();
+            }
+            // Move now, playing the best move found so far
+            "?" => {
+                if let Some(limits) = &self.searching {
+                    limits.stop();
+                }
+            }
+            "usermove" => match args.first() {
+                Some(text) => self.user_move(text),
+                None => println!("Error (missing move): usermove"),
+            },
+            "setboard" => {
+                self.abort_search();
+                match parse_fen(&args.join(" ")) {
+                    Ok(game_state) => {
+                        self.game_state = game_state;
+                        self.history.clear();
+                    }
+                    Err(e) => println!("tellusererror Illegal position: {}", e),
+                }
+            }
+            "undo" => self.undo(1),
+            "remove" => self.undo(2),
+            "level" => {
+                if self.level(args).is_none() {
+                    println!("Error (bad time control): {}", line);
+                }
+            }
+            "st" => match args.first().and_then(|s| s.parse::<f64>().ok()) {
+                Some(seconds) => {
+                    self.time_control.per_move = Some(Duration::from_secs_f64(seconds))
+                }
+                None => println!("Error (bad time): {}", line),
+            },
+            "sd" => match args.first().and_then(|d| d.parse::<u8>().ok()) {
+                Some(depth) => self.max_depth = depth.clamp(1, MAX_DEPTH),
+                None => println!("Error (bad depth): {}", line),
+            },
+            // Centiseconds left on the engine's clock
+            "time" => {
+                if let Some(centiseconds) = args.first().and_then(|t| t.parse::<u64>().ok()) {
+                    self.time_control.remaining = Some(Duration::from_millis(centiseconds * 10));
+                }
+            }
+            "post" => self.options.progress = Progress::Xboard,
+            "nopost" => self.options.progress = Progress::Quiet,
+            "option" => match args.join(" ").split_once('=') {
+                Some(("MultiPV", n)) => match n.parse::<usize>() {
+                    Ok(n) if n > 0 => self.options.multi_pv = n,
+                    _ => println!("Error (bad value): {}", line),
+                },
+                Some(("Skill Level", level)) => match level.parse().ok().and_then(Skill::new) {
+                    Some(skill) => self.options.skill = skill,
+                    None => println!("Error (bad value): {}", line),
+                },
+                // Takes effect at once, as options are sent after new
+                Some(("Seed", seed)) => match seed.parse() {
+                    Ok(seed) => {
+                        self.options.seed = Some(seed);
+                        table().clear();
+                        self.rng = GameRng::new(self.options.seed);
+                    }
+                    Err(_) if seed == "random" => self.options.seed = None,
+                    Err(_) => println!("Error (bad value): {}", line),
+                },
+                _ => println!("Error (unknown option): {}", line),
+            },
+            "quit" => {
+                self.abort_search();
+                return false;
+            }
+            // Protocol 1 engines get moves without the usermove prefix
+            _ if parse_coordinate(command, &self.game_state, false).is_some() => {
+                self.user_move(command)
+            }
+            _ => println!("Error (unknown command): {}", command),
+        }
+        true
+    }
+}
+
+fn read_lines(events: Sender<Event>) {
+    for line in io::stdin().lock().lines() {
+        match line {
+            Ok(line) => {
+                if events.send(Event::Line(line)).is_err() {
+                    return;
+                }
+            }
+            Err(_) => break,
+        }
+    }
+    let _ = events.send(Event::Line(String::from("quit")));
+}
+
+// Plays over the Chess Engine Communication Protocol used by XBoard/WinBoard
+pub fn xboard(options: &SearchOptions) {
+    let (sender, receiver): (Sender<Event>, Receiver<Event>) = mpsc::channel();
+    let input_sender = sender.clone();
+    thread::spawn(move || read_lines(input_sender));
+
+    let mut options = *options;
+    options.progress = Progress::Quiet;
+    let mut state = Xboard::new(&options, sender);
+    while let Ok(event) = receiver.recv() {
+        match event {
+            Event::Line(line) => {
+                if !state.command(line.trim()) {
+                    break;
+                }
+            }
+            Event::Done(generation, analysis) => state.search_done(generation, analysis),
+        }
+    }
+}
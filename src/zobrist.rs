@@ -0,0 +1,76 @@
+use crate::{
+    gamestate::GameState,
+    pieces::{Piece, PieceClass},
+    settings::BOARD_WIDTH,
+};
+
+const SQUARES: usize = (BOARD_WIDTH as usize) * (BOARD_WIDTH as usize);
+const PIECE_KEYS: usize = 12 * SQUARES;
+// Pieces, side to move, four castling rights and an en passant file
+const KEY_COUNT: usize = PIECE_KEYS + 1 + 4 + BOARD_WIDTH as usize;
+
+// Fixed seed, so hashes are the same between runs
+const KEYS: [u64; KEY_COUNT] = generate_keys(0x2545_f491_4f6c_dd1d);
+
+// SplitMix64, which is good enough for hash keys and usable in a const fn
+const fn generate_keys(seed: u64) -> [u64; KEY_COUNT] {
+    let mut keys = [0; KEY_COUNT];
+    let mut state = seed;
+    let mut i = 0;
+    while i < KEY_COUNT {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        keys[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    keys
+}
+
+fn piece_index(piece: &Piece) -> usize {
+    let class = match piece.class {
+        PieceClass::Pawn => 0,
+        PieceClass::Knight => 1,
+        PieceClass::Bishop => 2,
+        PieceClass::Rook => 3,
+        PieceClass::Queen => 4,
+        PieceClass::King => 5,
+    };
+    if piece.enemy {
+        class + 6
+    } else {
+        class
+    }
+}
+
+// Position hash for the given side to move. Boards are stored relative to the
+// engine, so the same position with colours swapped hashes differently
+pub fn hash(game_state: &GameState, enemy: bool) -> u64 {
+    let mut key = 0;
+    for y in 0..BOARD_WIDTH as usize {
+        for x in 0..BOARD_WIDTH as usize {
+            if let Some(piece) = &game_state.board[y][x] {
+                key ^= KEYS[piece_index(piece) * SQUARES + y * BOARD_WIDTH as usize + x];
+            }
+        }
+    }
+    if enemy {
+        key ^= KEYS[PIECE_KEYS];
+    }
+    let rights = [
+        game_state.engine_castling.queenside,
+        game_state.engine_castling.kingside,
+        game_state.opponent_castling.queenside,
+        game_state.opponent_castling.kingside,
+    ];
+    for (i, right) in rights.into_iter().enumerate() {
+        if right {
+            key ^= KEYS[PIECE_KEYS + 1 + i];
+        }
+    }
+    if let Some(midpoint) = game_state.en_passant_midpoint {
+        key ^= KEYS[PIECE_KEYS + 5 + midpoint.x as usize];
+    }
+    key
+}